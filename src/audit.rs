@@ -0,0 +1,367 @@
+//! # Audit Log
+//!
+//! An append-only trail of every tool call and resource read, enabled by
+//! an `[audit]` section in the configuration file. Each request becomes
+//! one JSON object on its own line (JSON Lines) recording when it
+//! happened, who made it, what it targeted with which arguments, how it
+//! ended and how long it took.
+//!
+//! Argument values whose names match a redaction pattern are replaced
+//! with [`REDACTED`] before they are written. The file is rotated once it
+//! reaches the configured size: `audit.jsonl` becomes `audit.jsonl.1`,
+//! `audit.jsonl.1` becomes `audit.jsonl.2`, and so on, and the oldest
+//! file beyond the configured count is deleted.
+//!
+//! Records are written by a dedicated thread, so a slow disk or a
+//! rotation never holds up the Tokio workers handling requests. They wait
+//! for it in a queue of bounded size; when the queue is full the record
+//! is dropped and counted, and once there is room again a
+//! `{"timestamp": ..., "dropped": n}` record is written ahead of the next
+//! one, so that the gap shows in the file itself. The file is opened on the first
+//! record. If it cannot be opened or written, the error is logged and the
+//! next record tries again; the request itself is never affected.
+
+use crate::config::AuditConfig;
+use crate::error::McpError;
+use crate::session::Session;
+use crate::wildcard::WildcardPattern;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// Argument names redacted when the configuration does not list its own
+pub const DEFAULT_REDACT: &[&str] = &["*password*", "*secret*", "*token*", "*api_key*", "authorization"];
+
+/// Value written in place of a redacted argument
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Request methods that are recorded
+const AUDITED_METHODS: &[&str] = &["tools/call", "resources/read"];
+
+/// Most records waiting for the writer thread; later ones are dropped and counted
+const MAX_QUEUED_RECORDS: usize = 4096;
+
+/// How an audited request ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AuditOutcome {
+    /// The request succeeded
+    #[serde(rename = "success")]
+    Success,
+    /// The tool ran but reported a failure with `isError: true`
+    #[serde(rename = "isError")]
+    IsError,
+    /// The request was answered with a JSON-RPC error
+    #[serde(rename = "error")]
+    Error,
+}
+
+/// One line of the audit log
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// When the request finished, in RFC 3339 format
+    pub timestamp: String,
+    /// Session the request arrived on
+    pub session_id: u64,
+    /// Who the client authenticated as, if anyone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// IP address of the client, for network transports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    /// JSON-RPC method of the request
+    pub method: String,
+    /// Name of the tool called or URI of the resource read
+    pub target: String,
+    /// Tool arguments, with sensitive values redacted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+    /// How the request ended
+    pub outcome: AuditOutcome,
+    /// JSON-RPC error code, when the outcome is an error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i64>,
+    /// Time the request took, in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Line written in place of records dropped because the queue was full
+#[derive(Debug, Serialize)]
+struct DroppedRecord {
+    /// When the gap was written, in RFC 3339 format
+    timestamp: String,
+    /// How many records are missing
+    dropped: u64,
+}
+
+/// Writer of the audit trail
+pub struct AuditLog {
+    redact: Vec<WildcardPattern>,
+    /// Queue of the writer thread, which stops once this is dropped
+    commands: SyncSender<Command>,
+    /// Records dropped since the last `dropped` record was queued
+    dropped: AtomicU64,
+}
+
+enum Command {
+    /// A serialized record, ending in a newline
+    Write(Vec<u8>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// State of the writer thread
+struct AuditWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    /// The open file, `None` until the first record or after a failure
+    file: Option<AuditFile>,
+}
+
+struct AuditFile {
+    file: File,
+    size: u64,
+}
+
+impl AuditLog {
+    /// Creates the audit log described by an `[audit]` section
+    ///
+    /// Starts the thread that writes the records.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Path, rotation and redaction settings
+    pub fn new(config: &AuditConfig) -> Self {
+        let writer = AuditWriter {
+            path: config.path.clone(),
+            max_bytes: config.max_bytes,
+            max_files: config.max_files,
+            file: None,
+        };
+        let (commands, receiver) = mpsc::sync_channel(MAX_QUEUED_RECORDS);
+        if let Err(e) = std::thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || writer.run(receiver))
+        {
+            tracing::error!("无法启动审计日志线程: {}", e);
+        }
+        AuditLog {
+            redact: config
+                .redact
+                .iter()
+                .map(|pattern| WildcardPattern::new(&pattern.to_lowercase()))
+                .collect(),
+            commands,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns whether requests with the given method are recorded
+    pub fn covers(method: &str) -> bool {
+        AUDITED_METHODS.contains(&method)
+    }
+
+    /// Records a finished request
+    ///
+    /// The record is queued for the writer thread without waiting for it.
+    /// If the queue is full it is dropped and counted instead. Failures to
+    /// write are logged rather than returned, so that auditing never
+    /// changes the outcome of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session the request arrived on
+    /// * `method` - JSON-RPC method of the request
+    /// * `params` - Parameters of the request
+    /// * `result` - What the handler returned
+    /// * `duration` - Time the request took
+    pub fn record(
+        &self,
+        session: &Session,
+        method: &str,
+        params: &Value,
+        result: &Result<Value, McpError>,
+        duration: Duration,
+    ) {
+        let (target, arguments) = match method {
+            "tools/call" => (&params["name"], params.get("arguments").map(|arguments| self.redact(arguments))),
+            _ => (&params["uri"], None),
+        };
+        let (outcome, error_code) = match result {
+            Ok(value) if value["isError"] == true => (AuditOutcome::IsError, None),
+            Ok(_) => (AuditOutcome::Success, None),
+            Err(error) => (AuditOutcome::Error, Some(error.code())),
+        };
+        let record = AuditRecord {
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            session_id: session.id(),
+            identity: session.identity().map(|identity| identity.name.clone()),
+            peer: session.peer().map(|peer| peer.to_string()),
+            method: method.to_string(),
+            target: target.as_str().unwrap_or_default().to_string(),
+            arguments,
+            outcome,
+            error_code,
+            duration_ms: duration.as_millis() as u64,
+        };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("无法序列化审计记录: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        self.queue_dropped(false);
+        match self.commands.try_send(Command::Write(line)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::error!("审计日志队列已满，丢弃了一条记录: {} {}", method, record.target);
+            }
+            Err(TrySendError::Disconnected(_)) => tracing::error!("审计日志线程已停止，丢弃了一条记录"),
+        }
+    }
+
+    /// Waits until every record queued so far has been written
+    ///
+    /// Records dropped since the last `dropped` record are reported first.
+    /// Blocks the calling thread, so async code should call it through
+    /// `spawn_blocking`.
+    pub fn flush(&self) {
+        self.queue_dropped(true);
+        let (done, wait) = mpsc::channel();
+        if self.commands.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Queues a `dropped` record if records have been dropped since the last one
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for room in the queue instead of keeping
+    ///   the count for the next record
+    fn queue_dropped(&self, wait: bool) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped == 0 {
+            return;
+        }
+        let record = DroppedRecord {
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            dropped,
+        };
+        let mut line = serde_json::to_vec(&record).unwrap_or_default();
+        line.push(b'\n');
+        let sent = if wait {
+            self.commands.send(Command::Write(line)).is_ok()
+        } else {
+            self.commands.try_send(Command::Write(line)).is_ok()
+        };
+        if !sent {
+            self.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+
+    /// Copies a value, replacing the values of keys that match a redaction pattern
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Tool arguments or any nested part of them
+    pub fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    let key_lower = key.to_lowercase();
+                    let value = if self.redact.iter().any(|pattern| pattern.matches(&key_lower)) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        self.redact(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            Value::Array(items) => items.iter().map(|item| self.redact(item)).collect(),
+            other => other.clone(),
+        }
+    }
+}
+
+impl AuditWriter {
+    /// Writes queued records until the [`AuditLog`] is dropped
+    fn run(mut self, commands: Receiver<Command>) {
+        for command in commands {
+            match command {
+                Command::Write(line) => {
+                    if let Err(e) = self.write(&line) {
+                        tracing::error!("写入审计日志失败: {:#}", e);
+                    }
+                }
+                Command::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    /// Appends one line, rotating the file first if it would grow too large
+    fn write(&mut self, line: &[u8]) -> Result<()> {
+        // Left empty on failure, so the next record reopens the file
+        let mut current = match self.file.take() {
+            Some(current) => current,
+            None => open_append(&self.path)?,
+        };
+        if self.max_bytes > 0 && current.size > 0 && current.size + line.len() as u64 > self.max_bytes {
+            drop(current);
+            self.rotate()?;
+            current = open_append(&self.path)?;
+        }
+        current.file.write_all(line).context("无法写入审计日志")?;
+        current.size += line.len() as u64;
+        self.file = Some(current);
+        Ok(())
+    }
+
+    /// Shifts every file up by one number, dropping the oldest
+    fn rotate(&self) -> Result<()> {
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path).context("无法删除审计日志")?;
+            return Ok(());
+        }
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, n + 1)).context("无法轮转审计日志")?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1)).context("无法轮转审计日志")?;
+        Ok(())
+    }
+}
+
+/// Gets the path of the `n`th rotated file, such as `audit.jsonl.1`
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+/// Opens a file for appending and gets its current size
+fn open_append(path: &Path) -> Result<AuditFile> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("无法打开审计日志 {}", path.display()))?;
+    let size = file.metadata()?.len();
+    Ok(AuditFile { file, size })
+}
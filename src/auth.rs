@@ -0,0 +1,158 @@
+//! # Authentication
+//!
+//! Network listeners can require clients to present a bearer token before
+//! they may use the server:
+//!
+//! - WebSocket clients send it in the `Authorization: Bearer <token>`
+//!   header of the opening handshake, and are refused with HTTP 401
+//!   otherwise;
+//! - raw TCP clients send an [`AUTHENTICATE_METHOD`] request as their
+//!   first message, and are disconnected if it fails.
+//!
+//! The WebSocket upgrade request is the only HTTP request the server
+//! serves MCP over: there is no plain HTTP transport, so the
+//! `Authorization` header is read nowhere else.
+//!
+//! Local transports (Unix sockets, stdio and in-process connections) are
+//! protected by the operating system and are not authenticated.
+//!
+//! The identity a token maps to is stored on the session, where tools can
+//! read it through [`RequestContext::identity`](crate::RequestContext::identity).
+
+use crate::config::AuthConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Method of the request a TCP client must send first when authentication is on
+pub const AUTHENTICATE_METHOD: &str = "authenticate";
+
+/// Default environment variable holding an extra token
+pub const DEFAULT_TOKEN_ENV: &str = "MCP_AUTH_TOKEN";
+
+/// Identity given to the token read from the environment
+pub const ENV_TOKEN_IDENTITY: &str = "env";
+
+/// Who a client authenticated as
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Identity {
+    /// Name of the identity, such as a user or service account
+    pub name: String,
+}
+
+impl Identity {
+    /// Creates an identity with the given name
+    pub fn new(name: impl Into<String>) -> Self {
+        Identity { name: name.into() }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Params of the [`AUTHENTICATE_METHOD`] request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthenticateRequest {
+    /// The bearer token
+    pub token: String,
+}
+
+/// Response for the [`AUTHENTICATE_METHOD`] request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthenticateResult {
+    /// Who the client is now authenticated as
+    pub identity: Identity,
+}
+
+/// Checks the credentials presented by clients
+///
+/// Implement this to plug in a different source of truth, such as a
+/// token introspection service, and install it with
+/// `McpServer::with_authenticator`.
+pub trait Authenticator: Send + Sync + 'static {
+    /// Resolves a bearer token to an identity
+    ///
+    /// # Returns
+    ///
+    /// The identity the token belongs to, or `None` if it is not valid
+    fn authenticate(&self, token: &str) -> Option<Identity>;
+}
+
+/// A fixed set of bearer tokens, each mapped to an identity
+#[derive(Debug, Clone, Default)]
+pub struct StaticTokens {
+    tokens: Vec<(String, Identity)>,
+}
+
+impl StaticTokens {
+    /// Creates an empty token set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a token
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The secret clients present
+    /// * `identity` - Name of the identity the token authenticates as
+    pub fn with_token(mut self, token: impl Into<String>, identity: impl Into<String>) -> Self {
+        self.tokens.push((token.into(), Identity::new(identity)));
+        self
+    }
+
+    /// Collects the tokens of the `[auth]` section and of its environment variable
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[auth]` section of the configuration file
+    pub fn from_config(config: &AuthConfig) -> Self {
+        let mut tokens = config
+            .tokens
+            .iter()
+            .fold(Self::new(), |tokens, entry| tokens.with_token(&entry.token, &entry.identity));
+        if let Ok(token) = std::env::var(&config.token_env)
+            && !token.is_empty()
+        {
+            tokens = tokens.with_token(token, ENV_TOKEN_IDENTITY);
+        }
+        tokens
+    }
+
+    /// Returns whether no token is configured
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+impl Authenticator for StaticTokens {
+    fn authenticate(&self, token: &str) -> Option<Identity> {
+        // Compare against every token so timing does not reveal which one matched
+        self.tokens
+            .iter()
+            .fold(None, |found, (expected, identity)| {
+                if constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+                    Some(identity)
+                } else {
+                    found
+                }
+            })
+            .cloned()
+    }
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header value
+///
+/// The scheme is matched case-insensitively.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Compares two byte strings in time independent of where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
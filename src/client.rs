@@ -0,0 +1,318 @@
+//! # In-Process Client
+//!
+//! `McpClient` speaks JSON-RPC to an MCP server over any [`Transport`].
+//! Together with [`McpServer::connect_in_process`] it gives tests and
+//! embedding applications a complete protocol session without sockets:
+//!
+//! ```ignore
+//! let (client, _server) = McpServer::new().connect_in_process();
+//! client.initialize(ClientCapabilities::default()).await?;
+//! let tools = client.list_tools().await?;
+//! ```
+//!
+//! Requests and notifications sent by the server (such as
+//! `elicitation/create`) are queued and can be taken with
+//! [`McpClient::next_server_message`] and answered with
+//! [`McpClient::respond`].
+
+use crate::auth::{AUTHENTICATE_METHOD, AuthenticateRequest, AuthenticateResult, Identity};
+use crate::error::McpError;
+use crate::models::*;
+use crate::server::{LATEST_PROTOCOL_VERSION, McpServer};
+use crate::transport::{ChannelTransport, MessageReader, MessageWriter, Transport};
+use anyhow::Context;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Requests awaiting a response from the server, keyed by request ID;
+/// `None` once the connection has ended
+type PendingMap = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<JsonRpcResponse>>>>>;
+
+/// A client connected to an MCP server
+pub struct McpClient {
+    /// Serialized messages queued for the server
+    outbound: mpsc::UnboundedSender<String>,
+    /// Requests awaiting a response
+    pending: PendingMap,
+    /// Allocator for client-side request IDs
+    next_request_id: AtomicU64,
+    /// Requests and notifications sent by the server
+    server_messages: tokio::sync::Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+}
+
+impl McpClient {
+    /// Creates a client talking over a transport
+    ///
+    /// Spawns tasks that write queued messages and route incoming
+    /// responses to the requests awaiting them. The connection is closed
+    /// when the client is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - Connection to the server
+    pub fn new<T: Transport>(transport: T) -> Self {
+        let (mut reader, mut writer) = transport.split();
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<String>();
+        let (server_tx, server_rx) = mpsc::unbounded_channel();
+        let pending: PendingMap = Arc::new(Mutex::new(Some(HashMap::new())));
+
+        tokio::spawn(async move {
+            while let Some(message) = outbound_rx.recv().await {
+                if writer.send(message).await.is_err() {
+                    break;
+                }
+            }
+            let _ = writer.close().await;
+        });
+
+        let routes = Arc::clone(&pending);
+        tokio::spawn(async move {
+            while let Ok(Some(message)) = reader.recv().await {
+                Self::route(&message, &routes, &server_tx);
+            }
+            // No response can arrive anymore; fail everything still waiting
+            routes.lock().unwrap().take();
+        });
+
+        McpClient {
+            outbound,
+            pending,
+            next_request_id: AtomicU64::new(1),
+            server_messages: tokio::sync::Mutex::new(server_rx),
+        }
+    }
+
+    /// Delivers a message from the server to whoever is waiting for it
+    fn route(message: &str, pending: &PendingMap, server_tx: &mpsc::UnboundedSender<McpMessage>) {
+        let Ok(value) = serde_json::from_str::<Value>(message) else {
+            tracing::warn!("客户端收到无效消息: {}", message);
+            return;
+        };
+        if value.get("method").is_some() {
+            if let Ok(message) = serde_json::from_value::<McpMessage>(value) {
+                let _ = server_tx.send(message);
+            }
+            return;
+        }
+
+        match serde_json::from_value::<JsonRpcResponse>(value) {
+            Ok(response) => {
+                let waiter = response
+                    .id
+                    .and_then(|id| pending.lock().unwrap().as_mut()?.remove(&id));
+                match waiter {
+                    Some(waiter) => {
+                        let _ = waiter.send(response);
+                    }
+                    None => tracing::warn!("客户端收到未知请求的响应"),
+                }
+            }
+            Err(e) => tracing::warn!("客户端收到无效响应: {}", e),
+        }
+    }
+
+    /// Sends a request and waits for the server's response
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Request method name
+    /// * `params` - Request parameters
+    ///
+    /// # Returns
+    ///
+    /// Result containing the typed result, `McpError::Client` carrying the
+    /// server's error, or `McpError::SessionClosed` if the connection ended
+    pub async fn request<P, R>(&self, method: &str, params: P) -> Result<R, McpError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(McpError::SessionClosed),
+        };
+
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id,
+        });
+        if self.outbound.send(message.to_string()).is_err() {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&id);
+            }
+            return Err(McpError::SessionClosed);
+        }
+
+        let response = rx.await.map_err(|_| McpError::SessionClosed)?;
+        if let Some(error) = response.error {
+            return Err(McpError::Client(error));
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .map_err(|e| McpError::Internal(anyhow::Error::new(e).context(format!("服务器对 {} 请求的响应格式无效", method))))
+    }
+
+    /// Sends a notification to the server
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Notification method name
+    /// * `params` - Notification parameters
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), McpError> {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        self.outbound
+            .send(message.to_string())
+            .map_err(|_| McpError::SessionClosed)
+    }
+
+    /// Waits for the next request or notification sent by the server
+    ///
+    /// # Returns
+    ///
+    /// `None` once the connection has ended
+    pub async fn next_server_message(&self) -> Option<McpMessage> {
+        self.server_messages.lock().await.recv().await
+    }
+
+    /// Answers a request the server sent to the client
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the server's request
+    /// * `result` - The result, or the error to report
+    pub fn respond(&self, id: u64, result: Result<Value, JsonRpcError>) -> Result<(), McpError> {
+        let response = match result {
+            Ok(result) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: Some(id),
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: Some(id),
+                result: None,
+                error: Some(error),
+            },
+        };
+        self.outbound
+            .send(serde_json::to_string(&response).context("无法序列化响应")?)
+            .map_err(|_| McpError::SessionClosed)
+    }
+
+    /// Authenticates with a bearer token
+    ///
+    /// Over raw TCP this must be the first request when the server
+    /// requires authentication.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token
+    ///
+    /// # Returns
+    ///
+    /// The identity the server resolved the token to
+    pub async fn authenticate(&self, token: &str) -> Result<Identity, McpError> {
+        let request = AuthenticateRequest {
+            token: token.to_string(),
+        };
+        let result: AuthenticateResult = self.request(AUTHENTICATE_METHOD, request).await?;
+        Ok(result.identity)
+    }
+
+    /// Performs the `initialize` handshake
+    ///
+    /// Sends `notifications/initialized` once the server has answered.
+    ///
+    /// # Arguments
+    ///
+    /// * `capabilities` - Capabilities this client declares
+    pub async fn initialize(&self, capabilities: ClientCapabilities) -> Result<InitializeResult, McpError> {
+        let request = InitializeRequest {
+            protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            capabilities,
+            client_info: Implementation {
+                name: concat!(env!("CARGO_PKG_NAME"), "-client").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        };
+        let result = self.request("initialize", request).await?;
+        self.notify("notifications/initialized", serde_json::json!({}))?;
+        Ok(result)
+    }
+
+    /// Lists the server's tools
+    pub async fn list_tools(&self) -> Result<Vec<Tool>, McpError> {
+        let result: ListToolsResult = self.request("tools/list", serde_json::json!({})).await?;
+        Ok(result.tools)
+    }
+
+    /// Calls a tool
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the tool
+    /// * `arguments` - Tool arguments
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<CallToolResult, McpError> {
+        let request = CallToolRequest {
+            name: name.to_string(),
+            arguments,
+        };
+        self.request("tools/call", request).await
+    }
+
+    /// Lists the server's resources
+    pub async fn list_resources(&self) -> Result<Vec<Resource>, McpError> {
+        let result: ListResourcesResult = self.request("resources/list", serde_json::json!({})).await?;
+        Ok(result.resources)
+    }
+
+    /// Reads a resource
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - URI of the resource
+    pub async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>, McpError> {
+        let request = ReadResourceRequest { uri: uri.to_string() };
+        let result: ReadResourceResult = self.request("resources/read", request).await?;
+        Ok(result.contents)
+    }
+}
+
+impl McpServer {
+    /// Connects an in-process client to this server
+    ///
+    /// The two are joined by a pair of tokio channels; no sockets are
+    /// involved. The session ends when the client is dropped.
+    ///
+    /// # Returns
+    ///
+    /// The client, and the handle of the task serving the session
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (client, server) = McpServer::new().connect_in_process();
+    /// client.initialize(ClientCapabilities::default()).await?;
+    /// drop(client);
+    /// server.await??;
+    /// ```
+    pub fn connect_in_process(&self) -> (McpClient, JoinHandle<anyhow::Result<()>>) {
+        let (server_end, client_end) = ChannelTransport::pair();
+        let server = Arc::new(self.clone()).serve_transport(server_end);
+        (McpClient::new(client_end), tokio::spawn(server))
+    }
+}
@@ -0,0 +1,388 @@
+//! # Configuration
+//!
+//! Loads server settings from a TOML file such as `mcp-config.toml`.
+//! Every section is optional; missing values fall back to defaults.
+
+use crate::models::ToolAnnotations;
+use crate::tools::QueuePolicy;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Top-level server configuration
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ServerConfig {
+    /// Server identity reported to clients
+    #[serde(default)]
+    pub server: ServerInfoConfig,
+    /// Per-tool settings keyed by tool name
+    #[serde(default)]
+    pub tools: HashMap<String, ToolConfig>,
+    /// Resource limits applied to connections and requests
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Authentication of network clients
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// TLS for the TCP and WebSocket listeners, plaintext if absent
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Which identities may use which tools and resources, everything if absent
+    #[serde(default)]
+    pub policy: Option<PolicyConfig>,
+    /// Where tools and resources may touch the filesystem
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    /// Audit trail of tool calls and resource reads, off if absent
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+    /// Admin listener serving Prometheus metrics, off if absent
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// Export of request spans to an OpenTelemetry collector, off if absent
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
+}
+
+impl ServerConfig {
+    /// Loads configuration from a TOML file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the configuration file
+    ///
+    /// # Returns
+    ///
+    /// Result containing the parsed configuration, or an error if the file
+    /// cannot be read or is not valid TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("无法读取配置文件 {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("配置文件格式错误 {}", path.display()))
+    }
+
+    /// Parses configuration from a TOML string
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - TOML document
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+/// The `[server]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServerInfoConfig {
+    /// Server name
+    #[serde(default = "default_server_name")]
+    pub name: String,
+    /// Server version
+    #[serde(default = "default_server_version")]
+    pub version: String,
+    /// Free-form description
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Seconds to wait for the client to answer server requests such as sampling
+    #[serde(default = "default_client_request_timeout_secs")]
+    pub client_request_timeout_secs: u64,
+    /// Permissions of the socket file when listening on a Unix domain socket
+    #[serde(default = "default_socket_mode")]
+    pub socket_mode: u32,
+    /// Seconds in-flight requests may take to finish once shutdown starts
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerInfoConfig {
+    fn default() -> Self {
+        ServerInfoConfig {
+            name: default_server_name(),
+            version: default_server_version(),
+            description: None,
+            client_request_timeout_secs: default_client_request_timeout_secs(),
+            socket_mode: default_socket_mode(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+        }
+    }
+}
+
+fn default_server_name() -> String {
+    env!("CARGO_PKG_NAME").to_string()
+}
+
+fn default_server_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+fn default_client_request_timeout_secs() -> u64 {
+    crate::session::DEFAULT_CLIENT_REQUEST_TIMEOUT.as_secs()
+}
+
+fn default_socket_mode() -> u32 {
+    0o660
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+/// The `[limits]` section
+///
+/// A value of 0 disables the corresponding limit.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LimitsConfig {
+    /// Largest inbound message accepted, in bytes
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+    /// Most connections open at once across all clients
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Most connections open at once from a single IP address
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+    /// Seconds a session may sit without traffic or pending work before it is closed
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Seconds a single request handler may run before it is abandoned
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Largest file-backed resource read into memory, in bytes
+    ///
+    /// Text files are read into memory; binary files are streamed to the
+    /// client and are not limited.
+    #[serde(default = "default_max_resource_size")]
+    pub max_resource_size: u64,
+    /// Requests each caller may send across all methods, unlimited if absent
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_message_size: default_max_message_size(),
+            max_connections: default_max_connections(),
+            max_connections_per_ip: 0,
+            idle_timeout_secs: 0,
+            request_timeout_secs: default_request_timeout_secs(),
+            max_resource_size: default_max_resource_size(),
+            rate_limit: None,
+        }
+    }
+}
+
+fn default_max_message_size() -> usize {
+    crate::transport::DEFAULT_MAX_MESSAGE_SIZE
+}
+
+fn default_max_connections() -> usize {
+    1024
+}
+
+fn default_request_timeout_secs() -> u64 {
+    300
+}
+
+fn default_max_resource_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+/// A `rate_limit` table, server-wide under `[limits]` or per tool
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Requests each caller may send per minute on average; 0 for no limit
+    pub per_minute: u32,
+    /// Requests a caller may send at once after being idle, `per_minute` if unset
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+/// The `[auth]` section
+///
+/// Authentication is required on network listeners as soon as at least
+/// one token is configured, here or through the environment variable.
+/// WebSocket clients present the token in the `Authorization` header of
+/// the upgrade request, the only HTTP request the server handles, and TCP
+/// clients in an `authenticate` request.
+///
+/// Without tokens or TLS client certificates, the server refuses to
+/// listen on addresses other than loopback unless `allow_anonymous` is set.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthConfig {
+    /// Accepted bearer tokens
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// Environment variable holding one more token, for the `env` identity
+    #[serde(default = "default_token_env")]
+    pub token_env: String,
+    /// Lets network listeners on non-loopback addresses run without authentication
+    #[serde(default)]
+    pub allow_anonymous: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            tokens: Vec::new(),
+            token_env: default_token_env(),
+            allow_anonymous: false,
+        }
+    }
+}
+
+fn default_token_env() -> String {
+    crate::auth::DEFAULT_TOKEN_ENV.to_string()
+}
+
+/// An `[[auth.tokens]]` entry
+#[derive(Debug, Deserialize, Clone)]
+pub struct TokenConfig {
+    /// Name of the identity the token authenticates as
+    pub identity: String,
+    /// The secret clients present
+    pub token: String,
+}
+
+/// The `[tls]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate chain
+    pub cert_path: PathBuf,
+    /// PEM file with the server's private key
+    pub key_path: PathBuf,
+    /// PEM file with the CA certificates client certificates must chain to
+    ///
+    /// When set, clients without a valid certificate are refused.
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// The `[sandbox]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct SandboxConfig {
+    /// Directories filesystem access is confined to; empty to allow any
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    /// Wildcard patterns of canonical paths that may never be accessed
+    #[serde(default = "default_sandbox_deny")]
+    pub deny: Vec<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            roots: Vec::new(),
+            deny: default_sandbox_deny(),
+        }
+    }
+}
+
+fn default_sandbox_deny() -> Vec<String> {
+    crate::sandbox::DEFAULT_DENY.iter().map(|pattern| pattern.to_string()).collect()
+}
+
+/// The `[audit]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuditConfig {
+    /// JSON Lines file records are appended to
+    pub path: PathBuf,
+    /// Size in bytes at which the file is rotated; 0 to never rotate
+    #[serde(default = "default_audit_max_bytes")]
+    pub max_bytes: u64,
+    /// Rotated files kept next to the current one, as `<path>.1` (newest) and up
+    #[serde(default = "default_audit_max_files")]
+    pub max_files: usize,
+    /// Wildcard patterns of argument names whose values are replaced, matched
+    /// case-insensitively at any depth
+    #[serde(default = "default_audit_redact")]
+    pub redact: Vec<String>,
+}
+
+fn default_audit_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_audit_max_files() -> usize {
+    5
+}
+
+fn default_audit_redact() -> Vec<String> {
+    crate::audit::DEFAULT_REDACT.iter().map(|pattern| pattern.to_string()).collect()
+}
+
+/// The `[metrics]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct MetricsConfig {
+    /// Address of the admin listener serving `/metrics`, such as `127.0.0.1:9090`
+    pub listen: String,
+}
+
+/// The `[telemetry]` section
+#[derive(Debug, Deserialize, Clone)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP endpoint of the collector, such as `http://127.0.0.1:4318`;
+    /// `/v1/traces` is appended when it has no path
+    pub otlp_endpoint: String,
+    /// `service.name` reported with every span
+    #[serde(default = "default_server_name")]
+    pub service_name: String,
+}
+
+/// The `[policy]` section
+///
+/// Once present, anything no rule grants is denied.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PolicyConfig {
+    /// Roles keyed by name, each listing the identities that hold it
+    #[serde(default)]
+    pub roles: HashMap<String, Vec<String>>,
+    /// Grants, checked in order until one matches
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// A `[[policy.rules]]` entry
+///
+/// The rule applies to a client whose identity matches one of `identities`
+/// or who holds one of `roles`. Identity, tool and resource entries are
+/// wildcard patterns such as `ops-*` or `file:///srv/docs/*`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PolicyRule {
+    /// Identity patterns the rule applies to
+    #[serde(default)]
+    pub identities: Vec<String>,
+    /// Roles the rule applies to
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Tool name patterns the rule allows listing and calling
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Resource URI patterns the rule allows listing and reading
+    #[serde(default)]
+    pub resources: Vec<String>,
+}
+
+/// A `[tools.<name>]` section
+///
+/// Unknown keys are ignored so tool-specific settings can live alongside.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ToolConfig {
+    /// Annotation overrides, merged over the tool's built-in hints
+    #[serde(default)]
+    pub annotations: Option<ToolAnnotations>,
+    /// Seconds one execution may run, replacing the tool's default; 0 for no limit
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Most executions running at once across all sessions; 0 for no limit
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    /// Whether calls over `max_concurrency` wait or are rejected
+    #[serde(default)]
+    pub queue: Option<QueuePolicy>,
+    /// Calls each caller may make to this tool, on top of the server-wide limit
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+}
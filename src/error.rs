@@ -0,0 +1,276 @@
+//! # Error Types
+//!
+//! Defines `McpError`, the error type returned by every request handler.
+//! Each variant maps to a stable JSON-RPC error code and carries enough
+//! context to build the `data` payload of the error response.
+
+use crate::models::JsonRpcError;
+use serde_json::{Value, json};
+use thiserror::Error;
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method (or tool) does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters or tool arguments
+pub const INVALID_PARAMS: i64 = -32602;
+/// Internal server error
+pub const INTERNAL_ERROR: i64 = -32603;
+/// A request sent to the client was not answered in time
+pub const REQUEST_TIMEOUT: i64 = -32001;
+/// The requested resource does not exist
+pub const RESOURCE_NOT_FOUND: i64 = -32002;
+/// The peer did not declare a capability the operation needs
+pub const CAPABILITY_NOT_SUPPORTED: i64 = -32003;
+/// The session ended before the operation completed
+pub const SESSION_CLOSED: i64 = -32004;
+/// The target lies outside what the caller may access
+pub const ACCESS_DENIED: i64 = -32005;
+/// The server is shutting down and no longer runs requests
+pub const SHUTTING_DOWN: i64 = -32006;
+/// A request handler did not finish within the configured time
+pub const HANDLER_TIMEOUT: i64 = -32007;
+/// A tool did not finish within its execution timeout
+pub const TOOL_TIMEOUT: i64 = -32008;
+/// A tool is already running at its concurrency limit
+pub const TOOL_OVERLOADED: i64 = -32009;
+/// The client did not present valid credentials
+pub const UNAUTHENTICATED: i64 = -32010;
+/// The caller sent more requests than its rate limit allows
+pub const RATE_LIMITED: i64 = -32011;
+/// The resource is larger than the configured maximum
+pub const RESOURCE_TOO_LARGE: i64 = -32012;
+
+/// Errors produced while handling an MCP request
+#[derive(Debug, Error)]
+pub enum McpError {
+    /// The message could not be parsed as JSON
+    #[error("解析错误: {0}")]
+    Parse(serde_json::Error),
+    /// The message is JSON but not a valid JSON-RPC request
+    #[error("无效请求: {0}")]
+    InvalidRequest(String),
+    /// The requested method is not implemented
+    #[error("方法未找到: {0}")]
+    MethodNotFound(String),
+    /// The requested tool is not registered
+    #[error("工具未找到: {0}")]
+    ToolNotFound(String),
+    /// The parameters failed validation
+    #[error("无效参数: {message}")]
+    InvalidParams {
+        /// Description of what is wrong
+        message: String,
+        /// Structured validation details, if any
+        details: Option<Value>,
+    },
+    /// The requested resource is not registered
+    #[error("资源未找到: {uri}")]
+    ResourceNotFound {
+        /// URI that was requested
+        uri: String,
+    },
+    /// The resource exists but could not be read
+    #[error("资源读取失败: {uri}")]
+    ResourceRead {
+        /// URI that was requested
+        uri: String,
+        /// Underlying cause
+        source: anyhow::Error,
+    },
+    /// The resource is larger than the server is configured to read into memory
+    #[error("资源过大: {uri} 有 {size} 字节，上限为 {limit} 字节")]
+    ResourceTooLarge {
+        /// URI that was requested
+        uri: String,
+        /// Size of the resource, in bytes
+        size: u64,
+        /// The limit that was exceeded, in bytes
+        limit: u64,
+    },
+    /// The target lies outside what the caller may access
+    #[error("拒绝访问: {target}")]
+    AccessDenied {
+        /// Path or URI that was requested
+        target: String,
+        /// Why access was denied
+        reason: String,
+    },
+    /// The client did not declare a capability the operation needs
+    #[error("客户端不支持该功能: {0}")]
+    CapabilityNotSupported(String),
+    /// A request sent to the client was not answered in time
+    #[error("客户端请求超时: {method}")]
+    RequestTimeout {
+        /// Method of the unanswered request
+        method: String,
+        /// Timeout that elapsed, in milliseconds
+        timeout_ms: u64,
+    },
+    /// The client answered a server request with an error
+    #[error("客户端返回错误: {}", .0.message)]
+    Client(JsonRpcError),
+    /// The session ended before the operation completed
+    #[error("会话已关闭")]
+    SessionClosed,
+    /// An inbound message exceeded the configured size limit
+    #[error("消息过大，上限为 {limit} 字节")]
+    MessageTooLarge {
+        /// The limit that was exceeded, in bytes
+        limit: usize,
+    },
+    /// A request handler did not finish within the configured time
+    #[error("请求处理超时: {method}")]
+    HandlerTimeout {
+        /// Method of the abandoned request
+        method: String,
+        /// Timeout that elapsed, in milliseconds
+        timeout_ms: u64,
+    },
+    /// A tool did not finish within its execution timeout
+    #[error("工具执行超时: {tool}")]
+    ToolTimeout {
+        /// Name of the tool
+        tool: String,
+        /// Timeout that elapsed, in milliseconds
+        timeout_ms: u64,
+    },
+    /// A tool is already running at its concurrency limit
+    #[error("工具繁忙: {tool}")]
+    ToolOverloaded {
+        /// Name of the tool
+        tool: String,
+        /// Most executions allowed at once
+        max_concurrency: usize,
+    },
+    /// The client did not present valid credentials
+    #[error("认证失败: {0}")]
+    Unauthenticated(String),
+    /// The caller sent more requests than its rate limit allows
+    #[error("请求过于频繁，请在 {retry_after_ms} 毫秒后重试")]
+    RateLimited {
+        /// Name of the tool whose limit was hit, `None` for the server-wide limit
+        tool: Option<String>,
+        /// How long to wait before the next request can succeed, in milliseconds
+        retry_after_ms: u64,
+    },
+    /// The server is shutting down and did not run, or cancelled, the request
+    #[error("服务器正在关闭")]
+    ShuttingDown,
+    /// Any other failure inside the server
+    #[error("内部错误: {0:#}")]
+    Internal(anyhow::Error),
+}
+
+impl McpError {
+    /// Creates an `InvalidParams` error without structured details
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        McpError::InvalidParams {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Gets the JSON-RPC error code for this error
+    pub fn code(&self) -> i64 {
+        match self {
+            McpError::Parse(_) => PARSE_ERROR,
+            McpError::InvalidRequest(_) | McpError::MessageTooLarge { .. } => INVALID_REQUEST,
+            McpError::MethodNotFound(_) | McpError::ToolNotFound(_) => METHOD_NOT_FOUND,
+            McpError::InvalidParams { .. } => INVALID_PARAMS,
+            McpError::ResourceNotFound { .. } => RESOURCE_NOT_FOUND,
+            McpError::ResourceTooLarge { .. } => RESOURCE_TOO_LARGE,
+            McpError::AccessDenied { .. } => ACCESS_DENIED,
+            McpError::CapabilityNotSupported(_) => CAPABILITY_NOT_SUPPORTED,
+            McpError::RequestTimeout { .. } => REQUEST_TIMEOUT,
+            McpError::Client(error) => error.code,
+            McpError::SessionClosed => SESSION_CLOSED,
+            McpError::HandlerTimeout { .. } => HANDLER_TIMEOUT,
+            McpError::ToolTimeout { .. } => TOOL_TIMEOUT,
+            McpError::ToolOverloaded { .. } => TOOL_OVERLOADED,
+            McpError::Unauthenticated(_) => UNAUTHENTICATED,
+            McpError::RateLimited { .. } => RATE_LIMITED,
+            McpError::ShuttingDown => SHUTTING_DOWN,
+            McpError::ResourceRead { .. } | McpError::Internal(_) => INTERNAL_ERROR,
+        }
+    }
+
+    /// Builds the optional `data` payload for this error
+    ///
+    /// Includes the cause chain, the resource URI and validation details
+    /// where they apply.
+    pub fn data(&self) -> Option<Value> {
+        match self {
+            McpError::Parse(e) => Some(json!({ "cause": [e.to_string()] })),
+            McpError::InvalidRequest(_) => None,
+            McpError::MethodNotFound(method) => Some(json!({ "method": method })),
+            McpError::ToolNotFound(name) => Some(json!({ "tool": name })),
+            McpError::InvalidParams { details, .. } => {
+                details.as_ref().map(|details| json!({ "details": details }))
+            }
+            McpError::ResourceNotFound { uri } => Some(json!({ "uri": uri })),
+            McpError::ResourceRead { uri, source } => Some(json!({
+                "uri": uri,
+                "cause": cause_chain(source),
+            })),
+            McpError::ResourceTooLarge { uri, size, limit } => Some(json!({
+                "uri": uri,
+                "size": size,
+                "limit": limit,
+            })),
+            McpError::AccessDenied { target, reason } => Some(json!({
+                "target": target,
+                "reason": reason,
+            })),
+            McpError::CapabilityNotSupported(capability) => {
+                Some(json!({ "capability": capability }))
+            }
+            McpError::RequestTimeout { method, timeout_ms } => Some(json!({
+                "method": method,
+                "timeoutMs": timeout_ms,
+            })),
+            McpError::MessageTooLarge { limit } => Some(json!({ "limit": limit })),
+            McpError::HandlerTimeout { method, timeout_ms } => Some(json!({
+                "method": method,
+                "timeoutMs": timeout_ms,
+            })),
+            McpError::ToolTimeout { tool, timeout_ms } => Some(json!({
+                "tool": tool,
+                "timeoutMs": timeout_ms,
+            })),
+            McpError::ToolOverloaded { tool, max_concurrency } => Some(json!({
+                "tool": tool,
+                "maxConcurrency": max_concurrency,
+            })),
+            McpError::RateLimited { tool, retry_after_ms } => Some(match tool {
+                Some(tool) => json!({ "tool": tool, "retryAfterMs": retry_after_ms }),
+                None => json!({ "retryAfterMs": retry_after_ms }),
+            }),
+            McpError::Client(error) => error.data.clone(),
+            McpError::SessionClosed | McpError::ShuttingDown | McpError::Unauthenticated(_) => None,
+            McpError::Internal(source) => Some(json!({ "cause": cause_chain(source) })),
+        }
+    }
+
+    /// Converts this error into the `error` member of a JSON-RPC response
+    pub fn to_json_rpc(&self) -> JsonRpcError {
+        JsonRpcError {
+            code: self.code(),
+            message: self.to_string(),
+            data: self.data(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for McpError {
+    fn from(error: anyhow::Error) -> Self {
+        McpError::Internal(error)
+    }
+}
+
+/// Flattens an error and its sources into a list of messages
+fn cause_chain(error: &anyhow::Error) -> Vec<String> {
+    error.chain().map(|cause| cause.to_string()).collect()
+}
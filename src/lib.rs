@@ -14,18 +14,60 @@
 //!
 //! ## Module Structure
 //!
+//! - [`audit`]: JSON Lines audit trail of tool calls and resource reads
+//! - [`auth`]: Bearer-token authentication of network clients
+//! - [`client`]: In-process client for tests and embedding
+//! - [`config`]: Configuration file loading
+//! - [`error`]: Error type shared by all request handlers
+//! - [`limits`]: Connection count limits
+//! - [`logging`]: Forwarding of log records to clients
+//! - [`metrics`]: Prometheus metrics and the admin endpoint serving them
 //! - [`models`]: Core data structures for MCP protocol
+//! - [`policy`]: Per-identity authorization of tools and resources
+//! - [`prompts`]: Prompt registry and implementations
+//! - [`rate_limit`]: Token-bucket rate limiting of requests and tool calls
+//! - [`sandbox`]: Canonicalization and confinement of filesystem paths
 //! - [`server`]: TCP server implementation and message routing
+//! - [`session`]: Per-connection state and server-to-client requests
+//! - [`shutdown`]: Graceful shutdown handle
+//! - [`telemetry`]: W3C trace context and OTLP export of request spans
+//! - [`tls`]: TLS and mutual TLS for network listeners
 //! - [`tools`]: Tool registry and implementations
+//! - [`transport`]: Message framing for sockets, WebSocket and in-memory channels
+//! - [`wildcard`]: Shell-style patterns for names and URIs
 //! - [`resources`]: Resource management and access
+//! - [`roots`]: Client filesystem roots and path confinement
 
+pub mod audit;
+pub mod auth;
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
 pub mod models;
+pub mod policy;
+pub mod prompts;
+pub mod rate_limit;
+pub mod sandbox;
 pub mod server;
+pub mod session;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tls;
 pub mod tools;
+pub mod transport;
+pub mod wildcard;
 pub mod resources;
+pub mod roots;
 
 // Re-export commonly used types
+pub use client::McpClient;
+pub use error::McpError;
 pub use models::*;
 pub use server::McpServer;
+pub use session::{RequestContext, Session};
 pub use tools::ToolRegistry;
+pub use prompts::PromptRegistry;
 pub use resources::ResourceRegistry;
@@ -0,0 +1,115 @@
+//! # Connection Limits
+//!
+//! Caps how many connections the server holds open at once, both overall
+//! and per client IP address. Listeners ask the [`ConnectionLimiter`] for a
+//! [`ConnectionPermit`] before serving a connection; the permit gives its
+//! slot back when the connection ends.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// A connection was refused because a limit was reached
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConnectionLimitExceeded {
+    /// The server already holds the maximum number of connections
+    #[error("连接数已达上限 {0}")]
+    Total(usize),
+    /// The client's address already holds the maximum number of connections
+    #[error("来自 {0} 的连接数已达上限 {1}")]
+    PerIp(IpAddr, usize),
+}
+
+/// Counts open connections against the configured limits
+///
+/// Cheap to clone; all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct ConnectionLimiter {
+    /// Most connections overall, 0 for no limit
+    max_connections: usize,
+    /// Most connections per IP address, 0 for no limit
+    max_connections_per_ip: usize,
+    counts: Arc<Mutex<ConnectionCounts>>,
+}
+
+#[derive(Default)]
+struct ConnectionCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimiter {
+    /// Creates a limiter
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - Most connections overall, 0 for no limit
+    /// * `max_connections_per_ip` - Most connections per IP address, 0 for no limit
+    pub fn new(max_connections: usize, max_connections_per_ip: usize) -> Self {
+        ConnectionLimiter {
+            max_connections,
+            max_connections_per_ip,
+            counts: Arc::default(),
+        }
+    }
+
+    /// Reserves a slot for a new connection
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - IP address of the client, `None` for local transports
+    ///   such as Unix sockets, which only count towards the overall limit
+    ///
+    /// # Returns
+    ///
+    /// A permit holding the slot until it is dropped, or the limit that
+    /// was reached
+    pub fn try_acquire(&self, peer: Option<IpAddr>) -> Result<ConnectionPermit, ConnectionLimitExceeded> {
+        let mut counts = self.counts.lock().unwrap();
+        if self.max_connections > 0 && counts.total >= self.max_connections {
+            return Err(ConnectionLimitExceeded::Total(self.max_connections));
+        }
+        if let Some(ip) = peer {
+            let per_ip = counts.per_ip.get(&ip).copied().unwrap_or_default();
+            if self.max_connections_per_ip > 0 && per_ip >= self.max_connections_per_ip {
+                return Err(ConnectionLimitExceeded::PerIp(ip, self.max_connections_per_ip));
+            }
+            counts.per_ip.insert(ip, per_ip + 1);
+        }
+        counts.total += 1;
+
+        Ok(ConnectionPermit {
+            counts: Arc::clone(&self.counts),
+            peer,
+        })
+    }
+
+    /// Gets the number of connections currently open
+    pub fn open_connections(&self) -> usize {
+        self.counts.lock().unwrap().total
+    }
+}
+
+/// A slot reserved by [`ConnectionLimiter::try_acquire`]
+///
+/// Releases the slot when dropped.
+pub struct ConnectionPermit {
+    counts: Arc<Mutex<ConnectionCounts>>,
+    peer: Option<IpAddr>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(ip) = self.peer
+            && let Some(count) = counts.per_ip.get_mut(&ip)
+        {
+            *count -= 1;
+            if *count == 0 {
+                counts.per_ip.remove(&ip);
+            }
+        }
+    }
+}
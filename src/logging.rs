@@ -0,0 +1,266 @@
+//! # Logging
+//!
+//! Implements the MCP logging capability. A `tracing` layer forwards
+//! records to connected clients as `notifications/message`, filtered by
+//! the level each client selected with `logging/setLevel`.
+//!
+//! Only records emitted inside a span carrying a `session_id` field are
+//! forwarded, and only to that session. Records outside any session, such
+//! as other clients' handshakes or exporter failures, stay on the server,
+//! as do records under the `audit` target, which are meant for operators.
+
+use crate::session::Session;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock, Weak};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name of the span field that ties records to a session
+pub const SESSION_ID_FIELD: &str = "session_id";
+
+/// Targets whose records are never forwarded to clients
+const SERVER_ONLY_TARGETS: &[&str] = &["audit"];
+
+/// Severity of a log message, as defined by MCP (RFC 5424 levels)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    /// Detailed debugging information
+    Debug,
+    /// Informational messages
+    Info,
+    /// Normal but significant events
+    Notice,
+    /// Warning conditions
+    Warning,
+    /// Error conditions
+    Error,
+    /// Critical conditions
+    Critical,
+    /// Action must be taken immediately
+    Alert,
+    /// System is unusable
+    Emergency,
+}
+
+impl From<tracing::Level> for LoggingLevel {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::TRACE | tracing::Level::DEBUG => LoggingLevel::Debug,
+            tracing::Level::INFO => LoggingLevel::Info,
+            tracing::Level::WARN => LoggingLevel::Warning,
+            tracing::Level::ERROR => LoggingLevel::Error,
+        }
+    }
+}
+
+/// Parameters of the `logging/setLevel` request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetLevelRequest {
+    /// Minimum level the client wants to receive
+    pub level: LoggingLevel,
+}
+
+/// Parameters of a `notifications/message` notification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoggingMessageNotification {
+    /// Severity of the message
+    pub level: LoggingLevel,
+    /// Name of the component that logged the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logger: Option<String>,
+    /// The message and its structured fields
+    pub data: Value,
+}
+
+/// Set of sessions that log records can be delivered to
+///
+/// Cheap to clone; clones share the same set. The server registers each
+/// session for the lifetime of its connection.
+#[derive(Clone, Default)]
+pub struct LogRouter {
+    sessions: Arc<RwLock<HashMap<u64, Weak<Session>>>>,
+}
+
+impl LogRouter {
+    /// Creates an empty router
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts delivering log records to a session
+    pub fn register(&self, session: &Arc<Session>) {
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id(), Arc::downgrade(session));
+    }
+
+    /// Stops delivering log records to a session
+    pub fn unregister(&self, session_id: u64) {
+        self.sessions.write().unwrap().remove(&session_id);
+    }
+
+    /// Returns whether any session wants records of this level
+    pub fn wants(&self, level: LoggingLevel) -> bool {
+        self.sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(Weak::upgrade)
+            .any(|session| session.log_level().is_some_and(|min| level >= min))
+    }
+
+    /// Delivers a log message to a session, if it wants it
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Session the record belongs to
+    /// * `message` - The log message
+    pub fn dispatch(&self, target: u64, message: &LoggingMessageNotification) {
+        let session = self.sessions.read().unwrap().get(&target).and_then(Weak::upgrade);
+        if let Some(session) = session
+            && session.log_level().is_some_and(|level| message.level >= level)
+        {
+            let _ = session.notify("notifications/message", message);
+        }
+    }
+}
+
+/// Session a span belongs to, stored in the span's extensions
+struct SessionScope(u64);
+
+/// A `tracing` layer that forwards records to MCP clients
+pub struct McpLogLayer {
+    router: LogRouter,
+}
+
+impl McpLogLayer {
+    /// Creates a layer delivering records through `router`
+    pub fn new(router: LogRouter) -> Self {
+        McpLogLayer { router }
+    }
+}
+
+impl<S> Layer<S> for McpLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = SessionIdVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(session_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SessionScope(session_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = LoggingLevel::from(*event.metadata().level());
+        if SERVER_ONLY_TARGETS.contains(&event.metadata().target()) || !self.router.wants(level) {
+            return;
+        }
+
+        let target = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<SessionScope>().map(|scope| scope.0))
+        });
+        let Some(target) = target else {
+            return;
+        };
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut data = Map::new();
+        if let Some(message) = visitor.message {
+            data.insert("message".to_string(), Value::String(message));
+        }
+        if !visitor.fields.is_empty() {
+            data.insert("fields".to_string(), Value::Object(visitor.fields));
+        }
+
+        let message = LoggingMessageNotification {
+            level,
+            logger: Some(event.metadata().target().to_string()),
+            data: Value::Object(data),
+        };
+        self.router.dispatch(target, &message);
+    }
+}
+
+/// Extracts the `session_id` field from span attributes
+struct SessionIdVisitor(Option<u64>);
+
+impl Visit for SessionIdVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == SESSION_ID_FIELD {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == SESSION_ID_FIELD {
+            self.0 = u64::try_from(value).ok();
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// Collects the message and structured fields of an event as JSON
+#[derive(Default)]
+pub(crate) struct FieldVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    /// Gets the structured fields collected, without the message
+    pub(crate) fn into_fields(self) -> Map<String, Value> {
+        self.fields
+    }
+
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(match value {
+                Value::String(message) => message,
+                other => other.to_string(),
+            });
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::String(format!("{:?}", value)));
+    }
+}
@@ -3,8 +3,13 @@
 //! This is the main entry point for the MCP Server application.
 //! It provides a command-line interface for starting the server and managing tools/resources.
 
-use mcp_server_rust::server::McpServer;
+use mcp_server_rust::config::ServerConfig;
+use mcp_server_rust::server::{ListenAddr, McpServer};
+use mcp_server_rust::telemetry;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::prelude::*;
 
 /// Command-line interface configuration
 ///
@@ -12,13 +17,16 @@ use clap::{Parser, Subcommand};
 #[derive(Parser)]
 #[command(author, version, about = "MCP Server - Model Context Protocol Server", long_about = None)]
 struct Cli {
+    /// 配置文件路径 (Path to the TOML configuration file)
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
 /// Available CLI commands
 ///
-/// - `Start`: Launch the TCP server on the specified address
+/// - `Start`: Launch the server on a TCP address, Unix domain socket, WebSocket address or stdio
 /// - `ListTools`: Display all registered tools
 /// - `ListResources`: Display all available resources
 #[derive(Subcommand)]
@@ -28,6 +36,9 @@ enum Commands {
         /// 监听地址 (Listening address)
         #[arg(short, long, default_value = "127.0.0.1:8080")]
         address: String,
+        /// 监听位置，覆盖 --address，如 unix:/run/mcp.sock、ws:127.0.0.1:8081 或 stdio (Listen location, overrides --address)
+        #[arg(short, long)]
+        listen: Option<ListenAddr>,
     },
     /// 列出所有可用的工具 (List all available tools)
     ListTools,
@@ -45,12 +56,43 @@ enum Commands {
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let server = McpServer::new();
+    let config = match &cli.config {
+        Some(path) => ServerConfig::load(path)?,
+        None => ServerConfig::default(),
+    };
+    let (otlp_layer, otlp) = config.telemetry.as_ref().map(telemetry::otlp).transpose()?.unzip();
+    let server = McpServer::with_config(config);
+
+    // Diagnostics go to stderr (filtered by RUST_LOG) and to clients that
+    // enabled logging with `logging/setLevel`; request spans also go to
+    // the OpenTelemetry collector, if one is configured
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))),
+        )
+        .with(server.log_layer())
+        .with(otlp_layer)
+        .init();
 
     match cli.command {
-        Commands::Start { address } => {
-            println!("启动 MCP 服务器...");
-            server.start(&address).await?;
+        Commands::Start { address, listen } => {
+            let listen = listen.unwrap_or(ListenAddr::Tcp(address));
+            tracing::info!("启动 MCP 服务器...");
+            let shutdown = server.shutdown_handle();
+            tokio::spawn(async move {
+                match shutdown_signal().await {
+                    Ok(()) => tracing::info!("收到关闭信号，开始优雅关闭"),
+                    Err(e) => tracing::error!("无法监听关闭信号: {}", e),
+                }
+                shutdown.shutdown();
+            });
+            server.serve(&listen).await?;
+            server.flush_audit().await;
+            if let Some(otlp) = otlp {
+                otlp.flush().await;
+            }
         }
         Commands::ListTools => {
             // Display all registered tools in a formatted manner
@@ -62,6 +104,22 @@ async fn main() -> anyhow::Result<()> {
                 for tool in tools {
                     println!("\n工具: {}", tool.name);
                     println!("描述: {}", tool.description);
+                    if let Some(annotations) = &tool.annotations {
+                        let hints = [
+                            (annotations.read_only_hint, "只读"),
+                            (annotations.destructive_hint, "破坏性"),
+                            (annotations.idempotent_hint, "幂等"),
+                            (annotations.open_world_hint, "访问外部"),
+                        ];
+                        let hints: Vec<&str> = hints
+                            .iter()
+                            .filter(|(hint, _)| *hint == Some(true))
+                            .map(|(_, label)| *label)
+                            .collect();
+                        if !hints.is_empty() {
+                            println!("特性: {}", hints.join(", "));
+                        }
+                    }
                     println!("参数:");
                     for (param_name, prop) in &tool.input_schema.properties {
                         let required = if tool.input_schema.required.contains(param_name) {
@@ -90,3 +148,21 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Waits for SIGINT (Ctrl-C) or, on Unix, SIGTERM
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
@@ -0,0 +1,230 @@
+//! # Metrics
+//!
+//! Prometheus metrics about requests, sessions and traffic, served in the
+//! text exposition format at `/metrics` on a separate admin listener
+//! configured by the `[metrics]` section of the configuration file.
+//!
+//! Each server has its own registry, so several servers in one process
+//! (as in tests) do not share counters.
+
+use crate::error::McpError;
+use anyhow::Result;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Path the metrics are served at
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Largest scrape request read, in bytes
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time a scraper has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Label used for methods and tools the server does not have
+///
+/// Keeps clients from creating a series per made-up name.
+pub const UNKNOWN_LABEL: &str = "unknown";
+
+/// All metrics of one server
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    errors: IntCounterVec,
+    active_sessions: IntGauge,
+    in_flight_tool_calls: IntGaugeVec,
+    received_bytes: IntCounter,
+    sent_bytes: IntCounter,
+    resource_read_bytes: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates the metrics in a fresh registry
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new("mcp_requests_total", "Requests handled, by method and tool"),
+            &["method", "tool"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("mcp_request_duration_seconds", "Time taken to handle requests, by method and tool"),
+            &["method", "tool"],
+        )
+        .unwrap();
+        let errors = IntCounterVec::new(
+            Opts::new("mcp_errors_total", "Error responses sent, by JSON-RPC error code"),
+            &["code"],
+        )
+        .unwrap();
+        let active_sessions = IntGauge::new("mcp_active_sessions", "Sessions currently open").unwrap();
+        let in_flight_tool_calls = IntGaugeVec::new(
+            Opts::new("mcp_in_flight_tool_calls", "Tool executions currently running, by tool"),
+            &["tool"],
+        )
+        .unwrap();
+        let received_bytes =
+            IntCounter::new("mcp_received_bytes_total", "Bytes of messages received from clients").unwrap();
+        let sent_bytes = IntCounter::new("mcp_sent_bytes_total", "Bytes of messages sent to clients").unwrap();
+        let resource_read_bytes = Histogram::with_opts(
+            HistogramOpts::new("mcp_resource_read_bytes", "Size of resource contents returned by reads")
+                .buckets(prometheus::exponential_buckets(256.0, 4.0, 10).unwrap()),
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(request_duration.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(active_sessions.clone())).unwrap();
+        registry.register(Box::new(in_flight_tool_calls.clone())).unwrap();
+        registry.register(Box::new(received_bytes.clone())).unwrap();
+        registry.register(Box::new(sent_bytes.clone())).unwrap();
+        registry.register(Box::new(resource_read_bytes.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            errors,
+            active_sessions,
+            in_flight_tool_calls,
+            received_bytes,
+            sent_bytes,
+            resource_read_bytes,
+        }
+    }
+
+    /// Records a handled request
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - JSON-RPC method of the request if the server handles
+    ///   it, [`UNKNOWN_LABEL`] otherwise
+    /// * `tool` - Name of the tool for `tools/call`, [`UNKNOWN_LABEL`] for
+    ///   tools that are not registered and empty for other methods
+    /// * `result` - What the handler returned
+    /// * `duration` - Time the request took
+    pub fn observe_request(
+        &self,
+        method: &str,
+        tool: &str,
+        result: &Result<serde_json::Value, McpError>,
+        duration: Duration,
+    ) {
+        self.requests.with_label_values(&[method, tool]).inc();
+        self.request_duration
+            .with_label_values(&[method, tool])
+            .observe(duration.as_secs_f64());
+        if let Err(error) = result {
+            self.observe_error(error);
+        }
+    }
+
+    /// Records an error response
+    pub fn observe_error(&self, error: &McpError) {
+        self.errors.with_label_values(&[&error.code().to_string()]).inc();
+    }
+
+    /// Counts a session as open until the returned guard is dropped
+    pub fn session_started(&self) -> GaugeGuard {
+        GaugeGuard::new(self.active_sessions.clone())
+    }
+
+    /// Counts a tool execution as running until the returned guard is dropped
+    pub fn tool_call_started(&self, tool: &str) -> GaugeGuard {
+        GaugeGuard::new(self.in_flight_tool_calls.with_label_values(&[tool]))
+    }
+
+    /// Adds to the bytes received from clients
+    pub fn add_received(&self, bytes: usize) {
+        self.received_bytes.inc_by(bytes as u64);
+    }
+
+    /// Adds to the bytes sent to clients
+    pub fn add_sent(&self, bytes: usize) {
+        self.sent_bytes.inc_by(bytes as u64);
+    }
+
+    /// Records the size of the contents returned by a resource read
+    pub fn observe_resource_read(&self, bytes: usize) {
+        self.resource_read_bytes.observe(bytes as f64);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+/// Increments a gauge while alive and decrements it when dropped
+pub struct GaugeGuard(IntGauge);
+
+impl GaugeGuard {
+    fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        GaugeGuard(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Answers one HTTP request on the admin listener
+///
+/// `GET /metrics` returns the metrics; any other path is answered with
+/// 404 and any other method with 405. The connection is closed afterwards.
+///
+/// # Arguments
+///
+/// * `stream` - An accepted admin connection
+/// * `metrics` - The metrics to serve
+pub async fn serve_scrape(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 || request.len() + n > MAX_REQUEST_SIZE {
+                anyhow::bail!("无效的指标请求");
+            }
+            request.extend_from_slice(&buffer[..n]);
+        }
+        Ok(())
+    })
+    .await??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => ("200 OK", TextEncoder::new().format_type().to_string(), metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain".to_string(), "Not Found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain".to_string(), "Method Not Allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -18,6 +18,50 @@ pub struct Tool {
     /// Input parameter schema for validation
     #[serde(rename = "inputSchema")]
     pub input_schema: ToolInputSchema,
+    /// Behavioral hints for clients deciding how to present the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Hints describing how a tool behaves
+///
+/// Hosts use these to decide, for example, which calls need human
+/// confirmation. They are advisory and not enforced by the server.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// Human-readable title for the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The tool does not modify its environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    /// The tool may perform destructive updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    /// Repeated calls with the same arguments have no additional effect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    /// The tool interacts with entities outside the server's control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /// Overlays the hints that are set in `other` onto these annotations
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Annotations whose `Some` values take precedence
+    pub fn merge(&mut self, other: &ToolAnnotations) {
+        if other.title.is_some() {
+            self.title = other.title.clone();
+        }
+        self.read_only_hint = other.read_only_hint.or(self.read_only_hint);
+        self.destructive_hint = other.destructive_hint.or(self.destructive_hint);
+        self.idempotent_hint = other.idempotent_hint.or(self.idempotent_hint);
+        self.open_world_hint = other.open_world_hint.or(self.open_world_hint);
+    }
 }
 
 /// Defines the input schema for a tool using JSON Schema
@@ -46,16 +90,118 @@ pub struct Property {
     pub description: String,
 }
 
-/// Represents content in response to a tool call
+/// Represents a single content item in a tool result or resource read
 ///
-/// Contains the actual result data from a tool execution.
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Content {
-    /// Content type (typically "text")
-    #[serde(rename = "type")]
-    pub type_: String,
-    /// The actual content/result text
-    pub text: String,
+/// Serialized as a JSON object tagged by its `type` field, so that
+/// text, binary media and resources can all appear in the same array.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    /// Plain text content
+    Text {
+        /// The actual content/result text
+        text: String,
+    },
+    /// Base64-encoded image data
+    Image {
+        /// Base64-encoded image bytes
+        data: String,
+        /// MIME type of the image (e.g., "image/png")
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Base64-encoded audio data
+    Audio {
+        /// Base64-encoded audio bytes
+        data: String,
+        /// MIME type of the audio (e.g., "audio/wav")
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// A resource embedded directly in the result
+    Resource {
+        /// The embedded resource contents
+        resource: ResourceContents,
+    },
+    /// A link to a resource the client may read separately
+    ResourceLink {
+        /// URI of the linked resource
+        uri: String,
+        /// Name of the linked resource
+        name: String,
+        /// Optional human-readable description
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        /// Optional MIME type of the linked resource
+        #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+impl Content {
+    /// Creates a text content item
+    pub fn text(text: impl Into<String>) -> Self {
+        Content::Text { text: text.into() }
+    }
+
+    /// Creates an image content item from base64 data
+    pub fn image(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Content::Image {
+            data: data.into(),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// Creates an audio content item from base64 data
+    pub fn audio(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Content::Audio {
+            data: data.into(),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// Creates an embedded resource content item
+    pub fn resource(resource: ResourceContents) -> Self {
+        Content::Resource { resource }
+    }
+
+    /// Creates a resource link content item
+    pub fn resource_link(uri: impl Into<String>, name: impl Into<String>) -> Self {
+        Content::ResourceLink {
+            uri: uri.into(),
+            name: name.into(),
+            description: None,
+            mime_type: None,
+        }
+    }
+}
+
+/// Contents of a resource, either as text or as a base64 blob
+///
+/// Used for embedded resources in tool results.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ResourceContents {
+    /// Textual resource contents
+    Text {
+        /// URI of the resource
+        uri: String,
+        /// MIME type of the resource content
+        #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        /// The resource text
+        text: String,
+    },
+    /// Binary resource contents
+    Blob {
+        /// URI of the resource
+        uri: String,
+        /// MIME type of the resource content
+        #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        /// Base64-encoded resource bytes
+        blob: String,
+    },
 }
 
 /// Represents a resource that can be read
@@ -86,6 +232,250 @@ pub struct McpMessage {
     pub id: Option<u64>,
 }
 
+/// The `error` member of a JSON-RPC 2.0 response
+///
+/// Produced from an `McpError` when a request fails.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonRpcError {
+    /// Numeric error code
+    pub code: i64,
+    /// Short human-readable description
+    pub message: String,
+    /// Additional structured information about the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// A JSON-RPC 2.0 response received from the client
+///
+/// Answers a request the server sent to the client, such as
+/// `sampling/createMessage`. Exactly one of `result` and `error` is set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    /// JSON-RPC protocol version (should be "2.0")
+    pub jsonrpc: String,
+    /// Identifier of the request being answered
+    pub id: Option<u64>,
+    /// Result on success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// Error on failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+/// Name and version of an MCP implementation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Implementation {
+    /// Implementation name
+    pub name: String,
+    /// Implementation version
+    pub version: String,
+}
+
+/// Capabilities a client declares during `initialize`
+///
+/// Each capability is present as an (often empty) object when supported.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ClientCapabilities {
+    /// The client can answer `sampling/createMessage` requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<serde_json::Value>,
+    /// The client can answer `elicitation/create` requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<serde_json::Value>,
+    /// The client can answer `roots/list` requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roots: Option<RootsCapability>,
+    /// Experimental, non-standard capabilities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<serde_json::Value>,
+}
+
+/// Details of the client's `roots` capability
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RootsCapability {
+    /// The client sends `notifications/roots/list_changed`
+    #[serde(rename = "listChanged", default, skip_serializing_if = "Option::is_none")]
+    pub list_changed: Option<bool>,
+}
+
+/// A filesystem root exposed by the client
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Root {
+    /// `file://` URI of the root directory
+    pub uri: String,
+    /// Optional human-readable name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Result of a `roots/list` request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListRootsResult {
+    /// Roots exposed by the client
+    pub roots: Vec<Root>,
+}
+
+/// Capabilities the server declares in its `initialize` result
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ServerCapabilities {
+    /// The server offers tools
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<serde_json::Value>,
+    /// The server offers resources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<serde_json::Value>,
+    /// The server offers prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<serde_json::Value>,
+    /// The server sends log messages and accepts `logging/setLevel`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<serde_json::Value>,
+    /// The server answers `completion/complete`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<serde_json::Value>,
+}
+
+/// Parameters of the `initialize` request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitializeRequest {
+    /// Protocol version the client wants to use
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: String,
+    /// Capabilities supported by the client
+    #[serde(default)]
+    pub capabilities: ClientCapabilities,
+    /// Information about the client
+    #[serde(rename = "clientInfo")]
+    pub client_info: Implementation,
+}
+
+/// Result of the `initialize` request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitializeResult {
+    /// Protocol version the server will speak
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: String,
+    /// Capabilities supported by the server
+    pub capabilities: ServerCapabilities,
+    /// Information about the server
+    #[serde(rename = "serverInfo")]
+    pub server_info: Implementation,
+}
+
+/// Speaker of a message in a sampling conversation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// The human user
+    User,
+    /// The model
+    Assistant,
+}
+
+/// A single message sent to the client's LLM
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SamplingMessage {
+    /// Who is speaking
+    pub role: Role,
+    /// What is said
+    pub content: Content,
+}
+
+/// Parameters of a `sampling/createMessage` request sent to the client
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    /// Conversation to continue
+    pub messages: Vec<SamplingMessage>,
+    /// Upper bound on the number of tokens to generate
+    pub max_tokens: u32,
+    /// Optional system prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Optional model selection preferences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<serde_json::Value>,
+    /// Optional sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Optional sequences that stop generation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+}
+
+impl CreateMessageRequest {
+    /// Creates a request with a single user text message
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The user message
+    /// * `max_tokens` - Upper bound on the number of tokens to generate
+    pub fn user_text(text: impl Into<String>, max_tokens: u32) -> Self {
+        CreateMessageRequest {
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: Content::text(text),
+            }],
+            max_tokens,
+            system_prompt: None,
+            model_preferences: None,
+            temperature: None,
+            stop_sequences: None,
+        }
+    }
+}
+
+/// Result of a `sampling/createMessage` request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    /// Who produced the message (normally the assistant)
+    pub role: Role,
+    /// The generated content
+    pub content: Content,
+    /// Name of the model that generated the message
+    pub model: String,
+    /// Why generation stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+/// Parameters of an `elicitation/create` request sent to the client
+///
+/// Asks the user to fill in a form described by a flat JSON Schema.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ElicitRequest {
+    /// Explanation shown to the user
+    pub message: String,
+    /// Schema of the requested fields (primitive properties only)
+    pub requested_schema: ToolInputSchema,
+}
+
+/// How the user responded to an elicitation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ElicitAction {
+    /// The user submitted the form
+    Accept,
+    /// The user explicitly refused to provide the information
+    Decline,
+    /// The user dismissed the form without choosing
+    Cancel,
+}
+
+/// Result of an `elicitation/create` request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ElicitResult {
+    /// The user's response
+    pub action: ElicitAction,
+    /// Submitted values, present when `action` is `accept`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
 /// Request to call a tool
 ///
 /// Sent as parameters to a `tools/call` RPC method.
@@ -104,6 +494,30 @@ pub struct CallToolRequest {
 pub struct CallToolResult {
     /// Array of content items returned by the tool
     pub content: Vec<Content>,
+    /// Whether the tool itself failed
+    ///
+    /// A failed tool still produces a successful JSON-RPC response, so
+    /// the model can read the error content and adjust its next call.
+    #[serde(rename = "isError", default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    /// Creates a successful result with the given content
+    pub fn success(content: Vec<Content>) -> Self {
+        CallToolResult {
+            content,
+            is_error: false,
+        }
+    }
+
+    /// Creates a failed result carrying a text description of the error
+    pub fn error(message: impl Into<String>) -> Self {
+        CallToolResult {
+            content: vec![Content::text(message)],
+            is_error: true,
+        }
+    }
 }
 
 /// Response for `tools/list` RPC method
@@ -124,6 +538,13 @@ pub struct ReadResourceRequest {
     pub uri: String,
 }
 
+/// Response for `resources/read` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceResult {
+    /// Contents of the resource
+    pub contents: Vec<ResourceContents>,
+}
+
 /// Response for `resources/list` RPC method
 ///
 /// Lists all available resources on the server.
@@ -132,3 +553,176 @@ pub struct ListResourcesResult {
     /// Vector of available resources
     pub resources: Vec<Resource>,
 }
+
+/// A template describing a family of resource URIs
+///
+/// Variables are written in braces, e.g. `file:///{path}`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResourceTemplate {
+    /// RFC 6570 URI template
+    #[serde(rename = "uriTemplate")]
+    pub uri_template: String,
+    /// Short name of the template
+    pub name: String,
+    /// Human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of matching resources, if uniform
+    #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// Response for `resources/templates/list` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListResourceTemplatesResult {
+    /// Vector of available resource templates
+    #[serde(rename = "resourceTemplates")]
+    pub resource_templates: Vec<ResourceTemplate>,
+}
+
+/// Describes an argument a prompt accepts
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptArgument {
+    /// Argument name
+    pub name: String,
+    /// Human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Whether the argument must be provided
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A prompt template offered by the server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Prompt {
+    /// Prompt name/identifier
+    pub name: String,
+    /// Human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arguments used to fill in the prompt
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+}
+
+/// Response for `prompts/list` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListPromptsResult {
+    /// Vector of available prompts
+    pub prompts: Vec<Prompt>,
+}
+
+/// Request to render a prompt
+///
+/// Sent as parameters to a `prompts/get` RPC method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPromptRequest {
+    /// Name of the prompt
+    pub name: String,
+    /// Values for the prompt's arguments
+    #[serde(default)]
+    pub arguments: HashMap<String, String>,
+}
+
+/// A message produced by rendering a prompt
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptMessage {
+    /// Who is speaking
+    pub role: Role,
+    /// What is said
+    pub content: Content,
+}
+
+/// Response for `prompts/get` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPromptResult {
+    /// Description of the rendered prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Rendered messages
+    pub messages: Vec<PromptMessage>,
+}
+
+/// What a completion request is about
+///
+/// `ref/tool` is an extension of this server: it completes tool
+/// arguments the same way prompt arguments are completed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum CompletionReference {
+    /// An argument of a prompt
+    #[serde(rename = "ref/prompt")]
+    Prompt {
+        /// Prompt name
+        name: String,
+    },
+    /// A variable of a resource template
+    #[serde(rename = "ref/resource")]
+    Resource {
+        /// URI template
+        uri: String,
+    },
+    /// An argument of a tool
+    #[serde(rename = "ref/tool")]
+    Tool {
+        /// Tool name
+        name: String,
+    },
+}
+
+/// The argument being completed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompletionArgument {
+    /// Argument or template variable name
+    pub name: String,
+    /// What the user has typed so far
+    pub value: String,
+}
+
+/// Request for `completion/complete` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteRequest {
+    /// Prompt, template or tool the argument belongs to
+    #[serde(rename = "ref")]
+    pub reference: CompletionReference,
+    /// The argument being completed
+    pub argument: CompletionArgument,
+}
+
+/// Completion values for an argument
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    /// Suggested values, at most [`Completion::MAX_VALUES`]
+    pub values: Vec<String>,
+    /// Total number of matching values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// Whether more values exist than were returned
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+impl Completion {
+    /// Maximum number of values returned in one response
+    pub const MAX_VALUES: usize = 100;
+
+    /// Builds a completion from all matching candidates, truncating as needed
+    pub fn from_candidates(mut candidates: Vec<String>) -> Self {
+        let total = candidates.len();
+        candidates.truncate(Self::MAX_VALUES);
+        Completion {
+            values: candidates,
+            total: Some(total),
+            has_more: total > Self::MAX_VALUES,
+        }
+    }
+}
+
+/// Response for `completion/complete` RPC method
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteResult {
+    /// The suggested values
+    pub completion: Completion,
+}
@@ -0,0 +1,161 @@
+//! # Authorization Policy
+//!
+//! Decides which identities may use which tools and resources, according
+//! to the `[policy]` section of the configuration file. Without that
+//! section every client may use everything; with it, a client may only
+//! list and call the tools, and list and read the resources, that some
+//! rule grants to its identity or to one of its roles.
+//!
+//! Clients without an identity, such as those on local transports, are
+//! checked as [`ANONYMOUS_IDENTITY`].
+//!
+//! Every decision on a call or read is logged under the `audit` target.
+
+use crate::auth::Identity;
+use crate::config::{PolicyConfig, PolicyRule};
+use crate::error::McpError;
+use crate::wildcard::WildcardPattern;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Identity name policy rules use for clients that did not authenticate
+pub const ANONYMOUS_IDENTITY: &str = "anonymous";
+
+/// Something a client asks to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action<'a> {
+    /// List or call the named tool
+    CallTool(&'a str),
+    /// List or read the resource with the given URI
+    ReadResource(&'a str),
+}
+
+impl Action<'_> {
+    /// Gets the tool name or resource URI the action targets
+    pub fn target(&self) -> &str {
+        match self {
+            Action::CallTool(name) => name,
+            Action::ReadResource(uri) => uri,
+        }
+    }
+}
+
+impl fmt::Display for Action<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CallTool(name) => write!(f, "调用工具 {}", name),
+            Action::ReadResource(uri) => write!(f, "读取资源 {}", uri),
+        }
+    }
+}
+
+/// The authorization policy loaded from the `[policy]` section
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Compiled rules, `None` when no policy is configured
+    rules: Option<Vec<Rule>>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Identity patterns the rule applies to, including those of its roles
+    identities: Vec<WildcardPattern>,
+    tools: Vec<WildcardPattern>,
+    resources: Vec<WildcardPattern>,
+}
+
+impl Rule {
+    fn compile(rule: &PolicyRule, roles: &HashMap<String, Vec<String>>) -> Self {
+        let members = rule.roles.iter().filter_map(|role| {
+            let members = roles.get(role);
+            if members.is_none() {
+                tracing::warn!("策略规则引用了未定义的角色: {}", role);
+            }
+            members
+        });
+        Rule {
+            identities: rule.identities.iter().chain(members.flatten()).map(|p| WildcardPattern::new(p)).collect(),
+            tools: rule.tools.iter().map(|p| WildcardPattern::new(p)).collect(),
+            resources: rule.resources.iter().map(|p| WildcardPattern::new(p)).collect(),
+        }
+    }
+
+    fn grants(&self, identity: &str, action: &Action<'_>) -> bool {
+        let targets = match action {
+            Action::CallTool(_) => &self.tools,
+            Action::ReadResource(_) => &self.resources,
+        };
+        self.identities.iter().any(|pattern| pattern.matches(identity))
+            && targets.iter().any(|pattern| pattern.matches(action.target()))
+    }
+}
+
+impl Policy {
+    /// Creates a policy that allows everything
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Compiles the `[policy]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The section, or `None` to allow everything
+    pub fn from_config(config: Option<&PolicyConfig>) -> Self {
+        Policy {
+            rules: config.map(|config| {
+                config
+                    .rules
+                    .iter()
+                    .map(|rule| Rule::compile(rule, &config.roles))
+                    .collect()
+            }),
+        }
+    }
+
+    /// Returns whether a policy is configured
+    pub fn is_enabled(&self) -> bool {
+        self.rules.is_some()
+    }
+
+    /// Checks an action without logging, for filtering lists
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Who is asking, `None` for an anonymous client
+    /// * `action` - What they ask to do
+    pub fn permits(&self, identity: Option<&Identity>, action: &Action<'_>) -> bool {
+        let Some(rules) = &self.rules else {
+            return true;
+        };
+        let name = identity.map_or(ANONYMOUS_IDENTITY, |identity| identity.name.as_str());
+        rules.iter().any(|rule| rule.grants(name, action))
+    }
+
+    /// Checks an action and records the decision in the audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Who is asking, `None` for an anonymous client
+    /// * `action` - What they ask to do
+    ///
+    /// # Returns
+    ///
+    /// `McpError::AccessDenied` if no rule grants the action
+    pub fn authorize(&self, identity: Option<&Identity>, action: &Action<'_>) -> Result<(), McpError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let name = identity.map_or(ANONYMOUS_IDENTITY, |identity| identity.name.as_str());
+        if self.permits(identity, action) {
+            tracing::info!(target: "audit", identity = name, "策略允许{}", action);
+            Ok(())
+        } else {
+            tracing::warn!(target: "audit", identity = name, "策略拒绝{}", action);
+            Err(McpError::AccessDenied {
+                target: action.target().to_string(),
+                reason: format!("身份 {} 无权{}", name, action),
+            })
+        }
+    }
+}
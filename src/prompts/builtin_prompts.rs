@@ -0,0 +1,67 @@
+//! # Built-in Prompts
+//!
+//! Contains implementations of default prompts provided by the MCP Server.
+//! New prompts should be added here and registered in PromptRegistry.
+
+use crate::error::McpError;
+use crate::models::{Content, GetPromptResult, PromptArgument, PromptMessage, Role};
+use crate::tools::builtin_tools::complete_city;
+use std::collections::HashMap;
+
+/// Weather report prompt implementation
+///
+/// Asks the LLM to write a short weather report for a city.
+#[derive(Clone, Copy)]
+pub struct WeatherReportPrompt;
+
+impl WeatherReportPrompt {
+    /// Gets the arguments this prompt accepts
+    pub fn arguments(&self) -> Vec<PromptArgument> {
+        vec![PromptArgument {
+            name: "city".to_string(),
+            description: Some("城市名称".to_string()),
+            required: true,
+        }]
+    }
+
+    /// Renders the prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `arguments` - Argument values, must contain `city`
+    ///
+    /// # Returns
+    ///
+    /// Result containing the rendered messages, or `McpError::InvalidParams`
+    /// if city is missing
+    pub fn get(&self, arguments: &HashMap<String, String>) -> Result<GetPromptResult, McpError> {
+        let city = arguments
+            .get("city")
+            .filter(|city| !city.trim().is_empty())
+            .ok_or_else(|| McpError::invalid_params("缺少 city 参数"))?;
+
+        Ok(GetPromptResult {
+            description: Some(format!("{} 的天气播报", city)),
+            messages: vec![PromptMessage {
+                role: Role::User,
+                content: Content::text(format!(
+                    "请查询 {} 的天气，并写一段简短的天气播报。",
+                    city
+                )),
+            }],
+        })
+    }
+
+    /// Suggests values for an argument of this prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - Name of the argument being completed
+    /// * `value` - What the user has typed so far
+    pub fn complete(&self, argument: &str, value: &str) -> Vec<String> {
+        match argument {
+            "city" => complete_city(value),
+            _ => Vec::new(),
+        }
+    }
+}
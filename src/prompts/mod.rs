@@ -0,0 +1,12 @@
+//! # Prompts Module
+//!
+//! Provides prompt management and built-in prompt implementations.
+//!
+//! Prompts are message templates a client can fill in and hand to its
+//! LLM. New prompts should be added to the `builtin_prompts` module and
+//! registered in the `PromptRegistry`.
+
+pub mod prompt_handler;
+pub mod builtin_prompts;
+
+pub use prompt_handler::PromptRegistry;
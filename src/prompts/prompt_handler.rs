@@ -0,0 +1,117 @@
+//! # Prompt Handler Module
+//!
+//! Manages prompt registration and rendering using the same enum-based
+//! dispatch as tools.
+
+use crate::error::McpError;
+use crate::models::{GetPromptResult, Prompt, PromptArgument};
+use super::builtin_prompts::WeatherReportPrompt;
+use std::collections::HashMap;
+
+/// Enumeration of all available prompt implementations
+#[derive(Clone)]
+pub enum PromptImpl {
+    /// Weather report prompt
+    WeatherReport(WeatherReportPrompt),
+}
+
+impl PromptImpl {
+    /// Gets the name of this prompt
+    pub fn name(&self) -> &str {
+        match self {
+            PromptImpl::WeatherReport(_) => "weather_report",
+        }
+    }
+
+    /// Gets the human-readable description of this prompt
+    pub fn description(&self) -> &str {
+        match self {
+            PromptImpl::WeatherReport(_) => "生成城市天气播报",
+        }
+    }
+
+    /// Gets the arguments this prompt accepts
+    pub fn arguments(&self) -> Vec<PromptArgument> {
+        match self {
+            PromptImpl::WeatherReport(prompt) => prompt.arguments(),
+        }
+    }
+
+    /// Renders this prompt with the given arguments
+    ///
+    /// # Arguments
+    ///
+    /// * `arguments` - Argument values keyed by name
+    ///
+    /// # Returns
+    ///
+    /// Result containing the rendered messages or `McpError::InvalidParams`
+    pub fn get(&self, arguments: &HashMap<String, String>) -> Result<GetPromptResult, McpError> {
+        match self {
+            PromptImpl::WeatherReport(prompt) => prompt.get(arguments),
+        }
+    }
+
+    /// Suggests values for an argument of this prompt
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - Name of the argument being completed
+    /// * `value` - What the user has typed so far
+    pub fn complete(&self, argument: &str, value: &str) -> Vec<String> {
+        match self {
+            PromptImpl::WeatherReport(prompt) => prompt.complete(argument, value),
+        }
+    }
+}
+
+/// Registry for managing all available prompts
+#[derive(Clone)]
+pub struct PromptRegistry {
+    /// Map of prompt names to prompt implementations
+    prompts: HashMap<String, PromptImpl>,
+}
+
+impl PromptRegistry {
+    /// Creates a new prompt registry with all built-in prompts
+    ///
+    /// # Returns
+    ///
+    /// A new `PromptRegistry` with default prompts registered
+    pub fn new() -> Self {
+        let mut prompts = HashMap::new();
+        prompts.insert(
+            "weather_report".to_string(),
+            PromptImpl::WeatherReport(WeatherReportPrompt),
+        );
+
+        PromptRegistry { prompts }
+    }
+
+    /// Gets a prompt by name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the prompt to retrieve
+    pub fn get(&self, name: &str) -> Option<&PromptImpl> {
+        self.prompts.get(name)
+    }
+
+    /// Gets a list of all available prompts
+    pub fn list_prompts(&self) -> Vec<Prompt> {
+        self.prompts
+            .values()
+            .map(|prompt| Prompt {
+                name: prompt.name().to_string(),
+                description: Some(prompt.description().to_string()),
+                arguments: prompt.arguments(),
+            })
+            .collect()
+    }
+}
+
+impl Default for PromptRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,108 @@
+//! # Rate Limits
+//!
+//! Token-bucket rate limiting of requests, applied server-wide and per
+//! tool. Every caller gets its own bucket, keyed by [`caller_key`]: the
+//! bucket holds up to `burst` tokens and refills at the configured rate,
+//! and each request takes one token. A request that finds the bucket
+//! empty is refused, and told how long until a token is available.
+
+use crate::config::RateLimitConfig;
+use crate::session::Session;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Number of buckets above which full, and so idle, buckets are dropped
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Gets the key a caller's requests are counted under
+///
+/// Authenticated clients are counted by identity, so reconnecting does
+/// not reset their budget. Other network clients are counted by IP
+/// address, and local clients by session.
+pub fn caller_key(session: &Session) -> String {
+    match (session.identity(), session.peer()) {
+        (Some(identity), _) => format!("identity:{}", identity),
+        (None, Some(ip)) => format!("ip:{}", ip),
+        (None, None) => format!("session:{}", session.id()),
+    }
+}
+
+/// Token buckets for one rate limit, keyed by caller
+///
+/// Cheap to clone; all clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// Tokens added to a bucket per second
+    per_second: f64,
+    /// Most tokens a bucket holds
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter from a rate limit section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Requests allowed per minute and the burst size
+    ///
+    /// # Returns
+    ///
+    /// `None` if the rate is 0, which disables the limit
+    pub fn new(config: &RateLimitConfig) -> Option<Self> {
+        if config.per_minute == 0 {
+            return None;
+        }
+        let burst = config.burst.unwrap_or(config.per_minute).max(1);
+        Some(RateLimiter {
+            per_second: f64::from(config.per_minute) / 60.0,
+            burst: f64::from(burst),
+            buckets: Arc::default(),
+        })
+    }
+
+    /// Takes a token from a caller's bucket
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The caller, as given by [`caller_key`]
+    ///
+    /// # Returns
+    ///
+    /// `Err` with the time until a token is available, rounded up to whole
+    /// milliseconds, if the bucket is empty
+    pub fn try_acquire(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait_ms = (1.0 - bucket.tokens) / self.per_second * 1000.0;
+            Err(Duration::from_millis(wait_ms.ceil() as u64))
+        }
+    }
+
+    /// Gets the tokens a bucket holds at `now`
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+}
@@ -0,0 +1,150 @@
+//! # MIME Detection
+//!
+//! Determines the MIME type of a resource from its file extension and,
+//! when that is inconclusive, from the leading bytes of its content.
+
+use std::path::Path;
+
+/// Fallback MIME type for content that cannot be identified
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Number of leading bytes needed by [`sniff`]
+///
+/// Large enough to reach the `ustar` magic of a tar header at offset 257.
+pub const SNIFF_LEN: usize = 512;
+
+/// Guesses a MIME type from a path's extension
+///
+/// # Arguments
+///
+/// * `path` - The file path to inspect
+///
+/// # Returns
+///
+/// The MIME type if the extension is known, otherwise `None`
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mime = match ext.as_str() {
+        "txt" | "log" | "conf" | "cfg" | "ini" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "js" | "mjs" => "text/javascript",
+        "rs" => "text/x-rust",
+        "py" => "text/x-python",
+        "sh" => "application/x-sh",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "ico" => "image/vnd.microsoft.icon",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "wasm" => "application/wasm",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// Guesses a MIME type from the leading bytes of some content
+///
+/// Recognizes common binary signatures. Content without a known
+/// signature is reported as `text/plain` if it is valid UTF-8 without
+/// NUL bytes, and is otherwise unidentified.
+///
+/// # Arguments
+///
+/// * `head` - Up to [`SNIFF_LEN`] leading bytes of the content
+///
+/// # Returns
+///
+/// The detected MIME type, or `None` if the content is unrecognized
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\0asm", "application/wasm"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"OggS", "audio/ogg"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") {
+        match &head[8..12] {
+            b"WAVE" => return Some("audio/wav"),
+            b"WEBP" => return Some("image/webp"),
+            _ => {}
+        }
+    }
+    if head.len() >= 262 && &head[257..262] == b"ustar" {
+        return Some("application/x-tar");
+    }
+    if looks_like_text(head) {
+        return Some("text/plain");
+    }
+    None
+}
+
+/// Determines the MIME type of a file from its path and leading bytes
+///
+/// The extension wins when it is known; otherwise the content is sniffed.
+///
+/// # Arguments
+///
+/// * `path` - The file path
+/// * `head` - Up to [`SNIFF_LEN`] leading bytes of the file
+pub fn detect(path: &Path, head: &[u8]) -> &'static str {
+    from_extension(path)
+        .or_else(|| sniff(head))
+        .unwrap_or(OCTET_STREAM)
+}
+
+/// Returns whether content of this MIME type should be returned as text
+pub fn is_text(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/json"
+                | "application/xml"
+                | "application/toml"
+                | "application/yaml"
+                | "application/x-sh"
+                | "image/svg+xml"
+        )
+}
+
+/// Heuristically checks whether a byte prefix is UTF-8 text
+///
+/// A multi-byte character cut off at the end of the prefix is tolerated.
+fn looks_like_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
@@ -5,7 +5,8 @@
 //! Resources are files or data sources that can be read via the server.
 //! New resources should be added to the `ResourceRegistry`.
 
+pub mod mime;
 pub mod resource_handler;
 
-pub use resource_handler::ResourceRegistry;
+pub use resource_handler::{BlobReader, ResourceRead, ResourceRegistry};
 
@@ -3,16 +3,88 @@
 //! Manages resource registration and access.
 //! Resources are files or data sources that can be read via the server.
 
-use crate::models::{Content, Resource};
-use anyhow::Result;
+use super::mime;
+use crate::error::McpError;
+use crate::models::{Resource, ResourceContents, ResourceTemplate};
+use crate::roots::{file_uri_to_path, is_within_roots, path_to_file_uri};
+use crate::sandbox::{PathKind, PathSandbox};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::transport::StreamedMessage;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// URI template matching the registered `file://` resources
+pub const FILE_TEMPLATE: &str = "file:///{path}";
+
+/// Where the content of a registered resource comes from
+#[derive(Clone)]
+enum ResourceSource {
+    /// Fixed text held in memory
+    Static(&'static str),
+    /// A file on the local filesystem
+    File(PathBuf),
+}
+
+/// A resource opened for reading
+pub enum ResourceRead {
+    /// Content read into memory
+    Contents(Vec<ResourceContents>),
+    /// A binary file, to be base64-encoded as it is sent
+    Blob(BlobReader),
+}
+
+/// A binary file-backed resource that has been opened but not read
+pub struct BlobReader {
+    /// URI of the resource
+    pub uri: String,
+    /// MIME type detected for the file
+    pub mime_type: String,
+    /// Number of bytes to read
+    pub size: u64,
+    /// The file, starting with the bytes already read to sniff its type
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+}
+
+impl BlobReader {
+    /// Gets the length of the base64 encoding of the file, in bytes
+    pub fn encoded_len(&self) -> usize {
+        (self.size as usize).div_ceil(3) * 4
+    }
+
+    /// Turns the file into the body of a message
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - Text sent before the encoded file
+    /// * `tail` - Text sent after it
+    pub fn into_message(self, head: String, tail: String) -> StreamedMessage {
+        StreamedMessage::new(head, self.reader, self.size, tail)
+    }
+}
+
+/// A registered resource together with its content source
+#[derive(Clone)]
+struct ResourceEntry {
+    resource: Resource,
+    source: ResourceSource,
+}
 
 /// Registry for managing all available resources
 ///
 /// Provides centralized access to resource metadata and content.
+#[derive(Clone)]
 pub struct ResourceRegistry {
-    /// Map of resource URIs to resource metadata
-    resources: HashMap<String, Resource>,
+    /// Map of resource URIs to resource entries
+    resources: HashMap<String, ResourceEntry>,
+    /// Confines which registered files may be listed and read
+    sandbox: Arc<PathSandbox>,
+    /// Largest file that may be read into memory, in bytes
+    max_resource_size: u64,
 }
 
 impl ResourceRegistry {
@@ -27,13 +99,89 @@ impl ResourceRegistry {
         // Initialize with default resources
         resources.insert(
             "file:///etc/hosts".to_string(),
-            Resource {
-                uri: "file:///etc/hosts".to_string(),
-                mime_type: "text/plain".to_string(),
+            ResourceEntry {
+                resource: Resource {
+                    uri: "file:///etc/hosts".to_string(),
+                    mime_type: "text/plain".to_string(),
+                },
+                source: ResourceSource::Static("127.0.0.1 localhost\n::1 localhost\n"),
             },
         );
 
-        ResourceRegistry { resources }
+        ResourceRegistry {
+            resources,
+            sandbox: Arc::default(),
+            max_resource_size: u64::MAX,
+        }
+    }
+
+    /// Confines file-backed resources with the given sandbox
+    ///
+    /// Files the sandbox refuses stay registered but are left out of
+    /// listings and cannot be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `sandbox` - Path rules from the `[sandbox]` section
+    pub fn with_sandbox(mut self, sandbox: Arc<PathSandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Refuses to read files larger than the given size into memory
+    ///
+    /// Applies to text files, and to binary files read with
+    /// [`ResourceRegistry::read_resource`]. Binary files sent through
+    /// [`ResourceRegistry::open_resource`] are never held in memory and
+    /// are not limited.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Largest file that may be read, in bytes; 0 for no limit
+    pub fn with_max_resource_size(mut self, limit: u64) -> Self {
+        self.max_resource_size = match limit {
+            0 => u64::MAX,
+            limit => limit,
+        };
+        self
+    }
+
+    /// Registers a file on the local filesystem as a resource
+    ///
+    /// The file is exposed under a `file://` URI built from its absolute
+    /// path. Its MIME type is detected from the extension, falling back
+    /// to sniffing the first bytes of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file to expose
+    ///
+    /// # Returns
+    ///
+    /// Result containing the registered resource metadata, or an error
+    /// if the path cannot be resolved
+    pub fn register_file(&mut self, path: impl AsRef<Path>) -> Result<Resource> {
+        let path = std::path::absolute(path.as_ref())
+            .with_context(|| format!("无法解析路径 {}", path.as_ref().display()))?;
+        let mut head = Vec::with_capacity(mime::SNIFF_LEN);
+        if let Ok(file) = std::fs::File::open(&path) {
+            file.take(mime::SNIFF_LEN as u64).read_to_end(&mut head)?;
+        }
+
+        let uri = path_to_file_uri(&path);
+        let resource = Resource {
+            uri: uri.clone(),
+            mime_type: mime::detect(&path, &head).to_string(),
+        };
+        self.resources.insert(
+            uri,
+            ResourceEntry {
+                resource: resource.clone(),
+                source: ResourceSource::File(path),
+            },
+        );
+
+        Ok(resource)
     }
 
     /// Gets resource metadata by URI
@@ -47,21 +195,97 @@ impl ResourceRegistry {
     /// Option containing resource metadata if found
     #[allow(dead_code)]
     pub fn get_uri(&self, uri: &str) -> Option<&Resource> {
-        self.resources.get(uri)
+        self.resources.get(uri).map(|entry| &entry.resource)
     }
 
     /// Gets a list of all available resources
     ///
+    /// File-backed resources the sandbox refuses are left out.
+    ///
     /// # Returns
     ///
     /// Vector of Resource definitions
     pub fn list_resources(&self) -> Vec<Resource> {
-        self.resources.values().cloned().collect()
+        self.resources
+            .values()
+            .filter(|entry| match &entry.source {
+                ResourceSource::File(path) => self.sandbox.permits(path),
+                ResourceSource::Static(_) => true,
+            })
+            .map(|entry| entry.resource.clone())
+            .collect()
+    }
+
+    /// Gets a list of all resource templates
+    ///
+    /// # Returns
+    ///
+    /// Vector of ResourceTemplate definitions
+    pub fn list_templates(&self) -> Vec<ResourceTemplate> {
+        vec![ResourceTemplate {
+            uri_template: FILE_TEMPLATE.to_string(),
+            name: "files".to_string(),
+            description: Some("已注册的本地文件".to_string()),
+            mime_type: None,
+        }]
+    }
+
+    /// Suggests values for a variable of a resource template
+    ///
+    /// Completes the `path` variable of [`FILE_TEMPLATE`] with the paths of
+    /// registered `file://` resources the sandbox permits.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri_template` - The template the variable belongs to
+    /// * `variable` - Name of the variable being completed
+    /// * `value` - What the user has typed so far
+    /// * `roots` - If given, only files inside these directories are suggested
+    ///
+    /// # Returns
+    ///
+    /// Result containing the matching values, sorted, or
+    /// `McpError::ResourceNotFound` for unknown templates
+    pub fn complete_template(
+        &self,
+        uri_template: &str,
+        variable: &str,
+        value: &str,
+        roots: Option<&[PathBuf]>,
+    ) -> Result<Vec<String>, McpError> {
+        if uri_template != FILE_TEMPLATE {
+            return Err(McpError::ResourceNotFound {
+                uri: uri_template.to_string(),
+            });
+        }
+        if variable != "path" {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates: Vec<String> = self
+            .resources
+            .keys()
+            .filter_map(|uri| file_uri_to_path(uri))
+            .filter(|path| self.sandbox.permits(path))
+            .filter(|path| roots.is_none_or(|roots| is_within_roots(path, roots)))
+            .filter_map(|path| {
+                let path = path.to_string_lossy();
+                path.strip_prefix('/').map(str::to_string)
+            })
+            .filter(|path| path.starts_with(value))
+            .collect();
+        candidates.sort();
+        Ok(candidates)
     }
 
-    /// Reads the content of a resource
+    /// Reads the whole content of a resource into memory
     ///
-    /// Asynchronously fetches and returns the content of a resource.
+    /// Textual content is returned as `text` and binary content as a
+    /// base64 `blob`. The content is held in memory in full (a blob takes
+    /// 4/3 of the file size), so files larger than the maximum resource
+    /// size are refused before they are read. See
+    /// [`ResourceRegistry::open_resource`] to send binary files without
+    /// holding them in memory.
     ///
     /// # Arguments
     ///
@@ -69,27 +293,164 @@ impl ResourceRegistry {
     ///
     /// # Returns
     ///
-    /// Result containing vector of Content items or error if resource not found
+    /// Result containing the resource contents, `McpError::ResourceNotFound`
+    /// for unknown URIs, `McpError::AccessDenied` for files the sandbox
+    /// refuses, `McpError::ResourceTooLarge` for files over the maximum
+    /// size or `McpError::ResourceRead` if reading fails
     ///
     /// # Example
     ///
     /// ```ignore
     /// let registry = ResourceRegistry::new();
-    /// let content = registry.read_resource("file:///etc/hosts").await?;
+    /// let contents = registry.read_resource("file:///etc/hosts").await?;
     /// ```
-    pub async fn read_resource(&self, uri: &str) -> Result<Vec<Content>> {
-        match uri {
-            "file:///etc/hosts" => {
-                let text = "127.0.0.1 localhost\n::1 localhost\n".to_string();
-                Ok(vec![Content {
-                    type_: "text".to_string(),
-                    text,
+    pub async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>, McpError> {
+        match self.open_resource(uri).await? {
+            ResourceRead::Contents(contents) => Ok(contents),
+            ResourceRead::Blob(blob) => {
+                self.check_size(uri, blob.size)?;
+                let (uri, mime_type) = (blob.uri.clone(), blob.mime_type.clone());
+                let blob = blob
+                    .into_message(String::new(), String::new())
+                    .into_string()
+                    .await
+                    .map_err(|e| McpError::ResourceRead {
+                        uri: uri.clone(),
+                        source: e.into(),
+                    })?;
+                Ok(vec![ResourceContents::Blob {
+                    uri,
+                    mime_type: Some(mime_type),
+                    blob,
                 }])
             }
-            _ => Err(anyhow::anyhow!("Resource not found")),
         }
     }
 
+    /// Opens a resource for reading
+    ///
+    /// Fixed and textual content is read into memory, so text files larger
+    /// than the maximum resource size are refused. Binary files are only
+    /// opened, and are read as they are sent, so they are not limited.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The URI of the resource to open
+    ///
+    /// # Returns
+    ///
+    /// Result containing the contents or the opened binary file, with the
+    /// same errors as [`ResourceRegistry::read_resource`]
+    pub async fn open_resource(&self, uri: &str) -> Result<ResourceRead, McpError> {
+        let entry = self
+            .resources
+            .get(uri)
+            .ok_or_else(|| McpError::ResourceNotFound {
+                uri: uri.to_string(),
+            })?;
+
+        let path = match &entry.source {
+            ResourceSource::Static(text) => {
+                return Ok(ResourceRead::Contents(vec![ResourceContents::Text {
+                    uri: uri.to_string(),
+                    mime_type: Some(entry.resource.mime_type.clone()),
+                    text: text.to_string(),
+                }]));
+            }
+            ResourceSource::File(path) => path,
+        };
+        let path = self.sandbox.check(path, PathKind::File).map_err(|e| {
+            if e.is_violation() {
+                McpError::AccessDenied {
+                    target: uri.to_string(),
+                    reason: e.to_string(),
+                }
+            } else {
+                McpError::ResourceRead {
+                    uri: uri.to_string(),
+                    source: e.into(),
+                }
+            }
+        })?;
+        self.open_file(uri, &path).await
+    }
+
+    /// Opens a file-backed resource
+    ///
+    /// Sniffs the leading bytes to pick a MIME type. If that type is
+    /// textual, reads the file and returns it as text if it is valid UTF-8
+    /// and as a base64 blob otherwise; if not, returns the file unread.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - URI of the resource
+    /// * `path` - Canonical path of the file
+    async fn open_file(&self, uri: &str, path: &Path) -> Result<ResourceRead, McpError> {
+        let read_error = |source| McpError::ResourceRead {
+            uri: uri.to_string(),
+            source,
+        };
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("无法打开文件 {}", path.display()))
+            .map_err(read_error)?;
+        let size = file
+            .metadata()
+            .await
+            .with_context(|| format!("无法读取文件信息 {}", path.display()))
+            .map_err(read_error)?
+            .len();
+        // At most `size` bytes are read, even if the file grows meanwhile
+        let mut file = file.take(size);
+
+        let mut head = Vec::with_capacity(mime::SNIFF_LEN);
+        (&mut file)
+            .take(mime::SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(|e| read_error(e.into()))?;
+        let mime_type = mime::detect(path, &head).to_string();
+
+        if !mime::is_text(&mime_type) {
+            return Ok(ResourceRead::Blob(BlobReader {
+                uri: uri.to_string(),
+                mime_type,
+                size,
+                reader: Box::new(AsyncReadExt::chain(std::io::Cursor::new(head), file)),
+            }));
+        }
+
+        // Text must be valid UTF-8 as a whole, so it is read into memory
+        self.check_size(uri, size)?;
+        let mut bytes = head;
+        file.read_to_end(&mut bytes).await.map_err(|e| read_error(e.into()))?;
+        let contents = match String::from_utf8(bytes) {
+            Ok(text) => ResourceContents::Text {
+                uri: uri.to_string(),
+                mime_type: Some(mime_type),
+                text,
+            },
+            Err(e) => ResourceContents::Blob {
+                uri: uri.to_string(),
+                mime_type: Some(mime_type),
+                blob: STANDARD.encode(e.into_bytes()),
+            },
+        };
+        Ok(ResourceRead::Contents(vec![contents]))
+    }
+
+    /// Refuses content too large to be held in memory
+    fn check_size(&self, uri: &str, size: u64) -> Result<(), McpError> {
+        if size > self.max_resource_size {
+            return Err(McpError::ResourceTooLarge {
+                uri: uri.to_string(),
+                size,
+                limit: self.max_resource_size,
+            });
+        }
+        Ok(())
+    }
+
     /// Gets all resource URIs
     ///
     /// # Returns
@@ -106,13 +467,3 @@ impl Default for ResourceRegistry {
         Self::new()
     }
 }
-
-impl Clone for ResourceRegistry {
-    /// Creates a clone of the resource registry
-    ///
-    /// This is used to pass resource registry to spawned async tasks.
-    fn clone(&self) -> Self {
-        ResourceRegistry::new()
-    }
-}
-
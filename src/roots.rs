@@ -0,0 +1,99 @@
+//! # Roots
+//!
+//! Helpers for working with the filesystem roots a client exposes through
+//! `roots/list`. Roots are `file://` URIs; file-oriented tools and
+//! resources must stay inside them.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Converts a `file://` URI into a local path
+///
+/// Percent-encoded bytes are decoded. Only URIs with an empty or
+/// `localhost` authority are accepted.
+///
+/// # Arguments
+///
+/// * `uri` - The URI to convert
+///
+/// # Returns
+///
+/// The path, or `None` if the URI is not a local `file://` URI
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(percent_decode(path)?))
+}
+
+/// Converts a local path into a `file://` URI
+///
+/// # Arguments
+///
+/// * `path` - An absolute path
+pub fn path_to_file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Checks whether a path lies inside one of the given roots
+///
+/// Both sides are normalized lexically (`.` and `..` are resolved
+/// without touching the filesystem) and, when they exist, canonicalized
+/// so that symlinks cannot be used to step outside a root.
+///
+/// # Arguments
+///
+/// * `path` - The path to check
+/// * `roots` - Root directories the path must be under
+pub fn is_within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    let path = resolve(path);
+    roots.iter().any(|root| path.starts_with(resolve(root)))
+}
+
+/// Canonicalizes a path if it exists, otherwise normalizes it lexically
+fn resolve(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| normalize(path))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Decodes `%XX` escapes in a URI path
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
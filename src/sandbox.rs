@@ -0,0 +1,207 @@
+//! # Path Sandbox
+//!
+//! Confines every filesystem access made on behalf of a client, by tools
+//! such as `search_files` and by `file://` resources, according to the
+//! `[sandbox]` section of the configuration file:
+//!
+//! - paths are canonicalized, so `..` and symlinks cannot lead elsewhere;
+//! - the canonical path must lie inside one of the allowed roots, if any
+//!   are configured;
+//! - it must not match any deny pattern, such as `**/.ssh/**`;
+//! - it must be a regular file or directory, never a device, FIFO or socket.
+//!
+//! These checks apply on top of the roots a client exposes, which are
+//! enforced separately (see [`crate::roots`]).
+
+use crate::config::SandboxConfig;
+use crate::roots::normalize;
+use crate::wildcard::WildcardPattern;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Deny patterns used when the configuration does not list its own
+pub const DEFAULT_DENY: &[&str] = &["**/.ssh/**", "**/.gnupg/**", "**/*.pem", "**/*.key", "/etc/shadow"];
+
+/// What a path is expected to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    /// A regular file
+    File,
+    /// A directory
+    Directory,
+}
+
+impl fmt::Display for PathKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathKind::File => f.write_str("文件"),
+            PathKind::Directory => f.write_str("目录"),
+        }
+    }
+}
+
+/// Why the sandbox refused a path
+#[derive(Debug, Error)]
+pub enum SandboxError {
+    /// The path does not exist or cannot be inspected
+    #[error("无法访问路径 {path}: {source}")]
+    Inaccessible {
+        /// The path as given
+        path: PathBuf,
+        /// Underlying cause
+        source: io::Error,
+    },
+    /// The path lies outside every allowed root
+    #[error("路径 {0} 不在允许的根目录内")]
+    OutsideRoots(PathBuf),
+    /// The path matches a deny pattern
+    #[error("路径 {path} 被规则 {pattern} 禁止访问")]
+    Denied {
+        /// The canonical path
+        path: PathBuf,
+        /// The deny pattern it matched
+        pattern: String,
+    },
+    /// The path is a device, FIFO, socket or similar
+    #[error("{0} 不是普通文件或目录")]
+    SpecialFile(PathBuf),
+    /// The path exists but is not of the expected kind
+    #[error("{path} 不是{expected}")]
+    WrongKind {
+        /// The canonical path
+        path: PathBuf,
+        /// What the path was expected to be
+        expected: PathKind,
+    },
+}
+
+impl SandboxError {
+    /// Returns whether the path was refused by the sandbox rules, as
+    /// opposed to not being accessible at all
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, SandboxError::Inaccessible { .. })
+    }
+}
+
+/// The path rules loaded from the `[sandbox]` section
+#[derive(Debug, Clone)]
+pub struct PathSandbox {
+    /// Canonical root directories, empty to allow any directory
+    roots: Vec<PathBuf>,
+    /// Deny patterns with their source text, for error messages
+    deny: Vec<(String, WildcardPattern)>,
+}
+
+impl Default for PathSandbox {
+    fn default() -> Self {
+        Self::from_config(&SandboxConfig::default())
+    }
+}
+
+impl PathSandbox {
+    /// Compiles the `[sandbox]` section
+    ///
+    /// Roots are canonicalized once here; a root that does not exist is
+    /// kept as written, so nothing can be reached through it until it does.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Allowed roots and deny patterns
+    pub fn from_config(config: &SandboxConfig) -> Self {
+        let roots = config
+            .roots
+            .iter()
+            .map(|root| {
+                std::fs::canonicalize(root).unwrap_or_else(|e| {
+                    tracing::warn!("沙箱根目录 {} 无法解析: {}", root.display(), e);
+                    normalize(root)
+                })
+            })
+            .collect();
+        let deny = config
+            .deny
+            .iter()
+            .map(|pattern| (pattern.clone(), WildcardPattern::new(pattern)))
+            .collect();
+        PathSandbox { roots, deny }
+    }
+
+    /// Gets the canonical allowed roots, empty if any directory is allowed
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolves a path and checks it against every rule
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path a client asked for
+    /// * `kind` - Whether it must be a file or a directory
+    ///
+    /// # Returns
+    ///
+    /// The canonical path, which is what should be accessed from then on,
+    /// or the reason it was refused
+    pub fn check(&self, path: &Path, kind: PathKind) -> Result<PathBuf, SandboxError> {
+        let inaccessible = |source| SandboxError::Inaccessible {
+            path: path.to_path_buf(),
+            source,
+        };
+        let canonical = std::fs::canonicalize(path).map_err(inaccessible)?;
+        self.check_rules(&canonical)?;
+
+        let file_type = std::fs::metadata(&canonical).map_err(inaccessible)?.file_type();
+        if !file_type.is_file() && !file_type.is_dir() {
+            return Err(SandboxError::SpecialFile(canonical));
+        }
+        if file_type.is_dir() != (kind == PathKind::Directory) {
+            return Err(SandboxError::WrongKind {
+                path: canonical,
+                expected: kind,
+            });
+        }
+        Ok(canonical)
+    }
+
+    /// Checks a path against the roots and deny patterns only
+    ///
+    /// Unlike [`check`](Self::check) this does not require the path to
+    /// exist: it is canonicalized if possible and normalized lexically
+    /// otherwise. Used to filter listings.
+    pub fn permits(&self, path: &Path) -> bool {
+        let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| normalize(path));
+        self.check_rules(&resolved).is_ok()
+    }
+
+    /// Checks whether a path matches a deny pattern
+    ///
+    /// A directory is also tested with a trailing `/`, so that `**/.ssh/**`
+    /// covers the `.ssh` directory itself.
+    pub fn is_denied(&self, path: &Path) -> bool {
+        self.denied_by(path).is_some()
+    }
+
+    fn check_rules(&self, canonical: &Path) -> Result<(), SandboxError> {
+        if !self.roots.is_empty() && !self.roots.iter().any(|root| canonical.starts_with(root)) {
+            return Err(SandboxError::OutsideRoots(canonical.to_path_buf()));
+        }
+        if let Some(pattern) = self.denied_by(canonical) {
+            return Err(SandboxError::Denied {
+                path: canonical.to_path_buf(),
+                pattern: pattern.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn denied_by(&self, path: &Path) -> Option<&str> {
+        let path = path.to_string_lossy();
+        let as_dir = format!("{}/", path.trim_end_matches('/'));
+        self.deny
+            .iter()
+            .find(|(_, pattern)| pattern.matches(&path) || pattern.matches(&as_dir))
+            .map(|(text, _)| text.as_str())
+    }
+}
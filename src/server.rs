@@ -1,24 +1,159 @@
 //! # MCP Server Core
 //!
-//! Implements the TCP server and JSON-RPC 2.0 protocol handling.
-//! Manages client connections and dispatches requests to tools and resources.
+//! Implements the TCP, Unix domain socket and WebSocket listeners and
+//! JSON-RPC 2.0 protocol handling. Manages client connections and dispatches requests
+//! to tools and resources.
 
+use crate::audit::AuditLog;
+use crate::auth::{AUTHENTICATE_METHOD, AuthenticateRequest, AuthenticateResult, Authenticator, Identity, StaticTokens};
+use crate::config::ServerConfig;
+use crate::error::McpError;
+use crate::limits::ConnectionLimiter;
+use crate::logging::{LogRouter, McpLogLayer, SetLevelRequest};
+use crate::metrics::{self, METRICS_PATH, Metrics, UNKNOWN_LABEL};
 use crate::models::*;
-use crate::tools::ToolRegistry;
-use crate::resources::ResourceRegistry;
-use anyhow::Result;
+use crate::policy::{ANONYMOUS_IDENTITY, Action, Policy};
+use crate::session::{RequestContext, Session};
+use crate::shutdown::{SHUTDOWN_NOTIFICATION, ShutdownHandle};
+use crate::telemetry::{ERROR_FIELD, TRACEPARENT_FIELD};
+use crate::tools::{ToolError, ToolRegistry};
+use crate::prompts::PromptRegistry;
+use crate::rate_limit::{RateLimiter, caller_key};
+use crate::resources::{BlobReader, ResourceRead, ResourceRegistry};
+use crate::roots::{file_uri_to_path, is_within_roots};
+use crate::sandbox::PathSandbox;
+use crate::tls;
+use crate::transport::{
+    LineTransport, MessageReader, MessageTooLarge, MessageWriter, StreamedMessage, Transport, WebSocketTransport,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinSet};
+use tokio::time::Instant;
+use tracing::Instrument;
+
+/// Protocol version the server prefers
+pub const LATEST_PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Protocol versions the server can speak
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+/// Request methods the server handles
+///
+/// Any other method is answered with `McpError::MethodNotFound`, and is
+/// recorded in metrics under [`UNKNOWN_LABEL`].
+pub const METHODS: &[&str] = &[
+    "initialize",
+    "ping",
+    "logging/setLevel",
+    "tools/list",
+    "tools/call",
+    "resources/list",
+    "resources/read",
+    "resources/templates/list",
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+];
+
+/// Where the server listens for connections
+///
+/// Parsed from `unix:<path>` for a Unix domain socket, `ws:<addr>` for
+/// WebSocket, `stdio` for standard input/output, or `tcp:<addr>` / a bare
+/// `<host>:<port>` for TCP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// A TCP address such as `127.0.0.1:8080`
+    Tcp(String),
+    /// Path of a Unix domain socket such as `/run/mcp.sock`
+    Unix(PathBuf),
+    /// A TCP address accepting WebSocket connections
+    WebSocket(String),
+    /// A single session over standard input and output
+    Stdio,
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "stdio" {
+            return Ok(ListenAddr::Stdio);
+        }
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                anyhow::bail!("缺少套接字路径: {}", s);
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        if let Some(addr) = s.strip_prefix("ws:") {
+            if addr.is_empty() {
+                anyhow::bail!("缺少监听地址: {}", s);
+            }
+            return Ok(ListenAddr::WebSocket(addr.to_string()));
+        }
+        let addr = s.strip_prefix("tcp:").unwrap_or(s);
+        if addr.is_empty() {
+            anyhow::bail!("缺少监听地址: {}", s);
+        }
+        Ok(ListenAddr::Tcp(addr.to_string()))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "tcp:{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            ListenAddr::WebSocket(addr) => write!(f, "ws:{}", addr),
+            ListenAddr::Stdio => write!(f, "stdio"),
+        }
+    }
+}
 
 /// The main MCP Server
 ///
 /// Manages tool and resource registries and handles client connections.
+#[derive(Clone)]
 pub struct McpServer {
     /// Registry of all available tools
     pub tool_registry: ToolRegistry,
     /// Registry of all available resources
     pub resource_registry: ResourceRegistry,
+    /// Registry of all available prompts
+    pub prompt_registry: PromptRegistry,
+    /// Configuration the server was created with
+    config: ServerConfig,
+    /// Sessions that receive forwarded log records
+    log_router: LogRouter,
+    /// Stops listeners and sessions gracefully
+    shutdown: ShutdownHandle,
+    /// Open connections counted against the configured limits
+    connections: ConnectionLimiter,
+    /// Checks the credentials of network clients, `None` to accept everyone
+    authenticator: Option<Arc<dyn Authenticator>>,
+    /// Which identities may use which tools and resources
+    policy: Arc<Policy>,
+    /// Server-wide request rate limit per caller, `None` for no limit
+    rate_limiter: Option<RateLimiter>,
+    /// Audit trail of tool calls and resource reads, `None` if disabled
+    audit: Option<Arc<AuditLog>>,
+    /// Prometheus metrics, shared by all clones
+    metrics: Arc<Metrics>,
 }
 
 impl McpServer {
@@ -30,19 +165,89 @@ impl McpServer {
     ///
     /// A new `McpServer` instance with default tools and resources
     pub fn new() -> Self {
-        let tool_registry = ToolRegistry::new();
-        let resource_registry = ResourceRegistry::new();
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// Creates a new MCP Server instance from a configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Server configuration, usually loaded from `mcp-config.toml`
+    ///
+    /// # Returns
+    ///
+    /// A new `McpServer` instance with built-in tools adjusted by the configuration
+    pub fn with_config(config: ServerConfig) -> Self {
+        let sandbox = Arc::new(PathSandbox::from_config(&config.sandbox));
+        let tool_registry = ToolRegistry::with_config(config.tools.clone()).with_sandbox(Arc::clone(&sandbox));
+        let resource_registry = ResourceRegistry::new()
+            .with_sandbox(sandbox)
+            .with_max_resource_size(config.limits.max_resource_size);
+        let connections = ConnectionLimiter::new(config.limits.max_connections, config.limits.max_connections_per_ip);
+        let tokens = StaticTokens::from_config(&config.auth);
+        let authenticator = (!tokens.is_empty()).then(|| Arc::new(tokens) as Arc<dyn Authenticator>);
+        let policy = Arc::new(Policy::from_config(config.policy.as_ref()));
+        let rate_limiter = config.limits.rate_limit.as_ref().and_then(RateLimiter::new);
+        let audit = config.audit.as_ref().map(|audit| Arc::new(AuditLog::new(audit)));
 
         McpServer {
             tool_registry,
             resource_registry,
+            prompt_registry: PromptRegistry::new(),
+            config,
+            log_router: LogRouter::new(),
+            shutdown: ShutdownHandle::new(),
+            connections,
+            authenticator,
+            policy,
+            rate_limiter,
+            audit,
+            metrics: Arc::new(Metrics::new()),
         }
     }
 
+    /// Requires network clients to authenticate with the given authenticator
+    ///
+    /// Replaces the static tokens from the configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `authenticator` - Checks the bearer tokens clients present
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let server = McpServer::new().with_authenticator(StaticTokens::new().with_token("s3cret", "ci"));
+    /// server.start("0.0.0.0:8080").await?;
+    /// ```
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Creates a `tracing` layer that forwards log records to this
+    /// server's clients as `notifications/message`
+    ///
+    /// Install it in the global subscriber to enable the MCP logging
+    /// capability.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// let server = McpServer::new();
+    /// tracing_subscriber::registry().with(server.log_layer()).init();
+    /// ```
+    pub fn log_layer(&self) -> McpLogLayer {
+        McpLogLayer::new(self.log_router.clone())
+    }
+
     /// Starts the TCP server and listens for client connections
     ///
-    /// Binds to the specified address and accepts connections in a loop.
-    /// Each connection is handled in a separate async task.
+    /// Binds to the specified address and accepts connections until the
+    /// server is shut down. Each connection is handled in a separate async
+    /// task; after shutdown this waits for all sessions to finish.
     ///
     /// # Arguments
     ///
@@ -50,7 +255,8 @@ impl McpServer {
     ///
     /// # Returns
     ///
-    /// Returns `Result<()>` with error if binding fails
+    /// Returns `Result<()>` with error if binding fails, or if the address
+    /// is not loopback and clients are not authenticated
     ///
     /// # Example
     ///
@@ -60,247 +266,1354 @@ impl McpServer {
     /// ```
     pub async fn start(&self, addr: &str) -> Result<()> {
         let listener = TcpListener::bind(addr).await?;
-        println!("MCP Server 监听在 {}", addr);
+        tracing::info!("MCP Server 监听在 {}", addr);
+        self.serve_tcp(listener).await
+    }
 
-        loop {
-            let (socket, _) = listener.accept().await?;
-            let tool_registry = self.clone_tool_registry();
-            let resource_registry = self.clone_resource_registry();
-            
+    /// Accepts newline-delimited connections on an already bound listener
+    ///
+    /// Connections are wrapped in TLS when the configuration has a `[tls]`
+    /// section. Clients identified by a certificate need no token; other
+    /// clients must, when authentication is on, send an `authenticate`
+    /// request as their first message. A listener on a non-loopback
+    /// address is refused when clients are not authenticated.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Listener to accept connections from
+    pub async fn serve_tcp(&self, listener: TcpListener) -> Result<()> {
+        self.check_exposure(&listener)?;
+        let tls = self.tls_acceptor()?;
+        self.accept_loop(listener, move |server, socket, peer| {
+            let tls = tls.clone();
+            async move {
+                let max_message_size = server.max_message_size();
+                match tls {
+                    Some(tls) => {
+                        let (stream, identity) = Self::accept_tls(&tls, socket).await?;
+                        let auth = server.stream_auth(identity);
+                        let transport = LineTransport::new(stream).with_max_message_size(max_message_size);
+                        server.serve_session(transport, auth, peer).await
+                    }
+                    None => {
+                        let auth = server.stream_auth(None);
+                        let transport = LineTransport::new(socket).with_max_message_size(max_message_size);
+                        server.serve_session(transport, auth, peer).await
+                    }
+                }
+            }
+        })
+        .await
+    }
+
+    /// Starts listening on a TCP address, Unix domain socket or WebSocket
+    /// address, or serves a single session over stdin/stdout
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - Where to listen
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let server = McpServer::new();
+    /// server.serve(&"unix:/run/mcp.sock".parse()?).await?;
+    /// ```
+    pub async fn serve(&self, listen: &ListenAddr) -> Result<()> {
+        if let Some(config) = &self.config.metrics {
+            let listener = TcpListener::bind(&config.listen)
+                .await
+                .with_context(|| format!("无法绑定指标地址 {}", config.listen))?;
+            tracing::info!("指标监听在 http://{}{}", config.listen, METRICS_PATH);
+            let server = self.clone();
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(socket, tool_registry, resource_registry).await {
-                    eprintln!("处理连接失败: {}", e);
+                if let Err(e) = server.serve_metrics(listener).await {
+                    tracing::error!("指标监听失败: {:#}", e);
                 }
             });
         }
+        match listen {
+            ListenAddr::Tcp(addr) => self.start(addr).await,
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => self.start_unix(path).await,
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => anyhow::bail!("当前平台不支持 Unix 套接字"),
+            ListenAddr::WebSocket(addr) => self.start_websocket(addr).await,
+            ListenAddr::Stdio => {
+                let stdio = tokio::io::join(tokio::io::stdin(), tokio::io::stdout());
+                let transport = LineTransport::new(stdio).with_max_message_size(self.max_message_size());
+                Arc::new(self.clone()).serve_transport(transport).await
+            }
+        }
+    }
+
+    /// Starts the WebSocket server and listens for client connections
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to bind to (e.g., "127.0.0.1:8081")
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<()>` with error if binding fails
+    pub async fn start_websocket(&self, addr: &str) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("MCP Server 监听在 {}", ListenAddr::WebSocket(addr.to_string()));
+        self.serve_websocket(listener).await
     }
 
-    /// Handles a single client connection
+    /// Accepts WebSocket connections on an already bound listener
     ///
-    /// Reads JSON-RPC messages line by line and processes them.
+    /// Connections are wrapped in TLS when the configuration has a `[tls]`
+    /// section. Clients identified by a certificate need no token; other
+    /// clients must, when authentication is on, send an
+    /// `Authorization: Bearer` header in the handshake. A listener on a
+    /// non-loopback address is refused when clients are not authenticated.
     ///
     /// # Arguments
     ///
-    /// * `socket` - The TCP socket for communication
-    /// * `tool_registry` - Registry of available tools
-    /// * `resource_registry` - Registry of available resources
-    async fn handle_connection(
-        mut socket: TcpStream,
-        tool_registry: ToolRegistry,
-        resource_registry: ResourceRegistry,
-    ) -> Result<()> {
-        let (reader, mut writer) = socket.split();
-        let mut reader = BufReader::new(reader);
-        let mut buffer = String::new();
-
-        while reader.read_line(&mut buffer).await? > 0 {
-            let trimmed = buffer.trim();
-            if !trimmed.is_empty() {
-                match Self::handle_message(trimmed, &tool_registry, &resource_registry).await {
-                    Ok(response) => {
-                        writer.write_all(response.as_bytes()).await?;
+    /// * `listener` - Listener to accept connections from
+    pub async fn serve_websocket(&self, listener: TcpListener) -> Result<()> {
+        self.check_exposure(&listener)?;
+        let tls = self.tls_acceptor()?;
+        self.accept_loop(listener, move |server, socket, peer| {
+            let tls = tls.clone();
+            async move {
+                match tls {
+                    Some(tls) => {
+                        let (stream, identity) = Self::accept_tls(&tls, socket).await?;
+                        server.serve_websocket_stream(stream, identity, peer).await
+                    }
+                    None => server.serve_websocket_stream(socket, None, peer).await,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Performs the WebSocket handshake on a connection and serves the session
+    ///
+    /// A client that does not finish the HTTP upgrade within the handshake
+    /// timeout is disconnected, as is one still upgrading when the server
+    /// shuts down.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The accepted connection, possibly already wrapped in TLS
+    /// * `identity` - Identity from the client's TLS certificate, if any
+    /// * `peer` - IP address of the client
+    async fn serve_websocket_stream<S>(
+        self: Arc<Self>,
+        stream: S,
+        identity: Option<Identity>,
+        peer: Option<IpAddr>,
+    ) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let max_message_size = self.max_message_size();
+        let handshake = async {
+            match (identity, &self.authenticator) {
+                (Some(identity), _) => {
+                    let transport = WebSocketTransport::accept_with_max_message_size(stream, max_message_size).await?;
+                    Ok((transport, SessionAuth::Authenticated(identity)))
+                }
+                (None, Some(authenticator)) => {
+                    let (transport, identity) =
+                        WebSocketTransport::accept_authenticated(stream, max_message_size, authenticator.as_ref())
+                            .await?;
+                    Ok((transport, SessionAuth::Authenticated(identity)))
+                }
+                (None, None) => {
+                    let transport = WebSocketTransport::accept_with_max_message_size(stream, max_message_size).await?;
+                    Ok((transport, SessionAuth::Anonymous))
+                }
+            }
+        };
+        let (transport, auth) = tokio::select! {
+            result = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake) => {
+                let result: std::io::Result<_> = result.context("WebSocket 握手超时")?;
+                result.context("WebSocket 握手失败")?
+            }
+            _ = self.shutdown.wait() => return Ok(()),
+        };
+        self.serve_session(transport, auth, peer).await
+    }
+
+    /// Serves Prometheus metrics on an already bound admin listener
+    ///
+    /// Started by [`McpServer::serve`] when the configuration has a
+    /// `[metrics]` section. Scrapers `GET /metrics`; the listener stops
+    /// when the server shuts down.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Listener to accept scrape connections from
+    pub async fn serve_metrics(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            tokio::select! {
+                _ = self.shutdown.wait() => return Ok(()),
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let registry = Arc::clone(&self.metrics);
+                        tokio::spawn(async move {
+                            if let Err(e) = metrics::serve_scrape(stream, &registry).await {
+                                tracing::debug!("指标请求失败: {:#}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        tracing::warn!("接受指标连接失败，稍后重试: {}", e);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Waits until every audit record queued so far has been written
+    ///
+    /// Does nothing when the audit log is disabled.
+    pub async fn flush_audit(&self) {
+        if let Some(audit) = self.audit.clone() {
+            let _ = tokio::task::spawn_blocking(move || audit.flush()).await;
+        }
+    }
+
+    /// Gets the server's Prometheus metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Refuses to serve clients beyond the local host without authentication
+    ///
+    /// A listener on a non-loopback address needs bearer tokens or TLS
+    /// client certificates, unless `[auth] allow_anonymous` is set, in
+    /// which case it is only warned about.
+    fn check_exposure(&self, listener: &TcpListener) -> Result<()> {
+        let addr = listener.local_addr()?;
+        let client_certificates = self.config.tls.as_ref().is_some_and(|tls| tls.client_ca_path.is_some());
+        if addr.ip().is_loopback() || self.authenticator.is_some() || client_certificates {
+            return Ok(());
+        }
+        if !self.config.auth.allow_anonymous {
+            anyhow::bail!(
+                "拒绝在 {} 上无认证监听: 请通过 [auth] 或 {} 环境变量配置令牌，或设置 [auth] allow_anonymous = true",
+                addr,
+                self.config.auth.token_env
+            );
+        }
+        tracing::warn!("{} 上的监听未启用认证，任何能访问该地址的客户端都可以调用所有工具", addr);
+        Ok(())
+    }
+
+    /// Builds the TLS acceptor from the `[tls]` section, if there is one
+    fn tls_acceptor(&self) -> Result<Option<TlsAcceptor>> {
+        let Some(config) = &self.config.tls else {
+            return Ok(None);
+        };
+        let acceptor = tls::load_acceptor(config)?;
+        match config.client_ca_path {
+            Some(_) => tracing::info!("已启用 TLS，要求客户端证书"),
+            None => tracing::info!("已启用 TLS"),
+        }
+        Ok(Some(acceptor))
+    }
+
+    /// Performs the server side of a TLS handshake
+    ///
+    /// # Returns
+    ///
+    /// The encrypted stream and the identity from the client's certificate, if any
+    async fn accept_tls(
+        acceptor: &TlsAcceptor,
+        socket: TcpStream,
+    ) -> Result<(tokio_rustls::server::TlsStream<TcpStream>, Option<Identity>)> {
+        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket))
+            .await
+            .context("TLS 握手超时")?
+            .context("TLS 握手失败")?;
+        let identity = tls::peer_identity(&stream);
+        Ok((stream, identity))
+    }
+
+    /// Decides how a network client authenticates
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity from the client's TLS certificate, if any
+    fn stream_auth(&self, identity: Option<Identity>) -> SessionAuth {
+        match (identity, &self.authenticator) {
+            (Some(identity), _) => SessionAuth::Authenticated(identity),
+            (None, Some(authenticator)) => SessionAuth::Handshake(Arc::clone(authenticator)),
+            (None, None) => SessionAuth::Anonymous,
+        }
+    }
+
+    /// Starts listening on a Unix domain socket
+    ///
+    /// A stale socket file left behind by a previous run is removed first;
+    /// a socket that still accepts connections, or a path that is not a
+    /// socket, is an error. The socket file gets the permissions set by
+    /// `socket_mode` in the configuration and is removed again when this
+    /// future completes or is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the socket file (e.g., "/run/mcp.sock")
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<()>` with error if the socket cannot be created
+    #[cfg(unix)]
+    pub async fn start_unix(&self, path: impl AsRef<Path>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = path.as_ref();
+        remove_stale_socket(path)?;
+        let listener = tokio::net::UnixListener::bind(path)
+            .with_context(|| format!("无法绑定套接字 {}", path.display()))?;
+        let _guard = SocketFileGuard(path.to_path_buf());
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.config.server.socket_mode))
+            .with_context(|| format!("无法设置套接字权限 {}", path.display()))?;
+        tracing::info!("MCP Server 监听在 {}", ListenAddr::Unix(path.to_path_buf()));
+
+        self.accept_loop(listener, |server, socket, _| {
+            let transport = LineTransport::new(socket).with_max_message_size(server.max_message_size());
+            server.serve_transport(transport)
+        })
+        .await
+    }
+
+    /// Gets a handle that shuts this server down gracefully
+    ///
+    /// The handle is shared by all clones of the server.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let server = McpServer::new();
+    /// let shutdown = server.shutdown_handle();
+    /// tokio::spawn(async move { server.start("127.0.0.1:8080").await });
+    /// // ...
+    /// shutdown.shutdown();
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Gets the number of connections currently open on this server's listeners
+    pub fn open_connections(&self) -> usize {
+        self.connections.open_connections()
+    }
+
+    /// Gets the largest inbound message accepted, in bytes
+    fn max_message_size(&self) -> usize {
+        match self.config.limits.max_message_size {
+            0 => usize::MAX,
+            limit => limit,
+        }
+    }
+
+    /// Converts a limit in seconds to a duration, with 0 meaning no limit
+    fn limit_duration(secs: u64) -> Option<Duration> {
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    /// Accepts connections until shutdown, then waits for their sessions
+    ///
+    /// Accept errors (e.g. running out of file descriptors) are logged and
+    /// retried after a short pause instead of stopping the server.
+    /// Connections beyond the configured connection limits are closed
+    /// right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Listener to accept connections from
+    /// * `handle` - Serves one accepted connection, given the client's IP address
+    async fn accept_loop<L, F, Fut>(&self, listener: L, handle: F) -> Result<()>
+    where
+        L: Listener,
+        F: Fn(Arc<Self>, L::Stream, Option<IpAddr>) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let server = Arc::new(self.clone());
+        let mut connections = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                _ = self.shutdown.wait() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        let permit = match self.connections.try_acquire(peer) {
+                            Ok(permit) => permit,
+                            Err(e) => {
+                                tracing::warn!("拒绝连接: {}", e);
+                                continue;
+                            }
+                        };
+                        let connection = handle(Arc::clone(&server), stream, peer);
+                        connections.spawn(async move {
+                            if let Err(e) = connection.await {
+                                tracing::error!("处理连接失败: {:#}", e);
+                            }
+                            drop(permit);
+                        });
                     }
                     Err(e) => {
-                        eprintln!("处理消息失败: {}", e);
+                        tracing::warn!("接受连接失败，稍后重试: {}", e);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    }
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            }
+        }
+
+        drop(listener);
+        tracing::info!("停止接受新连接，等待 {} 个会话结束", connections.len());
+        while connections.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    /// Creates a new session whose outbound messages go to `outbound`, and
+    /// streamed ones to `streams`
+    fn new_session(
+        &self,
+        outbound: mpsc::UnboundedSender<String>,
+        streams: mpsc::UnboundedSender<StreamedMessage>,
+        identity: Option<Identity>,
+        peer: Option<IpAddr>,
+    ) -> Session {
+        let timeout = Duration::from_secs(self.config.server.client_request_timeout_secs);
+        Session::new(outbound)
+            .with_streams(streams)
+            .with_request_timeout(timeout)
+            .with_identity(identity)
+            .with_peer(peer)
+    }
+
+    /// Runs a session over a transport until the client stops sending
+    ///
+    /// This is where all transports meet: each inbound message is handled
+    /// in its own task so that a handler waiting on the client (e.g. for
+    /// sampling) does not block reading the client's response. All outbound
+    /// messages go through the session's channel to a dedicated writer task,
+    /// which closes the transport once the session is over.
+    ///
+    /// When the server shuts down, the client is sent a shutdown notice and
+    /// new requests are refused, while responses to the server's own
+    /// requests are still accepted. In-flight requests get until the
+    /// shutdown deadline to finish; the rest are cancelled and answered
+    /// with `McpError::ShuttingDown`.
+    ///
+    /// A message over the configured size limit is answered with
+    /// `McpError::MessageTooLarge` and ends the session, as does a session
+    /// that stays idle longer than the configured idle timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - Connection to the client
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<()>` with error if the transport fails
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let server = Arc::new(McpServer::new());
+    /// let (socket, _) = listener.accept().await?;
+    /// server.serve_transport(ContentLengthTransport::new(socket)).await?;
+    /// ```
+    pub async fn serve_transport<T: Transport>(self: Arc<Self>, transport: T) -> Result<()> {
+        self.serve_session(transport, SessionAuth::Anonymous, None).await
+    }
+
+    /// Runs a session over a transport, authenticating the client as requested
+    ///
+    /// See [`McpServer::serve_transport`]. With `SessionAuth::Handshake`,
+    /// the client's first message must be a successful `authenticate`
+    /// request; otherwise it is answered with `McpError::Unauthenticated`
+    /// and the connection is closed. `peer` is the client's IP address,
+    /// `None` for local transports.
+    async fn serve_session<T: Transport>(
+        self: Arc<Self>,
+        transport: T,
+        auth: SessionAuth,
+        peer: Option<IpAddr>,
+    ) -> Result<()> {
+        let (mut reader, mut writer) = transport.split();
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<String>();
+        let (streams, mut streams_rx) = mpsc::unbounded_channel::<StreamedMessage>();
+
+        let metrics = Arc::clone(&self.metrics);
+        let writer_task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(message) = outbound_rx.recv() => {
+                        metrics.add_sent(message.len());
+                        writer.send(message).await?;
+                    }
+                    Some(message) = streams_rx.recv() => {
+                        metrics.add_sent(message.byte_len());
+                        writer.send_streamed(message).await?;
                     }
+                    else => break,
                 }
             }
-            buffer.clear();
+            writer.close().await
+        });
+
+        let identity = match auth {
+            SessionAuth::Anonymous => None,
+            SessionAuth::Authenticated(identity) => Some(identity),
+            SessionAuth::Handshake(authenticator) => {
+                match Self::authenticate_first_message(&mut reader, authenticator.as_ref()).await {
+                    Ok((id, identity)) => {
+                        let result = serde_json::to_value(AuthenticateResult {
+                            identity: identity.clone(),
+                        })?;
+                        let _ = outbound.send(Self::response(Some(id), Ok(result)));
+                        Some(identity)
+                    }
+                    Err((id, error)) => {
+                        tracing::warn!("{}", error);
+                        let _ = outbound.send(Self::response(id, Err(error)));
+                        drop((outbound, streams));
+                        writer_task.await??;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        if let Some(identity) = &identity {
+            tracing::info!(identity = %identity, "客户端已认证");
+        }
+
+        let session = Arc::new(self.new_session(outbound, streams, identity, peer));
+        let _active = self.metrics.session_started();
+        let span = tracing::info_span!(
+            "session",
+            session_id = session.id(),
+            identity = session.identity().map(|identity| identity.name.as_str()),
+            peer = session.peer().map(tracing::field::display),
+        );
+        self.log_router.register(&session);
+        let mut in_flight = JoinSet::new();
+        // Request ID of each in-flight task, to answer it if it is cancelled
+        let mut request_ids = HashMap::new();
+        let drain_timeout = Duration::from_secs(self.config.server.shutdown_timeout_secs);
+        let idle_timeout = Self::limit_duration(self.config.limits.idle_timeout_secs);
+        let max_message_size = self.max_message_size();
+
+        let read_result: std::io::Result<()> = async {
+            let mut deadline: Option<Instant> = None;
+            let mut last_activity = Instant::now();
+            loop {
+                if deadline.is_some() && in_flight.is_empty() {
+                    break;
+                }
+                tokio::select! {
+                    message = reader.recv() => {
+                        let message = match message {
+                            Ok(Some(message)) => message,
+                            Ok(None) => break,
+                            Err(e) => match MessageTooLarge::from_io_error(&e) {
+                                Some(too_large) => {
+                                    Self::reject_oversized(&session, too_large.limit);
+                                    break;
+                                }
+                                None => return Err(e),
+                            },
+                        };
+                        self.metrics.add_received(message.len());
+                        // Transports without a limit of their own are checked here
+                        if message.len() > max_message_size {
+                            Self::reject_oversized(&session, max_message_size);
+                            break;
+                        }
+                        last_activity = Instant::now();
+                        let message = message.trim().to_string();
+                        if message.is_empty() {
+                            continue;
+                        }
+
+                        let request_id = Self::request_id(&message);
+                        if deadline.is_some() {
+                            match request_id {
+                                Some(id) => {
+                                    session.send(Self::response(Some(id), Err(McpError::ShuttingDown)));
+                                }
+                                // Responses and notifications are still handled
+                                None => {
+                                    if let Some(response) = self.handle_message(&message, &session).await {
+                                        session.send(response);
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+
+                        let server = Arc::clone(&self);
+                        let task_session = Arc::clone(&session);
+                        let task = in_flight.spawn(
+                            async move {
+                                if let Some(response) = server.handle_message(&message, &task_session).await {
+                                    task_session.send(response);
+                                }
+                            }
+                            .instrument(span.clone()),
+                        );
+                        if let Some(id) = request_id {
+                            request_ids.insert(task.id(), id);
+                        }
+                    }
+                    _ = self.shutdown.wait(), if deadline.is_none() => {
+                        let notice = serde_json::json!({ "deadlineMs": drain_timeout.as_millis() as u64 });
+                        let _ = session.notify(SHUTDOWN_NOTIFICATION, notice);
+                        deadline = Some(Instant::now() + drain_timeout);
+                    }
+                    Some(done) = in_flight.join_next_with_id(), if !in_flight.is_empty() => {
+                        request_ids.remove(&Self::task_id(&done));
+                        last_activity = Instant::now();
+                    }
+                    _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                        tracing::warn!("关闭期限已到，取消 {} 个未完成的请求", in_flight.len());
+                        in_flight.abort_all();
+                        break;
+                    }
+                    // Only a session with nothing in flight counts as idle
+                    _ = tokio::time::sleep_until(last_activity + idle_timeout.unwrap_or_default()),
+                        if idle_timeout.is_some() && deadline.is_none() && in_flight.is_empty() => {
+                        tracing::info!("会话空闲超时，关闭连接");
+                        break;
+                    }
+                }
+            }
+            Ok(())
         }
+        .instrument(span.clone())
+        .await;
+
+        // The client stopped sending: no response to a server request can
+        // arrive anymore, but responses to its own requests are still owed.
+        session.cancel_pending();
+        while let Some(done) = in_flight.join_next_with_id().await {
+            if let Err(e) = &done
+                && e.is_cancelled()
+                && let Some(id) = request_ids.remove(&e.id())
+            {
+                session.send(Self::response(Some(id), Err(McpError::ShuttingDown)));
+            }
+        }
+        self.log_router.unregister(session.id());
+        session.close();
+        read_result?;
+        writer_task.await??;
 
         Ok(())
     }
 
+    /// Reads the client's first message and checks it is a valid `authenticate` request
+    ///
+    /// # Returns
+    ///
+    /// The request ID and the client's identity, or the ID to answer (if
+    /// any) and the reason authentication failed
+    async fn authenticate_first_message<R: MessageReader>(
+        reader: &mut R,
+        authenticator: &dyn Authenticator,
+    ) -> Result<(u64, Identity), (Option<u64>, McpError)> {
+        let unauthenticated = |id, reason: &str| (id, McpError::Unauthenticated(reason.to_string()));
+        let message = match tokio::time::timeout(HANDSHAKE_TIMEOUT, reader.recv()).await {
+            Ok(Ok(Some(message))) => message,
+            Ok(Ok(None)) | Ok(Err(_)) => return Err(unauthenticated(None, "连接在认证前关闭")),
+            Err(_) => return Err(unauthenticated(None, "等待认证超时")),
+        };
+
+        let message: McpMessage = serde_json::from_str(message.trim()).map_err(|_| unauthenticated(None, "首条消息必须是认证请求"))?;
+        let Some(id) = message.id else {
+            return Err(unauthenticated(None, "首条消息必须是认证请求"));
+        };
+        if message.method != AUTHENTICATE_METHOD {
+            return Err(unauthenticated(Some(id), "首条消息必须是认证请求"));
+        }
+        let request: AuthenticateRequest = Self::parse_params(message.params).map_err(|e| (Some(id), e))?;
+        match authenticator.authenticate(&request.token) {
+            Some(identity) => Ok((id, identity)),
+            None => Err(unauthenticated(Some(id), "无效的令牌")),
+        }
+    }
+
+    /// Answers a message over the size limit before the session is closed
+    ///
+    /// The oversized message is never parsed, so the response has a null ID.
+    fn reject_oversized(session: &Session, limit: usize) {
+        tracing::warn!("消息超过 {} 字节的上限，关闭连接", limit);
+        session.send(Self::response(None, Err(McpError::MessageTooLarge { limit })));
+    }
+
+    /// Gets the ID of a request message, or `None` for notifications,
+    /// responses and unparsable messages
+    fn request_id(message: &str) -> Option<u64> {
+        let value: Value = serde_json::from_str(message).ok()?;
+        value.get("method")?;
+        value.get("id")?.as_u64()
+    }
+
+    /// Gets the task ID of a finished in-flight handler
+    fn task_id(done: &Result<(task::Id, ()), task::JoinError>) -> task::Id {
+        match done {
+            Ok((id, ())) => *id,
+            Err(e) => e.id(),
+        }
+    }
+
     /// Processes a single JSON-RPC message
     ///
-    /// Parses the message and dispatches to appropriate handler based on method.
+    /// Requests are dispatched to the handler for their method, and
+    /// handler failures are turned into JSON-RPC error responses. Requests
+    /// over the caller's rate limit are answered with `McpError::RateLimited`
+    /// without running, and a handler running longer than the configured
+    /// request timeout is abandoned and answered with `McpError::HandlerTimeout`.
+    /// Tool calls and resource reads are recorded in the audit log, if
+    /// enabled, whatever their outcome.
+    /// Notifications and responses to server-initiated requests produce
+    /// no reply.
     ///
     /// # Arguments
     ///
     /// * `message` - The JSON-RPC message string
-    /// * `tool_registry` - Registry of available tools
-    /// * `resource_registry` - Registry of available resources
-    async fn handle_message(
-        message: &str,
-        tool_registry: &ToolRegistry,
-        resource_registry: &ResourceRegistry,
-    ) -> Result<String> {
-        let mcp_msg: McpMessage = serde_json::from_str(message)?;
-
-        let response = match mcp_msg.method.as_str() {
-            "tools/list" => Self::handle_list_tools(tool_registry, mcp_msg.id).await?,
-            "tools/call" => Self::handle_call_tool(tool_registry, mcp_msg.params, mcp_msg.id).await?,
-            "resources/list" => Self::handle_list_resources(resource_registry, mcp_msg.id).await?,
-            "resources/read" => Self::handle_read_resource(resource_registry, mcp_msg.params, mcp_msg.id).await?,
-            _ => {
-                serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "error": {
-                        "code": -32601,
-                        "message": "方法未找到"
-                    },
-                    "id": mcp_msg.id
-                })
-                .to_string()
+    /// * `session` - Session the message arrived on
+    ///
+    /// # Returns
+    ///
+    /// The serialized response, or `None` if the message needs no reply or
+    /// its response was streamed to the session
+    async fn handle_message(&self, message: &str, session: &Arc<Session>) -> Option<String> {
+        let value: Value = match serde_json::from_str(message) {
+            Ok(value) => value,
+            Err(e) => {
+                let error = McpError::Parse(e);
+                tracing::warn!("处理消息失败: {}", error);
+                self.metrics.observe_error(&error);
+                return Some(Self::response(None, Err(error)));
+            }
+        };
+
+        if value.get("method").is_none() && (value.get("result").is_some() || value.get("error").is_some()) {
+            match serde_json::from_value::<JsonRpcResponse>(value) {
+                Ok(response) => {
+                    if !session.handle_response(response) {
+                        tracing::warn!("收到未知请求的响应");
+                    }
+                }
+                Err(e) => tracing::warn!("处理消息失败: {}", McpError::InvalidRequest(e.to_string())),
+            }
+            return None;
+        }
+
+        let mcp_msg: McpMessage = match serde_json::from_value(value) {
+            Ok(msg) => msg,
+            Err(e) => {
+                let error = McpError::InvalidRequest(e.to_string());
+                tracing::warn!("处理消息失败: {}", error);
+                self.metrics.observe_error(&error);
+                return Some(Self::response(None, Err(error)));
+            }
+        };
+
+        let Some(id) = mcp_msg.id else {
+            self.handle_notification(&mcp_msg.method, mcp_msg.params, session);
+            return None;
+        };
+
+        let audit = self.audit.as_ref().filter(|_| AuditLog::covers(&mcp_msg.method));
+        let audit_params = audit.map(|_| mcp_msg.params.clone());
+        let tool = match (mcp_msg.method.as_str(), mcp_msg.params["name"].as_str()) {
+            ("tools/call", Some(name)) if self.tool_registry.get(name).is_some() => name.to_string(),
+            ("tools/call", _) => UNKNOWN_LABEL.to_string(),
+            _ => String::new(),
+        };
+        let span = tracing::info_span!(
+            "request",
+            method = %mcp_msg.method,
+            id,
+            tool = (!tool.is_empty()).then_some(tool.as_str()),
+            traceparent = mcp_msg.params["_meta"][TRACEPARENT_FIELD].as_str(),
+            error = tracing::field::Empty,
+        );
+        let started = Instant::now();
+
+        let result = async {
+            match self.check_rate(session) {
+                Err(error) => {
+                    tracing::warn!(caller = %caller_key(session), "请求被限流: {}", mcp_msg.method);
+                    Err(error)
+                }
+                Ok(()) => {
+                    let dispatch = self.dispatch(&mcp_msg.method, mcp_msg.params, session);
+                    match Self::limit_duration(self.config.limits.request_timeout_secs) {
+                        Some(timeout) => tokio::time::timeout(timeout, dispatch).await.unwrap_or_else(|_| {
+                            tracing::warn!("请求处理超时: {}", mcp_msg.method);
+                            Err(McpError::HandlerTimeout {
+                                method: mcp_msg.method.clone(),
+                                timeout_ms: timeout.as_millis() as u64,
+                            })
+                        }),
+                        None => dispatch.await,
+                    }
+                }
             }
+        }
+        .instrument(span.clone())
+        .await;
+        if let Err(error) = &result {
+            span.record(ERROR_FIELD, tracing::field::display(error));
+        }
+        let (result, blob) = match result {
+            Ok(Reply::Value(value)) => (Ok(value), None),
+            Ok(Reply::Blob(blob)) => (Ok(Value::Null), Some(blob)),
+            Err(error) => (Err(error), None),
+        };
+
+        // Only methods the server has become labels, whatever the request was answered with
+        let method_label = if METHODS.contains(&mcp_msg.method.as_str()) {
+            mcp_msg.method.as_str()
+        } else {
+            UNKNOWN_LABEL
         };
+        self.metrics.observe_request(method_label, &tool, &result, started.elapsed());
+        if let (Some(audit), Some(params)) = (audit, audit_params) {
+            audit.record(session, &mcp_msg.method, &params, &result, started.elapsed());
+        }
+        if let Some(blob) = blob {
+            session.send_streamed(Self::blob_response(id, blob)).await;
+            return None;
+        }
+        Some(Self::response(Some(id), result))
+    }
 
-        Ok(response)
+    /// Counts a request against the server-wide rate limit
+    fn check_rate(&self, session: &Session) -> Result<(), McpError> {
+        let Some(limiter) = &self.rate_limiter else {
+            return Ok(());
+        };
+        limiter.try_acquire(&caller_key(session)).map_err(|retry_after| McpError::RateLimited {
+            tool: None,
+            retry_after_ms: retry_after.as_millis() as u64,
+        })
     }
 
-    /// Handles `tools/list` RPC method
+    /// Runs the handler for a request method
+    ///
+    /// Every method handled here is listed in [`METHODS`].
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - JSON-RPC method name
+    /// * `params` - Request parameters
+    /// * `session` - Session the request arrived on
+    async fn dispatch(&self, method: &str, params: Value, session: &Arc<Session>) -> Result<Reply, McpError> {
+        let result = match method {
+            "initialize" => self.handle_initialize(session, params).await,
+            "ping" => Ok(serde_json::json!({})),
+            "logging/setLevel" => self.handle_set_level(session, params).await,
+            "tools/list" => self.handle_list_tools(session).await,
+            "tools/call" => self.handle_call_tool(session, params).await,
+            "resources/list" => self.handle_list_resources(session).await,
+            "resources/read" => return self.handle_read_resource(session, params).await,
+            "resources/templates/list" => self.handle_list_resource_templates().await,
+            "prompts/list" => self.handle_list_prompts().await,
+            "prompts/get" => self.handle_get_prompt(params).await,
+            "completion/complete" => self.handle_complete(session, params).await,
+            method => Err(McpError::MethodNotFound(method.to_string())),
+        };
+        result.map(Reply::Value)
+    }
+
+    /// Processes a JSON-RPC notification from the client
     ///
-    /// Returns a JSON-RPC response containing all available tools.
+    /// # Arguments
+    ///
+    /// * `method` - Notification method name
+    /// * `params` - Notification parameters
+    /// * `session` - Session the notification arrived on
+    fn handle_notification(&self, method: &str, _params: Value, session: &Arc<Session>) {
+        match method {
+            "notifications/roots/list_changed" => session.invalidate_roots(),
+            "notifications/initialized" | "notifications/cancelled" => {}
+            other => tracing::debug!("忽略未知通知: {}", other),
+        }
+    }
+
+    /// Serializes a handler outcome as a JSON-RPC response
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - JSON-RPC request ID
+    /// * `result` - The handler's result or error
+    fn response(id: Option<u64>, result: Result<Value, McpError>) -> String {
+        match result {
+            Ok(result) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "result": result,
+                "id": id
+            }),
+            Err(error) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": error.to_json_rpc(),
+                "id": id
+            }),
+        }
+        .to_string()
+    }
+
+    /// Builds the response to a resource read whose blob is streamed
+    ///
+    /// The response has the same shape as a `ReadResourceResult` with one
+    /// blob; only the blob itself is read and encoded as it is sent.
     ///
     /// # Arguments
     ///
-    /// * `tool_registry` - Registry of available tools
     /// * `id` - JSON-RPC request ID
-    async fn handle_list_tools(
-        tool_registry: &ToolRegistry,
-        id: Option<u64>,
-    ) -> Result<String> {
-        let tools = tool_registry.list_tools();
-        let result = ListToolsResult { tools };
-
-        Ok(serde_json::json!({
-            "jsonrpc": "2.0",
-            "result": result,
-            "id": id
+    /// * `blob` - The opened file
+    fn blob_response(id: u64, blob: BlobReader) -> StreamedMessage {
+        let head = format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{{\"contents\":[{{\"uri\":{},\"mimeType\":{},\"blob\":\"",
+            id,
+            Value::from(blob.uri.as_str()),
+            Value::from(blob.mime_type.as_str()),
+        );
+        blob.into_message(head, "\"}]}}".to_string())
+    }
+
+    /// Serializes a handler's result
+    ///
+    /// A result that cannot be serialized is a fault of the server, not of
+    /// the request, so it is reported as `McpError::Internal`.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Typed result of the handler
+    fn to_value<T: Serialize>(result: &T) -> Result<Value, McpError> {
+        Ok(serde_json::to_value(result).context("无法序列化响应")?)
+    }
+
+    /// Deserializes request parameters into their typed form
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Raw `params` value of the request
+    fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, McpError> {
+        serde_json::from_value(params).map_err(|e| {
+            let expected = std::any::type_name::<T>().rsplit("::").next().unwrap_or_default();
+            McpError::InvalidParams {
+                message: e.to_string(),
+                details: Some(serde_json::json!({ "expected": expected })),
+            }
         })
-        .to_string())
+    }
+
+    /// Handles `initialize` RPC method
+    ///
+    /// Records the client's capabilities on the session and returns the
+    /// server's identity and capabilities.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session being initialized
+    /// * `params` - RPC parameters containing the client's capabilities
+    async fn handle_initialize(&self, session: &Arc<Session>, params: Value) -> Result<Value, McpError> {
+        let request: InitializeRequest = Self::parse_params(params)?;
+        session.set_client_capabilities(request.capabilities);
+
+        // Speak the client's version if we support it, otherwise offer ours
+        let protocol_version = if SUPPORTED_PROTOCOL_VERSIONS.contains(&request.protocol_version.as_str()) {
+            request.protocol_version
+        } else {
+            LATEST_PROTOCOL_VERSION.to_string()
+        };
+
+        let result = InitializeResult {
+            protocol_version,
+            capabilities: ServerCapabilities {
+                tools: Some(serde_json::json!({})),
+                resources: Some(serde_json::json!({})),
+                prompts: Some(serde_json::json!({})),
+                logging: Some(serde_json::json!({})),
+                completions: Some(serde_json::json!({})),
+            },
+            server_info: Implementation {
+                name: self.config.server.name.clone(),
+                version: self.config.server.version.clone(),
+            },
+        };
+        Self::to_value(&result)
+    }
+
+    /// Handles `logging/setLevel` RPC method
+    ///
+    /// Sets the minimum level of log messages forwarded to this session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session of the calling client
+    /// * `params` - RPC parameters containing the level
+    async fn handle_set_level(&self, session: &Arc<Session>, params: Value) -> Result<Value, McpError> {
+        let request: SetLevelRequest = Self::parse_params(params)?;
+        session.set_log_level(request.level);
+        tracing::debug!(level = ?request.level, "客户端设置日志级别");
+        Ok(serde_json::json!({}))
+    }
+
+    /// Handles `tools/list` RPC method
+    ///
+    /// Returns all available tools the client's identity may call.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session of the calling client
+    async fn handle_list_tools(&self, session: &Arc<Session>) -> Result<Value, McpError> {
+        let mut tools = self.tool_registry.list_tools();
+        let total = tools.len();
+        tools.retain(|tool| self.policy.permits(session.identity(), &Action::CallTool(&tool.name)));
+        self.log_hidden(session, "工具", total - tools.len());
+        Self::to_value(&ListToolsResult { tools })
     }
 
     /// Handles `tools/call` RPC method
     ///
     /// Invokes a tool with the provided arguments and returns the result.
+    /// Tools the authorization policy denies the client, unknown tools and
+    /// invalid arguments are reported as JSON-RPC errors;
+    /// a failure inside the tool is returned as a result with `isError: true`.
+    ///
+    /// The tool's execution limits are enforced here: a call over its rate
+    /// limit fails with `McpError::RateLimited`, a call over its
+    /// concurrency limit waits for a slot or fails with
+    /// `McpError::ToolOverloaded`, and an execution running past its
+    /// timeout is abandoned with `McpError::ToolTimeout`.
     ///
     /// # Arguments
     ///
-    /// * `tool_registry` - Registry of available tools
+    /// * `session` - Session of the calling client, passed to the tool
     /// * `params` - RPC parameters containing tool name and arguments
-    /// * `id` - JSON-RPC request ID
-    async fn handle_call_tool(
-        tool_registry: &ToolRegistry,
-        params: Value,
-        id: Option<u64>,
-    ) -> Result<String> {
-        let request: CallToolRequest = serde_json::from_value(params)?;
-
-        match tool_registry.get(&request.name) {
-            Some(tool) => {
-                match tool.execute(request.arguments).await {
-                    Ok(result) => {
-                        Ok(serde_json::json!({
-                            "jsonrpc": "2.0",
-                            "result": result,
-                            "id": id
-                        })
-                        .to_string())
-                    }
-                    Err(e) => {
-                        Ok(serde_json::json!({
-                            "jsonrpc": "2.0",
-                            "error": {
-                                "code": -32602,
-                                "message": format!("工具执行失败: {}", e)
-                            },
-                            "id": id
-                        })
-                        .to_string())
-                    }
+    async fn handle_call_tool(&self, session: &Arc<Session>, params: Value) -> Result<Value, McpError> {
+        let request: CallToolRequest = Self::parse_params(params)?;
+        self.policy.authorize(session.identity(), &Action::CallTool(&request.name))?;
+        let tool = self
+            .tool_registry
+            .get(&request.name)
+            .ok_or_else(|| McpError::ToolNotFound(request.name.clone()))?;
+
+        self.tool_registry.check_rate(tool, &caller_key(session)).inspect_err(|_| {
+            tracing::warn!(caller = %caller_key(session), "工具调用被限流: {}", request.name);
+        })?;
+        // Held until the tool finishes, freeing the slot for the next call
+        let _slot = self.tool_registry.acquire_slot(tool).await?;
+        let _running = self.metrics.tool_call_started(tool.name());
+        let ctx = RequestContext::new(Arc::clone(session));
+        let execution = tool.execute(request.arguments, &ctx);
+        let outcome = match self.tool_registry.limits(tool).timeout {
+            Some(timeout) => tokio::time::timeout(timeout, execution).await.map_err(|_| {
+                tracing::warn!("工具执行超时: {}", request.name);
+                McpError::ToolTimeout {
+                    tool: request.name.clone(),
+                    timeout_ms: timeout.as_millis() as u64,
                 }
+            })?,
+            None => execution.await,
+        };
+        let result = match outcome {
+            Ok(result) => result,
+            Err(ToolError::Execution(e)) => CallToolResult::error(format!("{:#}", e)),
+            Err(ToolError::InvalidArguments(message)) => {
+                return Err(McpError::InvalidParams {
+                    message,
+                    details: Some(serde_json::json!({ "tool": request.name })),
+                });
             }
-            None => {
-                Ok(serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "error": {
-                        "code": -32601,
-                        "message": "工具未找到"
-                    },
-                    "id": id
-                })
-                .to_string())
-            }
-        }
+        };
+
+        Self::to_value(&result)
     }
 
     /// Handles `resources/list` RPC method
     ///
-    /// Returns a JSON-RPC response containing all available resources.
+    /// Returns all available resources the client's identity may read. If
+    /// the client exposes roots, `file://` resources outside them are left out.
     ///
     /// # Arguments
     ///
-    /// * `resource_registry` - Registry of available resources
-    /// * `id` - JSON-RPC request ID
-    async fn handle_list_resources(
-        resource_registry: &ResourceRegistry,
-        id: Option<u64>,
-    ) -> Result<String> {
-        let resources = resource_registry.list_resources();
-        let result = ListResourcesResult { resources };
-
-        Ok(serde_json::json!({
-            "jsonrpc": "2.0",
-            "result": result,
-            "id": id
-        })
-        .to_string())
+    /// * `session` - Session of the calling client
+    async fn handle_list_resources(&self, session: &Arc<Session>) -> Result<Value, McpError> {
+        let mut resources = self.resource_registry.list_resources();
+        let total = resources.len();
+        resources.retain(|resource| self.policy.permits(session.identity(), &Action::ReadResource(&resource.uri)));
+        self.log_hidden(session, "资源", total - resources.len());
+        if let Some(roots) = session.root_paths().await? {
+            resources.retain(|resource| match file_uri_to_path(&resource.uri) {
+                Some(path) => is_within_roots(&path, &roots),
+                None => true,
+            });
+        }
+        Self::to_value(&ListResourcesResult { resources })
     }
 
     /// Handles `resources/read` RPC method
     ///
-    /// Reads a resource and returns its content.
+    /// Reads a resource and returns its content. Resources the
+    /// authorization policy denies the client are refused, as are
+    /// `file://` resources outside the roots the client exposes.
     ///
     /// # Arguments
     ///
-    /// * `resource_registry` - Registry of available resources
+    /// * `session` - Session of the calling client
     /// * `params` - RPC parameters containing resource URI
-    /// * `id` - JSON-RPC request ID
-    async fn handle_read_resource(
-        resource_registry: &ResourceRegistry,
-        params: Value,
-        id: Option<u64>,
-    ) -> Result<String> {
-        let request: ReadResourceRequest = serde_json::from_value(params)?;
-
-        match resource_registry.read_resource(&request.uri).await {
-            Ok(contents) => {
-                Ok(serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "result": {
-                        "contents": contents
-                    },
-                    "id": id
-                })
-                .to_string())
-            }
-            Err(_) => {
-                Ok(serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "error": {
-                        "code": -32602,
-                        "message": "资源未找到"
-                    },
-                    "id": id
-                })
-                .to_string())
+    async fn handle_read_resource(&self, session: &Arc<Session>, params: Value) -> Result<Reply, McpError> {
+        let request: ReadResourceRequest = Self::parse_params(params)?;
+        self.policy.authorize(session.identity(), &Action::ReadResource(&request.uri))?;
+        if let (Some(path), Some(roots)) = (file_uri_to_path(&request.uri), session.root_paths().await?)
+            && !is_within_roots(&path, &roots)
+        {
+            return Err(McpError::AccessDenied {
+                target: request.uri,
+                reason: "资源不在客户端允许的根目录内".to_string(),
+            });
+        }
+        let contents = match self.resource_registry.open_resource(&request.uri).await? {
+            ResourceRead::Contents(contents) => contents,
+            ResourceRead::Blob(blob) => {
+                self.metrics.observe_resource_read(blob.encoded_len());
+                return Ok(Reply::Blob(blob));
             }
+        };
+        let size = contents
+            .iter()
+            .map(|content| match content {
+                ResourceContents::Text { text, .. } => text.len(),
+                ResourceContents::Blob { blob, .. } => blob.len(),
+            })
+            .sum();
+        self.metrics.observe_resource_read(size);
+
+        Self::to_value(&ReadResourceResult { contents }).map(Reply::Value)
+    }
+
+    /// Records in the audit log how many list entries the policy hid
+    fn log_hidden(&self, session: &Session, kind: &str, hidden: usize) {
+        if hidden > 0 {
+            let identity = session.identity().map_or(ANONYMOUS_IDENTITY, |identity| identity.name.as_str());
+            tracing::info!(target: "audit", identity, hidden, "策略隐藏了 {} 个{}", hidden, kind);
         }
     }
 
-    /// Creates a clone of the tool registry
+    /// Handles `resources/templates/list` RPC method
     ///
-    /// Used for passing to spawned async tasks.
-    fn clone_tool_registry(&self) -> ToolRegistry {
-        ToolRegistry::new()
+    /// Returns all available resource templates.
+    async fn handle_list_resource_templates(&self) -> Result<Value, McpError> {
+        let resource_templates = self.resource_registry.list_templates();
+        Self::to_value(&ListResourceTemplatesResult { resource_templates })
     }
 
-    /// Creates a clone of the resource registry
+    /// Handles `prompts/list` RPC method
+    ///
+    /// Returns all available prompts.
+    async fn handle_list_prompts(&self) -> Result<Value, McpError> {
+        let prompts = self.prompt_registry.list_prompts();
+        Self::to_value(&ListPromptsResult { prompts })
+    }
+
+    /// Handles `prompts/get` RPC method
+    ///
+    /// Renders a prompt with the provided arguments.
+    ///
+    /// # Arguments
     ///
-    /// Used for passing to spawned async tasks.
-    fn clone_resource_registry(&self) -> ResourceRegistry {
-        ResourceRegistry::new()
+    /// * `params` - RPC parameters containing prompt name and arguments
+    async fn handle_get_prompt(&self, params: Value) -> Result<Value, McpError> {
+        let request: GetPromptRequest = Self::parse_params(params)?;
+        let prompt = self.prompt_registry.get(&request.name).ok_or_else(|| McpError::InvalidParams {
+            message: format!("提示未找到: {}", request.name),
+            details: Some(serde_json::json!({ "prompt": request.name })),
+        })?;
+
+        Self::to_value(&prompt.get(&request.arguments)?)
+    }
+
+    /// Handles `completion/complete` RPC method
+    ///
+    /// Suggests values for a prompt argument, a resource template variable
+    /// or, as an extension, a tool argument. Arguments without completions
+    /// yield an empty list. The authorization policy applies as for
+    /// calls and reads: tools the caller may not call are not completed,
+    /// and template values are only suggested if the caller may read the
+    /// resulting resource. Templates with more than one variable are
+    /// refused, since that resource is not known.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session of the calling client
+    /// * `params` - RPC parameters containing the reference and argument
+    async fn handle_complete(&self, session: &Arc<Session>, params: Value) -> Result<Value, McpError> {
+        let request: CompleteRequest = Self::parse_params(params)?;
+        let argument = &request.argument;
+
+        let candidates = match &request.reference {
+            CompletionReference::Prompt { name } => self
+                .prompt_registry
+                .get(name)
+                .ok_or_else(|| McpError::InvalidParams {
+                    message: format!("提示未找到: {}", name),
+                    details: Some(serde_json::json!({ "prompt": name })),
+                })?
+                .complete(&argument.name, &argument.value),
+            CompletionReference::Resource { uri } => {
+                // Candidates are checked against the policy as the URI they complete,
+                // which is only known when the template has no other variable
+                if uri.matches('{').count() > 1 {
+                    return Err(McpError::InvalidParams {
+                        message: format!("不支持补全含有多个变量的资源模板: {}", uri),
+                        details: Some(serde_json::json!({ "uri": uri })),
+                    });
+                }
+                let roots = session.root_paths().await?;
+                let mut candidates = self.resource_registry.complete_template(
+                    uri,
+                    &argument.name,
+                    &argument.value,
+                    roots.as_deref(),
+                )?;
+                let template_variable = format!("{{{}}}", argument.name);
+                candidates.retain(|candidate| {
+                    let resource = uri.replace(&template_variable, candidate);
+                    self.policy.permits(session.identity(), &Action::ReadResource(&resource))
+                });
+                candidates
+            }
+            CompletionReference::Tool { name } => {
+                self.policy.authorize(session.identity(), &Action::CallTool(name))?;
+                let tool = self
+                    .tool_registry
+                    .get(name)
+                    .ok_or_else(|| McpError::ToolNotFound(name.clone()))?;
+                let ctx = RequestContext::new(Arc::clone(session));
+                tool.complete(&argument.name, &argument.value, &ctx).await?
+            }
+        };
+
+        let completion = Completion::from_candidates(candidates);
+        Self::to_value(&CompleteResult { completion })
+    }
+}
+
+/// A successful outcome of a request handler
+enum Reply {
+    /// A result held in memory
+    Value(Value),
+    /// A binary resource, read and encoded while the response is sent
+    Blob(BlobReader),
+}
+
+/// How a session's client is authenticated
+enum SessionAuth {
+    /// The transport does not authenticate clients
+    Anonymous,
+    /// The client was authenticated while connecting
+    Authenticated(Identity),
+    /// The client must authenticate with its first message
+    Handshake(Arc<dyn Authenticator>),
+}
+
+/// Time a client has to complete the TLS handshake or WebSocket upgrade, or
+/// send its `authenticate` request
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause before accepting again after an accept error
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A listener the accept loop can take connections from
+trait Listener: Send {
+    /// Stream of an accepted connection
+    type Stream: Send + 'static;
+
+    /// Accepts the next connection
+    ///
+    /// # Returns
+    ///
+    /// The stream and, for network listeners, the client's IP address
+    fn accept(&self) -> impl Future<Output = std::io::Result<(Self::Stream, Option<IpAddr>)>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Stream = tokio::net::TcpStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Stream, Option<IpAddr>)> {
+        TcpListener::accept(self)
+            .await
+            .map(|(stream, peer)| (stream, Some(peer.ip())))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for tokio::net::UnixListener {
+    type Stream = tokio::net::UnixStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Stream, Option<IpAddr>)> {
+        tokio::net::UnixListener::accept(self)
+            .await
+            .map(|(stream, _)| (stream, None))
+    }
+}
+
+/// Removes a socket file left behind by a server that is no longer running
+///
+/// # Arguments
+///
+/// * `path` - Path of the socket file
+///
+/// # Returns
+///
+/// An error if the path exists but is not a socket, or if another server
+/// still accepts connections on it
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("无法访问 {}", path.display())),
+    };
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("{} 已存在且不是套接字", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("套接字 {} 正在被另一个服务器使用", path.display());
+    }
+
+    std::fs::remove_file(path).with_context(|| format!("无法删除残留的套接字 {}", path.display()))?;
+    tracing::info!("已删除残留的套接字 {}", path.display());
+    Ok(())
+}
+
+/// Removes the socket file when the listener shuts down
+#[cfg(unix)]
+struct SocketFileGuard(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFileGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            tracing::warn!("无法删除套接字 {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+impl Default for McpServer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -0,0 +1,463 @@
+//! # Sessions
+//!
+//! A `Session` represents one connected client. Besides carrying the
+//! capabilities the client declared in `initialize`, it owns the outbound
+//! message channel, which lets server code send its own requests to the
+//! client (such as `sampling/createMessage`) and await the correlated
+//! response.
+
+use crate::auth::Identity;
+use crate::error::McpError;
+use crate::logging::LoggingLevel;
+use crate::models::{
+    ClientCapabilities, CreateMessageRequest, CreateMessageResult, ElicitRequest, ElicitResult,
+    JsonRpcResponse, ListRootsResult, Root,
+};
+use crate::roots::file_uri_to_path;
+use crate::transport::StreamedMessage;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Default time to wait for the client to answer a server request
+pub const DEFAULT_CLIENT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Source of process-wide unique session identifiers
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Sender half of a pending server-to-client request
+type PendingSender = oneshot::Sender<Result<Value, McpError>>;
+
+/// State of a single client connection
+pub struct Session {
+    /// Unique identifier of this session
+    id: u64,
+    /// Channel of serialized messages to write to the client
+    ///
+    /// `None` once the session is closed, or for detached sessions.
+    outbound: Mutex<Option<mpsc::UnboundedSender<String>>>,
+    /// Channel of streamed messages, for writers that send them in parts
+    ///
+    /// `None` if the session was created without one, or once it is closed.
+    streams: Mutex<Option<mpsc::UnboundedSender<StreamedMessage>>>,
+    /// Server-to-client requests awaiting a response, keyed by request ID
+    ///
+    /// `None` once the client stops sending, since no response can arrive.
+    pending: Mutex<Option<HashMap<u64, PendingSender>>>,
+    /// Allocator for server-side request IDs
+    next_request_id: AtomicU64,
+    /// Capabilities the client declared in `initialize`
+    client_capabilities: RwLock<ClientCapabilities>,
+    /// Roots last fetched from the client, cleared when they change
+    roots: Mutex<Option<Vec<Root>>>,
+    /// Incremented whenever the client reports changed roots
+    roots_generation: AtomicU64,
+    /// Minimum level of log messages forwarded to the client, if any
+    log_level: RwLock<Option<LoggingLevel>>,
+    /// Time to wait for the client to answer a server request
+    request_timeout: Duration,
+    /// Who the client authenticated as, `None` for unauthenticated transports
+    identity: Option<Identity>,
+    /// IP address of the client, `None` for local transports
+    peer: Option<IpAddr>,
+}
+
+impl Session {
+    /// Creates a session that writes outbound messages to `outbound`
+    ///
+    /// # Arguments
+    ///
+    /// * `outbound` - Channel drained by the connection's writer
+    pub fn new(outbound: mpsc::UnboundedSender<String>) -> Self {
+        Session {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            outbound: Mutex::new(Some(outbound)),
+            streams: Mutex::new(None),
+            pending: Mutex::new(Some(HashMap::new())),
+            next_request_id: AtomicU64::new(1),
+            client_capabilities: RwLock::new(ClientCapabilities::default()),
+            roots: Mutex::new(None),
+            roots_generation: AtomicU64::new(0),
+            log_level: RwLock::new(None),
+            request_timeout: DEFAULT_CLIENT_REQUEST_TIMEOUT,
+            identity: None,
+            peer: None,
+        }
+    }
+
+    /// Creates a session that is not connected to any client
+    ///
+    /// Useful for invoking tools outside a connection, e.g. from the CLI
+    /// or tests. Requests to the client fail with `SessionClosed`.
+    pub fn detached() -> Self {
+        let (outbound, _) = mpsc::unbounded_channel();
+        let session = Session::new(outbound);
+        session.close();
+        session
+    }
+
+    /// Sends streamed messages to their own channel instead of assembling them
+    ///
+    /// # Arguments
+    ///
+    /// * `streams` - Channel drained by the connection's writer
+    pub fn with_streams(self, streams: mpsc::UnboundedSender<StreamedMessage>) -> Self {
+        *self.streams.lock().unwrap() = Some(streams);
+        self
+    }
+
+    /// Sets how long to wait for the client to answer server requests
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Sets who the client authenticated as
+    pub fn with_identity(mut self, identity: Option<Identity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Gets who the client authenticated as
+    ///
+    /// # Returns
+    ///
+    /// `None` when the client connected over a transport that does not
+    /// authenticate, such as a Unix socket
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// Sets the IP address the client connected from
+    pub fn with_peer(mut self, peer: Option<IpAddr>) -> Self {
+        self.peer = peer;
+        self
+    }
+
+    /// Gets the IP address the client connected from
+    ///
+    /// # Returns
+    ///
+    /// `None` for local transports such as Unix sockets and stdio
+    pub fn peer(&self) -> Option<IpAddr> {
+        self.peer
+    }
+
+    /// Gets the unique identifier of this session
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Gets the capabilities the client declared in `initialize`
+    pub fn client_capabilities(&self) -> ClientCapabilities {
+        self.client_capabilities.read().unwrap().clone()
+    }
+
+    /// Records the capabilities the client declared in `initialize`
+    pub fn set_client_capabilities(&self, capabilities: ClientCapabilities) {
+        *self.client_capabilities.write().unwrap() = capabilities;
+    }
+
+    /// Gets the minimum log level the client asked for with `logging/setLevel`
+    ///
+    /// `None` until the client selects a level; no log messages are sent before that.
+    pub fn log_level(&self) -> Option<LoggingLevel> {
+        *self.log_level.read().unwrap()
+    }
+
+    /// Sets the minimum level of log messages forwarded to the client
+    pub fn set_log_level(&self, level: LoggingLevel) {
+        *self.log_level.write().unwrap() = Some(level);
+    }
+
+    /// Queues a serialized message for delivery to the client
+    ///
+    /// # Returns
+    ///
+    /// `false` if the session is closed and the message was dropped
+    pub fn send(&self, message: String) -> bool {
+        match self.outbound.lock().unwrap().as_ref() {
+            Some(outbound) => outbound.send(message).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Queues a streamed message for delivery to the client
+    ///
+    /// Sessions created without a streams channel assemble the message
+    /// and queue it like any other.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the session is closed or the message could not be
+    /// assembled, and it was dropped
+    pub async fn send_streamed(&self, message: StreamedMessage) -> bool {
+        if let Some(streams) = self.streams.lock().unwrap().as_ref() {
+            return streams.send(message).is_ok();
+        }
+        if self.outbound.lock().unwrap().is_none() {
+            return false;
+        }
+        match message.into_string().await {
+            Ok(message) => self.send(message),
+            Err(e) => {
+                tracing::warn!("无法组装流式消息: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Sends a notification to the client
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Notification method name
+    /// * `params` - Notification parameters
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), McpError> {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        if self.send(message.to_string()) {
+            Ok(())
+        } else {
+            Err(McpError::SessionClosed)
+        }
+    }
+
+    /// Sends a request to the client and waits for its response
+    ///
+    /// Allocates a fresh server-side request ID, registers it as pending
+    /// and waits up to the session's request timeout for the matching
+    /// response to arrive through [`Session::handle_response`].
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Request method name
+    /// * `params` - Request parameters
+    ///
+    /// # Returns
+    ///
+    /// Result containing the client's result, or an error if the client
+    /// answered with an error, did not answer in time, or disconnected
+    pub async fn request<P, R>(&self, method: &str, params: P) -> Result<R, McpError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(McpError::SessionClosed),
+        };
+
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id,
+        });
+        if !self.send(message.to_string()) {
+            self.remove_pending(id);
+            return Err(McpError::SessionClosed);
+        }
+
+        let outcome = match tokio::time::timeout(self.request_timeout, rx).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_)) => Err(McpError::SessionClosed),
+            Err(_) => {
+                self.remove_pending(id);
+                Err(McpError::RequestTimeout {
+                    method: method.to_string(),
+                    timeout_ms: self.request_timeout.as_millis() as u64,
+                })
+            }
+        };
+
+        // The client's reply is well-formed JSON, so a mismatch is not a parse error
+        serde_json::from_value(outcome?).map_err(|e| {
+            McpError::Internal(anyhow::Error::new(e).context(format!("客户端对 {} 请求的响应格式无效", method)))
+        })
+    }
+
+    /// Routes a response from the client to the request awaiting it
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Response received from the client
+    ///
+    /// # Returns
+    ///
+    /// `false` if no pending request matches the response ID
+    pub fn handle_response(&self, response: JsonRpcResponse) -> bool {
+        let Some(id) = response.id else {
+            return false;
+        };
+        let Some(tx) = self.remove_pending(id) else {
+            return false;
+        };
+
+        let outcome = match (response.error, response.result) {
+            (Some(error), _) => Err(McpError::Client(error)),
+            (None, result) => Ok(result.unwrap_or(Value::Null)),
+        };
+        let _ = tx.send(outcome);
+        true
+    }
+
+    /// Asks the client's LLM to generate a message
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Conversation and sampling parameters
+    ///
+    /// # Returns
+    ///
+    /// Result containing the generated message, or
+    /// `McpError::CapabilityNotSupported` if the client did not declare
+    /// the `sampling` capability
+    pub async fn create_message(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<CreateMessageResult, McpError> {
+        if self.client_capabilities().sampling.is_none() {
+            return Err(McpError::CapabilityNotSupported("sampling".to_string()));
+        }
+        self.request("sampling/createMessage", request).await
+    }
+
+    /// Asks the user, through the client, for additional input
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Message and schema of the requested fields
+    ///
+    /// # Returns
+    ///
+    /// Result containing the user's response, or
+    /// `McpError::CapabilityNotSupported` if the client did not declare
+    /// the `elicitation` capability
+    pub async fn elicit(&self, request: ElicitRequest) -> Result<ElicitResult, McpError> {
+        if !self.supports_elicitation() {
+            return Err(McpError::CapabilityNotSupported("elicitation".to_string()));
+        }
+        self.request("elicitation/create", request).await
+    }
+
+    /// Returns whether the client declared the `elicitation` capability
+    pub fn supports_elicitation(&self) -> bool {
+        self.client_capabilities.read().unwrap().elicitation.is_some()
+    }
+
+    /// Lists the filesystem roots the client exposes
+    ///
+    /// The list is fetched with `roots/list` on first use and cached until
+    /// the client sends `notifications/roots/list_changed`.
+    ///
+    /// # Returns
+    ///
+    /// Result containing the client's roots, or
+    /// `McpError::CapabilityNotSupported` if the client did not declare
+    /// the `roots` capability
+    pub async fn list_roots(&self) -> Result<Vec<Root>, McpError> {
+        if self.client_capabilities.read().unwrap().roots.is_none() {
+            return Err(McpError::CapabilityNotSupported("roots".to_string()));
+        }
+        if let Some(roots) = self.roots.lock().unwrap().clone() {
+            return Ok(roots);
+        }
+
+        let generation = self.roots_generation.load(Ordering::Acquire);
+        let result: ListRootsResult = self.request("roots/list", serde_json::json!({})).await?;
+        // Only cache if the roots did not change while we were asking
+        if self.roots_generation.load(Ordering::Acquire) == generation {
+            *self.roots.lock().unwrap() = Some(result.roots.clone());
+        }
+        Ok(result.roots)
+    }
+
+    /// Gets the local directories of the client's roots
+    ///
+    /// # Returns
+    ///
+    /// Result containing `None` if the client does not expose roots (so
+    /// access is not confined), otherwise the root directories. Roots that
+    /// are not local `file://` URIs are skipped.
+    pub async fn root_paths(&self) -> Result<Option<Vec<PathBuf>>, McpError> {
+        if self.client_capabilities.read().unwrap().roots.is_none() {
+            return Ok(None);
+        }
+        let roots = self.list_roots().await?;
+        Ok(Some(
+            roots
+                .iter()
+                .filter_map(|root| file_uri_to_path(&root.uri))
+                .collect(),
+        ))
+    }
+
+    /// Forgets the cached roots after the client reported a change
+    pub fn invalidate_roots(&self) {
+        self.roots_generation.fetch_add(1, Ordering::AcqRel);
+        self.roots.lock().unwrap().take();
+    }
+
+    /// Fails all requests still waiting for the client
+    ///
+    /// Called once the client stops sending, since no response can
+    /// arrive after that.
+    pub fn cancel_pending(&self) {
+        let pending = self.pending.lock().unwrap().take();
+        for (_, tx) in pending.into_iter().flatten() {
+            let _ = tx.send(Err(McpError::SessionClosed));
+        }
+    }
+
+    /// Removes a pending request, returning its sender if it was present
+    fn remove_pending(&self, id: u64) -> Option<PendingSender> {
+        self.pending.lock().unwrap().as_mut()?.remove(&id)
+    }
+
+    /// Closes the outbound channel and fails all pending requests
+    pub fn close(&self) {
+        self.outbound.lock().unwrap().take();
+        self.streams.lock().unwrap().take();
+        self.cancel_pending();
+    }
+}
+
+/// Context passed to a tool while it executes
+///
+/// Gives the tool access to the session that issued the call, so it can
+/// send its own requests to the client.
+#[derive(Clone)]
+pub struct RequestContext {
+    /// Session of the calling client
+    pub session: Arc<Session>,
+}
+
+impl RequestContext {
+    /// Creates a context for a call made through `session`
+    pub fn new(session: Arc<Session>) -> Self {
+        RequestContext { session }
+    }
+
+    /// Creates a context that is not connected to any client
+    pub fn detached() -> Self {
+        RequestContext::new(Arc::new(Session::detached()))
+    }
+
+    /// Gets who the calling client authenticated as, if anyone
+    pub fn identity(&self) -> Option<&Identity> {
+        self.session.identity()
+    }
+}
@@ -0,0 +1,60 @@
+//! # Shutdown
+//!
+//! A `ShutdownHandle` lets a server be stopped gracefully, either from a
+//! signal handler or programmatically. Once triggered:
+//!
+//! 1. listeners stop accepting new connections;
+//! 2. every session receives a `notifications/shutdown` notice;
+//! 3. in-flight requests get until the configured deadline to finish;
+//! 4. whatever is still running is cancelled and answered with an error.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Method of the notice sent to sessions when the server shuts down
+pub const SHUTDOWN_NOTIFICATION: &str = "notifications/shutdown";
+
+/// Triggers and observes a graceful shutdown
+///
+/// Cheap to clone; all clones share the same state.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    /// Creates a handle that has not been triggered
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        ShutdownHandle {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Starts a graceful shutdown
+    ///
+    /// Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Returns whether shutdown has been triggered
+    pub fn is_shutdown(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Waits until shutdown is triggered
+    ///
+    /// Returns immediately if it already has been.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
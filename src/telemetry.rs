@@ -0,0 +1,472 @@
+//! # Telemetry
+//!
+//! Distributed tracing of requests. Every JSON-RPC request is handled in
+//! a `request` span carrying its method, tool name and ID, nested in a
+//! `session` span for the connection. A client that is itself being
+//! traced may pass its W3C trace context in the `_meta.traceparent` field
+//! of a request; the request span then continues that trace.
+//!
+//! With a `[telemetry]` section in the configuration file, [`otlp`]
+//! creates a `tracing` layer exporting request spans, and every span
+//! opened inside them, to an OpenTelemetry collector over OTLP/HTTP in
+//! its JSON encoding. Spans are sent in batches from a background task
+//! through a queue of bounded size; if the collector cannot be reached
+//! the batch is dropped and logged, and while it is slow to answer the
+//! queue fills up and further spans are dropped and counted, so requests
+//! never wait for the collector and memory use stays bounded. Only `http://` endpoints are
+//! supported, as for a collector running next to the server.
+
+use crate::config::TelemetryConfig;
+use crate::logging::FieldVisitor;
+use anyhow::{Context as _, Result};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Request, Uri};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde_json::{Map, Value, json};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name of the `_meta` key, and of the span field, carrying a W3C trace context
+///
+/// A span that declares this field starts a trace of its own: it
+/// continues the trace given in the field, or a new one if the field is
+/// left empty or is malformed.
+pub const TRACEPARENT_FIELD: &str = "traceparent";
+
+/// Name of the span field whose value marks the span as failed
+pub const ERROR_FIELD: &str = "error";
+
+/// Path appended to an endpoint given without one
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Spans above which a batch is exported without waiting for the interval
+const MAX_BATCH: usize = 512;
+
+/// Most closed spans waiting for the exporter; later ones are dropped
+const MAX_QUEUED_SPANS: usize = 4 * MAX_BATCH;
+
+/// Time between exports of a partial batch
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time the collector has to accept a batch
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest collector response read, in bytes
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// OTLP span kinds
+const SPAN_KIND_INTERNAL: u8 = 1;
+const SPAN_KIND_SERVER: u8 = 2;
+
+/// OTLP status codes
+const STATUS_CODE_UNSET: u8 = 0;
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// A W3C trace context, as carried in a `traceparent` value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    /// ID of the whole trace
+    pub trace_id: [u8; 16],
+    /// ID of the span the context was taken from
+    pub span_id: [u8; 8],
+    /// Whether the caller records the trace
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Parses a `traceparent` value such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    ///
+    /// # Returns
+    ///
+    /// `None` if the value is malformed, or its trace or span ID is all zeros
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = decode_hex::<1>(parts.next()?)?[0];
+        let trace_id = decode_hex::<16>(parts.next()?)?;
+        let span_id = decode_hex::<8>(parts.next()?)?;
+        let flags = decode_hex::<1>(parts.next()?)?[0];
+        // Later versions may append fields; version 00 may not
+        if version == 0xff || (version == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(TraceContext {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// Decodes exactly `N` bytes from lowercase hex digits
+fn decode_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 || !text.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Generates a random, non-zero trace or span ID
+fn random_id<const N: usize>() -> [u8; N] {
+    loop {
+        let mut id = [0; N];
+        rand::fill(&mut id);
+        if id != [0; N] {
+            return id;
+        }
+    }
+}
+
+/// Fields recorded on a span, stored in the span's extensions
+struct SpanFields(Map<String, Value>);
+
+/// Place of an exported span in its trace, stored in the span's extensions
+struct Traced {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    sampled: bool,
+    kind: u8,
+    start: SystemTime,
+}
+
+enum Command {
+    Export(Value),
+    Flush(oneshot::Sender<()>),
+}
+
+/// A `tracing` layer that exports traced spans to an OpenTelemetry collector
+///
+/// Created by [`otlp`].
+pub struct OtlpLayer {
+    commands: mpsc::Sender<Command>,
+    /// Spans dropped because the queue was full, reported by the exporter
+    dropped: Arc<AtomicU64>,
+}
+
+/// Handle to the background task of an [`OtlpLayer`]
+#[derive(Clone)]
+pub struct OtlpHandle {
+    commands: mpsc::Sender<Command>,
+}
+
+impl OtlpHandle {
+    /// Exports the spans waiting for the next batch, and waits until done
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.commands.send(Command::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+}
+
+/// Starts exporting spans to the collector given by a `[telemetry]` section
+///
+/// Must be called from within a Tokio runtime, which runs the export.
+///
+/// # Arguments
+///
+/// * `config` - Collector endpoint and service name
+///
+/// # Returns
+///
+/// The layer to add to the subscriber and a handle to flush it, or an
+/// error if the endpoint is not a valid `http://` URL
+///
+/// # Example
+///
+/// ```ignore
+/// use tracing_subscriber::prelude::*;
+///
+/// let (layer, handle) = telemetry::otlp(&config)?;
+/// tracing_subscriber::registry().with(layer).init();
+/// server.serve(&listen).await?;
+/// handle.flush().await;
+/// ```
+pub fn otlp(config: &TelemetryConfig) -> Result<(OtlpLayer, OtlpHandle)> {
+    let endpoint = Endpoint::parse(&config.otlp_endpoint)?;
+    let resource = json!({
+        "attributes": [{ "key": "service.name", "value": { "stringValue": config.service_name } }],
+    });
+    let (commands, receiver) = mpsc::channel(MAX_QUEUED_SPANS);
+    let dropped = Arc::new(AtomicU64::new(0));
+    tokio::spawn(export_spans(receiver, endpoint, resource, dropped.clone()));
+    Ok((
+        OtlpLayer {
+            commands: commands.clone(),
+            dropped,
+        },
+        OtlpHandle { commands },
+    ))
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        let mut fields = visitor.into_fields();
+
+        let traced = if attrs.metadata().fields().field(TRACEPARENT_FIELD).is_some() {
+            let remote = fields
+                .remove(TRACEPARENT_FIELD)
+                .and_then(|traceparent| traceparent.as_str().and_then(TraceContext::parse));
+            // The root of a trace also carries the fields of the spans
+            // around it, such as the session ID
+            for outer in span.scope().skip(1) {
+                if let Some(SpanFields(outer)) = outer.extensions().get::<SpanFields>() {
+                    for (name, value) in outer {
+                        fields.entry(name.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+            }
+            Some(Traced {
+                trace_id: remote.map_or_else(random_id, |remote| remote.trace_id),
+                span_id: random_id(),
+                parent_span_id: remote.map(|remote| remote.span_id),
+                sampled: remote.is_none_or(|remote| remote.sampled),
+                kind: SPAN_KIND_SERVER,
+                start: SystemTime::now(),
+            })
+        } else {
+            span.parent().and_then(|parent| {
+                parent.extensions().get::<Traced>().map(|parent| Traced {
+                    trace_id: parent.trace_id,
+                    span_id: random_id(),
+                    parent_span_id: Some(parent.span_id),
+                    sampled: parent.sampled,
+                    kind: SPAN_KIND_INTERNAL,
+                    start: SystemTime::now(),
+                })
+            })
+        };
+
+        let mut extensions = span.extensions_mut();
+        extensions.insert(SpanFields(fields));
+        if let Some(traced) = traced {
+            extensions.insert(traced);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        if let Some(SpanFields(fields)) = span.extensions_mut().get_mut::<SpanFields>() {
+            fields.extend(visitor.into_fields());
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let (Some(traced), Some(SpanFields(fields))) = (extensions.get::<Traced>(), extensions.get::<SpanFields>())
+        else {
+            return;
+        };
+        if !traced.sampled {
+            return;
+        }
+
+        let status = match fields.get(ERROR_FIELD) {
+            Some(Value::String(message)) => json!({ "code": STATUS_CODE_ERROR, "message": message }),
+            Some(other) => json!({ "code": STATUS_CODE_ERROR, "message": other.to_string() }),
+            None => json!({ "code": STATUS_CODE_UNSET }),
+        };
+        let attributes: Vec<Value> = fields
+            .iter()
+            .filter(|(name, _)| *name != ERROR_FIELD)
+            .map(|(name, value)| json!({ "key": name, "value": attribute_value(value) }))
+            .collect();
+        let mut exported = json!({
+            "traceId": encode_hex(&traced.trace_id),
+            "spanId": encode_hex(&traced.span_id),
+            "name": span.name(),
+            "kind": traced.kind,
+            "startTimeUnixNano": unix_nanos(traced.start).to_string(),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()).to_string(),
+            "attributes": attributes,
+            "status": status,
+        });
+        if let Some(parent_span_id) = traced.parent_span_id {
+            exported["parentSpanId"] = Value::String(encode_hex(&parent_span_id));
+        }
+        if let Err(TrySendError::Full(_)) = self.commands.try_send(Command::Export(exported)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Converts a span field to an OTLP `AnyValue`
+fn attribute_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(value) => json!({ "boolValue": value }),
+        Value::Number(number) if number.is_f64() => json!({ "doubleValue": number }),
+        // 64-bit integers are strings in the JSON encoding
+        Value::Number(number) => json!({ "intValue": number.to_string() }),
+        Value::String(value) => json!({ "stringValue": value }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+/// Collects spans into batches of at most [`MAX_BATCH`] and posts them to the collector
+async fn export_spans(mut commands: mpsc::Receiver<Command>, endpoint: Endpoint, resource: Value, dropped: Arc<AtomicU64>) {
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+    let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Export(span)) => {
+                    batch.push(span);
+                    if batch.len() >= MAX_BATCH {
+                        endpoint.export(&resource, &mut batch).await;
+                    }
+                }
+                Some(Command::Flush(done)) => {
+                    report_dropped(&dropped);
+                    endpoint.export(&resource, &mut batch).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    endpoint.export(&resource, &mut batch).await;
+                    break;
+                }
+            },
+            _ = interval.tick() => {
+                report_dropped(&dropped);
+                endpoint.export(&resource, &mut batch).await;
+            }
+        }
+    }
+}
+
+/// Logs the spans dropped since the last report, if any
+fn report_dropped(dropped: &AtomicU64) {
+    let count = dropped.swap(0, Ordering::Relaxed);
+    if count > 0 {
+        tracing::warn!("追踪跨度队列已满，丢弃了 {} 个追踪跨度", count);
+    }
+}
+
+/// Where the collector accepts spans
+struct Endpoint {
+    uri: Uri,
+    /// HTTP client, keeping connections to the collector open between batches
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl Endpoint {
+    fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .with_context(|| format!("OTLP 端点必须以 http:// 开头: {}", url))?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if host.is_empty() {
+            anyhow::bail!("OTLP 端点缺少主机: {}", url);
+        }
+        let path = match path {
+            "" | "/" => TRACES_PATH,
+            path => path,
+        };
+        let uri = format!("http://{}{}", host, path)
+            .parse()
+            .with_context(|| format!("无效的 OTLP 端点: {}", url))?;
+        Ok(Endpoint {
+            uri,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        })
+    }
+
+    /// Posts a batch, emptying it; failures are logged and the spans dropped
+    async fn export(&self, resource: &Value, batch: &mut Vec<Value>) {
+        if batch.is_empty() {
+            return;
+        }
+        let count = batch.len();
+        let request = json!({
+            "resourceSpans": [{
+                "resource": resource,
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "spans": std::mem::take(batch),
+                }],
+            }],
+        });
+        match tokio::time::timeout(EXPORT_TIMEOUT, self.post(&request)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("导出 {} 个追踪跨度失败: {:#}", count, e),
+            Err(_) => tracing::warn!("导出 {} 个追踪跨度超时", count),
+        }
+    }
+
+    async fn post(&self, body: &Value) -> Result<()> {
+        let request = Request::post(&self.uri)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_vec(body)?)))?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .with_context(|| format!("无法连接 OTLP 收集器 {}", self.uri))?;
+        let status = response.status();
+        // Read to the end so the connection can be reused for the next batch
+        let body = Limited::new(response.into_body(), MAX_RESPONSE_SIZE)
+            .collect()
+            .await
+            .map(|body| body.to_bytes())
+            .unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("OTLP 收集器拒绝了请求: {} {}", status, String::from_utf8_lossy(&body));
+        }
+        Ok(())
+    }
+}
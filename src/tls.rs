@@ -0,0 +1,95 @@
+//! # TLS
+//!
+//! Optional TLS termination for the TCP and WebSocket listeners, enabled
+//! by a `[tls]` section in the configuration file. When the section also
+//! names a client CA, every client must present a certificate signed by
+//! it (mutual TLS), and the certificate's subject becomes the client's
+//! identity.
+
+use crate::auth::Identity;
+use crate::config::TlsConfig;
+use anyhow::{Context, Result};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore};
+
+/// Builds a TLS acceptor from the `[tls]` section
+///
+/// # Arguments
+///
+/// * `config` - Paths of the server certificate, its key and the optional client CA
+///
+/// # Returns
+///
+/// Result containing the acceptor, or an error if a file cannot be read
+/// or does not hold a usable certificate or key
+pub fn load_acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = load_certs(&config.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .with_context(|| format!("无法读取私钥 {}", config.key_path.display()))?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .context("TLS 协议版本配置错误")?;
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("无效的客户端 CA 证书 {}", ca_path.display()))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("无法创建客户端证书校验器")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .context("证书与私钥不匹配")?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Gets the identity of a client from its verified certificate
+///
+/// The identity is the subject's common name, or the whole subject if it
+/// has none.
+///
+/// # Arguments
+///
+/// * `stream` - An accepted TLS connection
+///
+/// # Returns
+///
+/// `None` if the client presented no certificate
+pub fn peer_identity<S>(stream: &tokio_rustls::server::TlsStream<S>) -> Option<Identity> {
+    let cert = stream.get_ref().1.peer_certificates()?.first()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let subject = cert.subject();
+    let name = subject
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| subject.to_string());
+    Some(Identity::new(name))
+}
+
+/// Reads all certificates from a PEM file
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("无法读取证书 {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("{} 中没有证书", path.display());
+    }
+    Ok(certs)
+}
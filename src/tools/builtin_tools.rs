@@ -3,18 +3,34 @@
 //! Contains implementations of default tools provided by the MCP Server.
 //! New tools should be added here and registered in ToolRegistry.
 
-use crate::models::{CallToolResult, Content, Property, ToolInputSchema};
-use anyhow::{Context, Result};
+use super::tool_handler::ToolError;
+use crate::error::McpError;
+use crate::models::{CallToolResult, Content, ElicitAction, ElicitRequest, Property, ToolInputSchema};
+use crate::roots::is_within_roots;
+use crate::sandbox::{PathKind, PathSandbox};
+use crate::session::RequestContext;
+use crate::wildcard::WildcardPattern;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// File search tool implementation
 ///
 /// Searches for files matching a pattern in a specified directory.
-#[derive(Clone, Copy)]
-pub struct SearchFilesTool;
+#[derive(Clone, Default)]
+pub struct SearchFilesTool {
+    /// Confines the directories searched and the files reported
+    sandbox: Arc<PathSandbox>,
+}
 
 impl SearchFilesTool {
+    /// Creates the tool confined by the given sandbox
+    pub fn new(sandbox: Arc<PathSandbox>) -> Self {
+        SearchFilesTool { sandbox }
+    }
+
     /// Gets the input schema for file search parameters
     ///
     /// # Returns
@@ -46,15 +62,25 @@ impl SearchFilesTool {
 
     /// Executes the file search tool
     ///
+    /// Walks the directory recursively and reports files whose name matches
+    /// the pattern. The directory must pass the server's path sandbox, and
+    /// files the sandbox denies are not reported. When the client exposes
+    /// roots, the directory defaults to the first root and must lie inside
+    /// one of them.
+    ///
     /// # Arguments
     ///
     /// * `arguments` - JSON value containing:
     ///   - `pattern` (required): Search pattern
-    ///   - `directory` (optional): Directory to search in (defaults to ".")
+    ///   - `directory` (optional): Directory to search in (defaults to the
+    ///     first client root, or "." if the client exposes none)
+    /// * `ctx` - Context of the call, used to look up the client's roots
     ///
     /// # Returns
     ///
-    /// Result containing search results or error if pattern is missing
+    /// Result containing search results, or `ToolError::InvalidArguments`
+    /// if pattern is missing or the directory is refused by the sandbox or
+    /// outside the client's roots
     ///
     /// # Example
     ///
@@ -64,31 +90,229 @@ impl SearchFilesTool {
     ///     "pattern": "*.txt",
     ///     "directory": "/tmp"
     /// });
-    /// let result = tool.execute(args).await?;
+    /// let result = tool.execute(args, &RequestContext::detached()).await?;
     /// ```
-    pub async fn execute(&self, arguments: Value) -> Result<CallToolResult> {
+    pub async fn execute(&self, arguments: Value, ctx: &RequestContext) -> Result<CallToolResult, ToolError> {
         let pattern = arguments["pattern"]
             .as_str()
-            .context("缺少 pattern 参数")?;
-        let directory = arguments["directory"]
-            .as_str()
-            .unwrap_or(".");
+            .ok_or_else(|| ToolError::InvalidArguments("缺少 pattern 参数".to_string()))?
+            .to_string();
 
-        // Mock implementation - in real scenario, use globbing or similar
-        let text = format!(
-            "在目录 {} 中搜索模式 '{}'\n找到以下文件:\n1. /path/to/file1.txt\n2. /path/to/file2.log",
-            directory, pattern
-        );
+        let roots = ctx
+            .session
+            .root_paths()
+            .await
+            .map_err(|e| ToolError::Execution(anyhow::Error::new(e).context("无法获取客户端根目录")))?;
+        let directory = match (arguments["directory"].as_str(), &roots) {
+            (Some(directory), _) => PathBuf::from(directory),
+            (None, Some(roots)) => roots
+                .first()
+                .cloned()
+                .ok_or_else(|| ToolError::InvalidArguments("客户端未提供任何根目录".to_string()))?,
+            (None, None) => PathBuf::from("."),
+        };
+        let directory = self.sandbox.check(&directory, PathKind::Directory).map_err(|e| {
+            if e.is_violation() {
+                ToolError::InvalidArguments(e.to_string())
+            } else {
+                ToolError::Execution(e.into())
+            }
+        })?;
+        if let Some(roots) = &roots
+            && !is_within_roots(&directory, roots)
+        {
+            return Err(ToolError::InvalidArguments(format!(
+                "目录 {} 不在客户端允许的根目录内",
+                directory.display()
+            )));
+        }
+
+        let matcher = WildcardPattern::new(&pattern);
+        let search_dir = directory.clone();
+        // Stops the walk if this call is abandoned, e.g. on timeout
+        let cancel = CancelOnDrop::default();
+        let cancelled = Arc::clone(&cancel.0);
+        let sandbox = Arc::clone(&self.sandbox);
+        let found = tokio::task::spawn_blocking(move || search_directory(&search_dir, &matcher, &sandbox, &cancelled))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        let mut text = format!("在目录 {} 中搜索模式 '{}'\n", directory.display(), pattern);
+        if found.is_empty() {
+            text.push_str("未找到匹配的文件");
+        } else {
+            text.push_str("找到以下文件:");
+            for (i, path) in found.iter().enumerate() {
+                text.push_str(&format!("\n{}. {}", i + 1, path.display()));
+            }
+            if found.len() == MAX_SEARCH_RESULTS {
+                text.push_str(&format!("\n(结果已截断为前 {} 个)", MAX_SEARCH_RESULTS));
+            }
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    /// Suggests values for an argument of this tool
+    ///
+    /// Completes `directory` with subdirectories of the path typed so far.
+    /// Only directories the sandbox permits, or that lead to one of its
+    /// roots, are suggested; when the client exposes roots, they must also
+    /// be inside a client root (or lead to one).
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - Name of the argument being completed
+    /// * `value` - What the user has typed so far
+    /// * `ctx` - Context of the request, used to look up the client's roots
+    ///
+    /// # Returns
+    ///
+    /// Result containing the matching values, sorted
+    pub async fn complete(&self, argument: &str, value: &str, ctx: &RequestContext) -> Result<Vec<String>, McpError> {
+        if argument != "directory" {
+            return Ok(Vec::new());
+        }
+        let roots = ctx.session.root_paths().await?;
+        if value.is_empty()
+            && let Some(roots) = roots
+        {
+            return Ok(roots.iter().map(|root| root.display().to_string()).collect());
+        }
 
-        Ok(CallToolResult {
-            content: vec![Content {
-                type_: "text".to_string(),
-                text,
-            }],
+        let value = value.to_string();
+        let sandbox = Arc::clone(&self.sandbox);
+        let candidates = tokio::task::spawn_blocking(move || complete_directory(&value, &sandbox, roots.as_deref()))
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(candidates)
+    }
+}
+
+/// Lists subdirectories whose path starts with `value`
+///
+/// Hidden directories are only suggested once the typed name starts with a dot.
+fn complete_directory(value: &str, sandbox: &PathSandbox, roots: Option<&[PathBuf]>) -> Vec<String> {
+    // Split into the directory to list and the partial name typed so far
+    let (parent, partial) = match value.rfind('/') {
+        Some(i) => (&value[..=i], &value[i + 1..]),
+        None => ("", value),
+    };
+    let list_dir = if parent.is_empty() { Path::new(".") } else { Path::new(parent) };
+    let Ok(entries) = std::fs::read_dir(list_dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(partial) && (!name.starts_with('.') || partial.starts_with('.')))
+        .map(|name| format!("{}{}", parent, name))
+        .filter(|candidate| {
+            let path = Path::new(candidate);
+            sandbox.permits(path)
+                || std::fs::canonicalize(path).is_ok_and(|path| sandbox.roots().iter().any(|root| root.starts_with(&path)))
+        })
+        .filter(|candidate| {
+            roots.is_none_or(|roots| {
+                let path = Path::new(candidate);
+                is_within_roots(path, roots) || roots.iter().any(|root| root.starts_with(path))
+            })
         })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Maximum number of files reported by a single search
+const MAX_SEARCH_RESULTS: usize = 100;
+
+/// Maximum directory depth visited by a search
+const MAX_SEARCH_DEPTH: usize = 16;
+
+/// Sets a flag when dropped, telling blocking work to give up
+#[derive(Default)]
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
     }
 }
 
+/// Recursively collects files under `directory` whose name matches `matcher`
+///
+/// Symlinked directories are not followed and unreadable subdirectories
+/// are skipped; only an unreadable starting directory is an error. Paths
+/// the sandbox denies are skipped, as are special files and symlinks that
+/// do not resolve to a permitted regular file. The walk stops early, with
+/// what was found so far, once `cancelled` is set.
+fn search_directory(
+    directory: &Path,
+    matcher: &WildcardPattern,
+    sandbox: &PathSandbox,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut stack = vec![(directory.to_path_buf(), 0)];
+
+    'walk: while let Some((dir, depth)) = stack.pop() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if depth == 0 => {
+                return Err(anyhow::Error::new(e).context(format!("无法读取目录 {}", dir.display())));
+            }
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if sandbox.is_denied(&path) {
+                continue;
+            }
+            if file_type.is_dir() {
+                if depth + 1 < MAX_SEARCH_DEPTH {
+                    stack.push((path, depth + 1));
+                }
+                continue;
+            }
+            let is_file = file_type.is_file() || (file_type.is_symlink() && sandbox.check(&path, PathKind::File).is_ok());
+            if is_file && matcher.matches(&entry.file_name().to_string_lossy()) {
+                found.push(path);
+                if found.len() == MAX_SEARCH_RESULTS {
+                    break 'walk;
+                }
+            }
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+/// Cities suggested when completing the `city` argument
+pub const KNOWN_CITIES: &[&str] = &[
+    "北京", "上海", "广州", "深圳", "杭州", "成都", "武汉", "西安", "南京", "重庆",
+    "Beijing", "Shanghai", "Guangzhou", "Shenzhen", "Hangzhou", "Chengdu", "Wuhan", "Xi'an",
+    "Nanjing", "Chongqing",
+];
+
+/// Lists known cities starting with `value`, ignoring ASCII case
+pub fn complete_city(value: &str) -> Vec<String> {
+    let value = value.to_lowercase();
+    KNOWN_CITIES
+        .iter()
+        .filter(|city| city.to_lowercase().starts_with(&value))
+        .map(|city| city.to_string())
+        .collect()
+}
+
 /// Weather query tool implementation
 ///
 /// Retrieves weather information for a specified city.
@@ -120,26 +344,34 @@ impl WeatherTool {
 
     /// Executes the weather query tool
     ///
+    /// If `city` is missing and the client supports elicitation, the user
+    /// is asked for it through the client before continuing.
+    ///
     /// # Arguments
     ///
     /// * `arguments` - JSON value containing:
     ///   - `city` (required): The city name to get weather for
+    /// * `ctx` - Context of the call, used to ask the user for a missing city
     ///
     /// # Returns
     ///
-    /// Result containing weather information or error if city is missing
+    /// Result containing weather information, `ToolError::InvalidArguments`
+    /// if city is missing and cannot be asked for, or `ToolError::Execution`
+    /// if the user declines or cancels
     ///
     /// # Example
     ///
     /// ```ignore
     /// let tool = WeatherTool;
     /// let args = serde_json::json!({ "city": "Beijing" });
-    /// let result = tool.execute(args).await?;
+    /// let result = tool.execute(args, &RequestContext::detached()).await?;
     /// ```
-    pub async fn execute(&self, arguments: Value) -> Result<CallToolResult> {
-        let city = arguments["city"]
-            .as_str()
-            .context("缺少 city 参数")?;
+    pub async fn execute(&self, arguments: Value, ctx: &RequestContext) -> Result<CallToolResult, ToolError> {
+        let city = match arguments["city"].as_str() {
+            Some(city) => city.to_string(),
+            None if ctx.session.supports_elicitation() => self.elicit_city(ctx).await?,
+            None => return Err(ToolError::InvalidArguments("缺少 city 参数".to_string())),
+        };
 
         // Mock implementation - in real scenario, call weather API
         let text = format!(
@@ -147,12 +379,58 @@ impl WeatherTool {
             city
         );
 
-        Ok(CallToolResult {
-            content: vec![Content {
-                type_: "text".to_string(),
-                text,
-            }],
-        })
+        Ok(CallToolResult::success(vec![Content::text(text)]))
     }
-}
 
+    /// Suggests values for an argument of this tool
+    ///
+    /// Completes `city` from a list of well-known cities.
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - Name of the argument being completed
+    /// * `value` - What the user has typed so far
+    pub fn complete(&self, argument: &str, value: &str) -> Vec<String> {
+        match argument {
+            "city" => complete_city(value),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Asks the user for the city to query
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Context of the call
+    ///
+    /// # Returns
+    ///
+    /// Result containing the city the user entered, or an execution error
+    /// if the user declines, cancels or submits no city
+    async fn elicit_city(&self, ctx: &RequestContext) -> Result<String, ToolError> {
+        let request = ElicitRequest {
+            message: "请输入要查询天气的城市".to_string(),
+            requested_schema: self.schema(),
+        };
+        let response = ctx
+            .session
+            .elicit(request)
+            .await
+            .map_err(|e| ToolError::Execution(anyhow::Error::new(e).context("无法获取城市")))?;
+
+        match response.action {
+            ElicitAction::Accept => response
+                .content
+                .as_ref()
+                .and_then(|content| content.get("city"))
+                .and_then(Value::as_str)
+                .filter(|city| !city.trim().is_empty())
+                .map(|city| city.trim().to_string())
+                .ok_or_else(|| ToolError::Execution(anyhow::anyhow!("用户未提供城市"))),
+            ElicitAction::Decline => Err(ToolError::Execution(anyhow::anyhow!(
+                "用户拒绝提供城市，无法查询天气"
+            ))),
+            ElicitAction::Cancel => Err(ToolError::Execution(anyhow::anyhow!("用户取消了天气查询"))),
+        }
+    }
+}
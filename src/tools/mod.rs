@@ -9,6 +9,6 @@
 pub mod tool_handler;
 pub mod builtin_tools;
 
-pub use tool_handler::ToolRegistry;
+pub use tool_handler::{QueuePolicy, ToolError, ToolLimits, ToolRegistry};
 
 
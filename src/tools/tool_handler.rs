@@ -3,16 +3,69 @@
 //! Manages tool registration and execution using an enum-based approach
 //! for type safety and zero-cost abstractions.
 
-use crate::models::{Tool, ToolInputSchema, CallToolResult};
-use anyhow::Result;
+use crate::config::ToolConfig;
+use crate::error::McpError;
+use crate::models::{Tool, ToolAnnotations, ToolInputSchema, CallToolResult};
+use crate::rate_limit::RateLimiter;
+use crate::sandbox::PathSandbox;
+use crate::session::RequestContext;
+use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use super::builtin_tools::{SearchFilesTool, WeatherTool};
 
+/// Errors a tool can report from `execute`
+///
+/// The variants map to different outcomes on the wire: invalid arguments
+/// are a protocol error, while an execution failure is returned as a
+/// normal tool result with `isError: true`.
+#[derive(Debug, Error)]
+pub enum ToolError {
+    /// The arguments do not satisfy the tool's input schema
+    #[error("无效参数: {0}")]
+    InvalidArguments(String),
+    /// The tool ran but could not complete its work
+    #[error("{0:#}")]
+    Execution(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ToolError {
+    fn from(error: anyhow::Error) -> Self {
+        ToolError::Execution(error)
+    }
+}
+
+/// What a call does when its tool is already running at its concurrency limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueuePolicy {
+    /// Wait for a running call to finish
+    #[default]
+    Wait,
+    /// Fail right away with `McpError::ToolOverloaded`
+    Reject,
+}
+
+/// Execution limits of a tool
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolLimits {
+    /// How long one execution may run, `None` for no limit
+    pub timeout: Option<Duration>,
+    /// Most executions running at once across all sessions, `None` for no limit
+    pub max_concurrency: Option<usize>,
+    /// What a call does when `max_concurrency` executions are already running
+    pub queue: QueuePolicy,
+}
+
 /// Enumeration of all available tool implementations
 ///
 /// This enum provides type-safe tool dispatch without dynamic allocation.
 /// Each variant holds a concrete tool implementation.
+#[derive(Clone)]
 pub enum ToolImpl {
     /// File search tool for finding files in the filesystem
     SearchFiles(SearchFilesTool),
@@ -37,6 +90,44 @@ impl ToolImpl {
         }
     }
 
+    /// Gets the built-in behavioral hints for this tool
+    ///
+    /// These can be overridden per tool in the configuration file.
+    pub fn annotations(&self) -> ToolAnnotations {
+        match self {
+            ToolImpl::SearchFiles(_) => ToolAnnotations {
+                title: Some("搜索文件".to_string()),
+                read_only_hint: Some(true),
+                destructive_hint: Some(false),
+                idempotent_hint: Some(true),
+                open_world_hint: Some(false),
+            },
+            ToolImpl::Weather(_) => ToolAnnotations {
+                title: Some("天气查询".to_string()),
+                read_only_hint: Some(true),
+                destructive_hint: Some(false),
+                idempotent_hint: Some(true),
+                open_world_hint: Some(true),
+            },
+        }
+    }
+
+    /// Gets the built-in execution limits for this tool
+    ///
+    /// These can be overridden per tool in the configuration file.
+    pub fn limits(&self) -> ToolLimits {
+        match self {
+            // Walks whole directory trees, so only a couple may run at once
+            ToolImpl::SearchFiles(_) => ToolLimits {
+                timeout: Some(Duration::from_secs(30)),
+                max_concurrency: Some(2),
+                queue: QueuePolicy::Wait,
+            },
+            // May wait on the user through elicitation, so it has no timeout of its own
+            ToolImpl::Weather(_) => ToolLimits::default(),
+        }
+    }
+
     /// Gets the input schema for this tool
     ///
     /// Describes what parameters the tool accepts.
@@ -52,14 +143,34 @@ impl ToolImpl {
     /// # Arguments
     ///
     /// * `arguments` - JSON value containing tool arguments
+    /// * `ctx` - Context of the call, giving access to the calling session
     ///
     /// # Returns
     ///
-    /// Result containing the tool's output or an error
-    pub async fn execute(&self, arguments: Value) -> Result<CallToolResult> {
+    /// Result containing the tool's output or a `ToolError`
+    pub async fn execute(&self, arguments: Value, ctx: &RequestContext) -> Result<CallToolResult, ToolError> {
         match self {
-            ToolImpl::SearchFiles(tool) => tool.execute(arguments).await,
-            ToolImpl::Weather(tool) => tool.execute(arguments).await,
+            ToolImpl::SearchFiles(tool) => tool.execute(arguments, ctx).await,
+            ToolImpl::Weather(tool) => tool.execute(arguments, ctx).await,
+        }
+    }
+
+    /// Suggests values for an argument of this tool
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - Name of the argument being completed
+    /// * `value` - What the user has typed so far
+    /// * `ctx` - Context of the request, giving access to the calling session
+    ///
+    /// # Returns
+    ///
+    /// Result containing the matching values; empty if the argument has
+    /// no completions
+    pub async fn complete(&self, argument: &str, value: &str, ctx: &RequestContext) -> Result<Vec<String>, McpError> {
+        match self {
+            ToolImpl::SearchFiles(tool) => tool.complete(argument, value, ctx).await,
+            ToolImpl::Weather(tool) => Ok(tool.complete(argument, value)),
         }
     }
 }
@@ -67,9 +178,16 @@ impl ToolImpl {
 /// Registry for managing all available tools
 ///
 /// Provides centralized access to tools and their metadata.
+#[derive(Clone)]
 pub struct ToolRegistry {
     /// Map of tool names to tool implementations
     tools: HashMap<String, ToolImpl>,
+    /// Per-tool settings from the configuration file
+    configs: HashMap<String, ToolConfig>,
+    /// Execution slots of tools with a concurrency limit, shared by all clones
+    slots: HashMap<String, Arc<Semaphore>>,
+    /// Call rate limits of tools that have one, shared by all clones
+    rates: HashMap<String, RateLimiter>,
 }
 
 impl ToolRegistry {
@@ -79,11 +197,61 @@ impl ToolRegistry {
     ///
     /// A new `ToolRegistry` with default tools registered
     pub fn new() -> Self {
+        Self::with_config(HashMap::new())
+    }
+
+    /// Creates a tool registry with all built-in tools and per-tool settings
+    ///
+    /// # Arguments
+    ///
+    /// * `configs` - Tool settings keyed by tool name, as loaded from the
+    ///   `[tools.<name>]` sections of the configuration file
+    ///
+    /// # Returns
+    ///
+    /// A new `ToolRegistry` with default tools registered
+    pub fn with_config(configs: HashMap<String, ToolConfig>) -> Self {
         let mut tools = HashMap::new();
-        tools.insert("search_files".to_string(), ToolImpl::SearchFiles(SearchFilesTool));
+        tools.insert("search_files".to_string(), ToolImpl::SearchFiles(SearchFilesTool::default()));
         tools.insert("get_weather".to_string(), ToolImpl::Weather(WeatherTool));
 
-        ToolRegistry { tools }
+        let mut registry = ToolRegistry {
+            tools,
+            configs,
+            slots: HashMap::new(),
+            rates: HashMap::new(),
+        };
+        registry.slots = registry
+            .tools
+            .values()
+            .filter_map(|tool| {
+                let max_concurrency = registry.limits(tool).max_concurrency?;
+                Some((tool.name().to_string(), Arc::new(Semaphore::new(max_concurrency))))
+            })
+            .collect();
+        registry.rates = registry
+            .configs
+            .iter()
+            .filter_map(|(name, config)| Some((name.clone(), RateLimiter::new(config.rate_limit.as_ref()?)?)))
+            .collect();
+        registry
+    }
+
+    /// Confines the filesystem tools with the given sandbox
+    ///
+    /// Tools use the default sandbox, which only applies the built-in
+    /// deny patterns, until this is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `sandbox` - Path rules from the `[sandbox]` section
+    pub fn with_sandbox(mut self, sandbox: Arc<PathSandbox>) -> Self {
+        for tool in self.tools.values_mut() {
+            if let ToolImpl::SearchFiles(search) = tool {
+                *search = SearchFilesTool::new(Arc::clone(&sandbox));
+            }
+        }
+        self
     }
 
     /// Gets a tool by name
@@ -102,6 +270,7 @@ impl ToolRegistry {
     /// Gets a list of all available tools
     ///
     /// Converts internal tool implementations to public Tool structures.
+    /// Configured annotations are merged over each tool's built-in hints.
     ///
     /// # Returns
     ///
@@ -113,10 +282,94 @@ impl ToolRegistry {
                 name: tool.name().to_string(),
                 description: tool.description().to_string(),
                 input_schema: tool.schema(),
+                annotations: Some(self.annotations(tool)),
             })
             .collect()
     }
 
+    /// Resolves the effective annotations of a tool
+    fn annotations(&self, tool: &ToolImpl) -> ToolAnnotations {
+        let mut annotations = tool.annotations();
+        if let Some(overrides) = self
+            .configs
+            .get(tool.name())
+            .and_then(|config| config.annotations.as_ref())
+        {
+            annotations.merge(overrides);
+        }
+        annotations
+    }
+
+    /// Resolves the effective execution limits of a tool
+    ///
+    /// Configured values replace the tool's built-in limits; a configured
+    /// `timeout_secs` or `max_concurrency` of 0 removes the limit.
+    pub fn limits(&self, tool: &ToolImpl) -> ToolLimits {
+        let mut limits = tool.limits();
+        if let Some(config) = self.configs.get(tool.name()) {
+            if let Some(secs) = config.timeout_secs {
+                limits.timeout = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            if let Some(max_concurrency) = config.max_concurrency {
+                limits.max_concurrency = (max_concurrency > 0).then_some(max_concurrency);
+            }
+            if let Some(queue) = config.queue {
+                limits.queue = queue;
+            }
+        }
+        limits
+    }
+
+    /// Reserves an execution slot for a tool with a concurrency limit
+    ///
+    /// Depending on the tool's queue policy, waits for a slot to free up
+    /// or fails right away when none is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `tool` - The tool about to run
+    ///
+    /// # Returns
+    ///
+    /// A permit to hold while the tool runs, `None` if the tool has no
+    /// concurrency limit, or `McpError::ToolOverloaded`
+    pub async fn acquire_slot(&self, tool: &ToolImpl) -> Result<Option<OwnedSemaphorePermit>, McpError> {
+        let Some(slots) = self.slots.get(tool.name()) else {
+            return Ok(None);
+        };
+        let limits = self.limits(tool);
+        let overloaded = || McpError::ToolOverloaded {
+            tool: tool.name().to_string(),
+            max_concurrency: limits.max_concurrency.unwrap_or_default(),
+        };
+
+        let permit = match limits.queue {
+            QueuePolicy::Wait => Arc::clone(slots).acquire_owned().await.map_err(|_| overloaded())?,
+            QueuePolicy::Reject => Arc::clone(slots).try_acquire_owned().map_err(|_| overloaded())?,
+        };
+        Ok(Some(permit))
+    }
+
+    /// Counts a call against a tool's rate limit
+    ///
+    /// # Arguments
+    ///
+    /// * `tool` - The tool about to run
+    /// * `caller` - Who is calling, as given by [`caller_key`](crate::rate_limit::caller_key)
+    ///
+    /// # Returns
+    ///
+    /// `McpError::RateLimited` if the caller has used up its calls for now
+    pub fn check_rate(&self, tool: &ToolImpl, caller: &str) -> Result<(), McpError> {
+        let Some(limiter) = self.rates.get(tool.name()) else {
+            return Ok(());
+        };
+        limiter.try_acquire(caller).map_err(|retry_after| McpError::RateLimited {
+            tool: Some(tool.name().to_string()),
+            retry_after_ms: retry_after.as_millis() as u64,
+        })
+    }
+
     /// Gets all tool names
     ///
     /// # Returns
@@ -134,12 +387,3 @@ impl Default for ToolRegistry {
     }
 }
 
-impl Clone for ToolRegistry {
-    /// Creates a clone of the tool registry
-    ///
-    /// This is used to pass tool registry to spawned async tasks.
-    fn clone(&self) -> Self {
-        ToolRegistry::new()
-    }
-}
-
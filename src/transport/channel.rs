@@ -0,0 +1,88 @@
+//! # In-Memory Channel Transport
+//!
+//! Carries messages over a pair of tokio channels, for running a session
+//! inside the same process without any sockets.
+
+use super::{MessageReader, MessageWriter, Transport};
+use std::io;
+use tokio::sync::mpsc;
+
+/// One end of an in-memory message channel
+///
+/// Messages sent on one end of a [`ChannelTransport::pair`] are received
+/// on the other.
+pub struct ChannelTransport {
+    inbound: mpsc::UnboundedReceiver<String>,
+    outbound: mpsc::UnboundedSender<String>,
+}
+
+impl ChannelTransport {
+    /// Creates a transport from the channels it reads from and writes to
+    ///
+    /// # Arguments
+    ///
+    /// * `inbound` - Messages received from the peer
+    /// * `outbound` - Messages sent to the peer
+    pub fn new(inbound: mpsc::UnboundedReceiver<String>, outbound: mpsc::UnboundedSender<String>) -> Self {
+        ChannelTransport { inbound, outbound }
+    }
+
+    /// Creates two connected ends
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (server_end, client_end) = ChannelTransport::pair();
+    /// tokio::spawn(Arc::new(server).serve_transport(server_end));
+    /// ```
+    pub fn pair() -> (Self, Self) {
+        let (a_tx, a_rx) = mpsc::unbounded_channel();
+        let (b_tx, b_rx) = mpsc::unbounded_channel();
+        (ChannelTransport::new(a_rx, b_tx), ChannelTransport::new(b_rx, a_tx))
+    }
+}
+
+impl Transport for ChannelTransport {
+    type Reader = ChannelReader;
+    type Writer = ChannelWriter;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        (
+            ChannelReader { inbound: self.inbound },
+            ChannelWriter {
+                outbound: Some(self.outbound),
+            },
+        )
+    }
+}
+
+/// Receiving half of a [`ChannelTransport`]
+pub struct ChannelReader {
+    inbound: mpsc::UnboundedReceiver<String>,
+}
+
+impl MessageReader for ChannelReader {
+    async fn recv(&mut self) -> io::Result<Option<String>> {
+        Ok(self.inbound.recv().await)
+    }
+}
+
+/// Sending half of a [`ChannelTransport`]
+pub struct ChannelWriter {
+    /// `None` once closed, which ends the peer's stream of messages
+    outbound: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl MessageWriter for ChannelWriter {
+    async fn send(&mut self, message: String) -> io::Result<()> {
+        self.outbound
+            .as_ref()
+            .and_then(|outbound| outbound.send(message).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "通道已关闭"))
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.outbound.take();
+        Ok(())
+    }
+}
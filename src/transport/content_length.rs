@@ -0,0 +1,154 @@
+//! # Content-Length Framed Transport
+//!
+//! Carries messages framed like the Language Server Protocol: a header
+//! block ending in an empty line, whose `Content-Length` gives the size in
+//! bytes of the body that follows.
+//!
+//! ```text
+//! Content-Length: 42\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","method":"ping","id":1}
+//! ```
+
+use super::{DEFAULT_MAX_MESSAGE_SIZE, MessageReader, MessageTooLarge, MessageWriter, StreamedMessage, Transport};
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+
+/// Longest header line accepted, in bytes
+const MAX_HEADER_LINE: usize = 1024;
+
+/// `Content-Length` framed messages over a byte stream
+pub struct ContentLengthTransport<S> {
+    stream: S,
+    max_message_size: usize,
+}
+
+impl<S> ContentLengthTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    /// Wraps a byte stream
+    pub fn new(stream: S) -> Self {
+        ContentLengthTransport {
+            stream,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the largest body accepted, in bytes
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl<S> Transport for ContentLengthTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    type Reader = ContentLengthReader<S>;
+    type Writer = ContentLengthWriter<S>;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (reader, writer) = tokio::io::split(self.stream);
+        (
+            ContentLengthReader {
+                reader: BufReader::new(reader),
+                max_message_size: self.max_message_size,
+            },
+            ContentLengthWriter { writer },
+        )
+    }
+}
+
+/// Receiving half of a [`ContentLengthTransport`]
+pub struct ContentLengthReader<S> {
+    reader: BufReader<ReadHalf<S>>,
+    max_message_size: usize,
+}
+
+impl<S> MessageReader for ContentLengthReader<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    async fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut content_length = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            // Header lines are bounded too, so a client cannot stream an endless header
+            let read = (&mut self.reader)
+                .take(MAX_HEADER_LINE as u64)
+                .read_line(&mut line)
+                .await?;
+            if read == MAX_HEADER_LINE && !line.ends_with('\n') {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "消息头过长"));
+            }
+            if read == 0 {
+                if content_length.is_none() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "消息头不完整"));
+            }
+
+            let header = line.trim_end_matches(['\r', '\n']);
+            if header.is_empty() {
+                // Tolerate stray blank lines between messages
+                if content_length.is_some() {
+                    break;
+                }
+                continue;
+            }
+            // Other headers, such as Content-Type, are ignored
+            if let Some((name, value)) = header.split_once(':')
+                && name.trim().eq_ignore_ascii_case("content-length")
+            {
+                let length = value.trim().parse::<usize>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("无效的 Content-Length: {}", value.trim()))
+                })?;
+                content_length = Some(length);
+            }
+        }
+
+        let content_length = content_length.unwrap_or_default();
+        if content_length > self.max_message_size {
+            return Err(MessageTooLarge {
+                limit: self.max_message_size,
+            }
+            .into_io_error());
+        }
+        let mut body = vec![0u8; content_length];
+        self.reader.read_exact(&mut body).await?;
+        String::from_utf8(body)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Sending half of a [`ContentLengthTransport`]
+pub struct ContentLengthWriter<S> {
+    writer: WriteHalf<S>,
+}
+
+impl<S> MessageWriter for ContentLengthWriter<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    async fn send(&mut self, message: String) -> io::Result<()> {
+        let header = format!("Content-Length: {}\r\n\r\n", message.len());
+        self.writer.write_all(header.as_bytes()).await?;
+        self.writer.write_all(message.as_bytes()).await?;
+        self.writer.flush().await
+    }
+
+    async fn send_streamed(&mut self, message: StreamedMessage) -> io::Result<()> {
+        let header = format!("Content-Length: {}\r\n\r\n", message.byte_len());
+        self.writer.write_all(header.as_bytes()).await?;
+        message.write_to(&mut self.writer).await?;
+        self.writer.flush().await
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.writer.shutdown().await
+    }
+}
@@ -0,0 +1,128 @@
+//! # Newline-Delimited Transport
+//!
+//! Carries one JSON-RPC message per line over any byte stream, such as a
+//! TCP or Unix socket or stdin/stdout.
+
+use super::{DEFAULT_MAX_MESSAGE_SIZE, MessageReader, MessageTooLarge, MessageWriter, StreamedMessage, Transport};
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+
+/// Newline-delimited messages over a byte stream
+pub struct LineTransport<S> {
+    stream: S,
+    max_message_size: usize,
+}
+
+impl<S> LineTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    /// Wraps a byte stream
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (socket, _) = listener.accept().await?;
+    /// Arc::new(server).serve_transport(LineTransport::new(socket)).await?;
+    /// ```
+    pub fn new(stream: S) -> Self {
+        LineTransport {
+            stream,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the longest line accepted, in bytes, excluding the newline
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl<S> Transport for LineTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    type Reader = LineReader<S>;
+    type Writer = LineWriter<S>;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (reader, writer) = tokio::io::split(self.stream);
+        (
+            LineReader {
+                reader: BufReader::new(reader),
+                max_message_size: self.max_message_size,
+            },
+            LineWriter { writer },
+        )
+    }
+}
+
+/// Receiving half of a [`LineTransport`]
+pub struct LineReader<S> {
+    reader: BufReader<ReadHalf<S>>,
+    max_message_size: usize,
+}
+
+impl<S> MessageReader for LineReader<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    async fn recv(&mut self) -> io::Result<Option<String>> {
+        // Read buffer by buffer so an endless line is refused before it is held in memory
+        let mut line = Vec::new();
+        loop {
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+
+            let (chunk, used, complete) = match available.iter().position(|&byte| byte == b'\n') {
+                Some(end) => (&available[..end], end + 1, true),
+                None => (available, available.len(), false),
+            };
+            if line.len() + chunk.len() > self.max_message_size {
+                return Err(MessageTooLarge {
+                    limit: self.max_message_size,
+                }
+                .into_io_error());
+            }
+            line.extend_from_slice(chunk);
+            self.reader.consume(used);
+            if complete {
+                break;
+            }
+        }
+
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Sending half of a [`LineTransport`]
+pub struct LineWriter<S> {
+    writer: WriteHalf<S>,
+}
+
+impl<S> MessageWriter for LineWriter<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    async fn send(&mut self, message: String) -> io::Result<()> {
+        self.writer.write_all(message.as_bytes()).await?;
+        self.writer.write_all(b"\n").await
+    }
+
+    async fn send_streamed(&mut self, message: StreamedMessage) -> io::Result<()> {
+        message.write_to(&mut self.writer).await?;
+        self.writer.write_all(b"\n").await
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.writer.shutdown().await
+    }
+}
@@ -0,0 +1,199 @@
+//! # Transports
+//!
+//! A transport carries whole JSON-RPC messages between the server and one
+//! client. `McpServer::serve_transport` runs a session over any type
+//! implementing [`Transport`], so the dispatch and session logic is shared
+//! by every way of connecting.
+//!
+//! Built-in transports:
+//!
+//! - [`LineTransport`]: newline-delimited messages over a byte stream
+//! - [`ContentLengthTransport`]: LSP-style `Content-Length` framing over a byte stream
+//! - [`WebSocketTransport`]: one message per WebSocket text message
+//! - [`ChannelTransport`]: an in-memory pair of tokio channels
+//!
+//! Library users can plug in their own by implementing [`Transport`],
+//! [`MessageReader`] and [`MessageWriter`].
+//!
+//! Readers that enforce a size limit report an oversized message as an
+//! `io::Error` wrapping [`MessageTooLarge`], so the server can answer with
+//! a JSON-RPC error before closing the connection.
+//!
+//! Responses carrying a large binary resource are sent as a
+//! [`StreamedMessage`], whose blob is read and encoded while it is being
+//! written. The line and `Content-Length` transports write it chunk by
+//! chunk and the WebSocket transport sends it as a fragmented message, so
+//! the blob is never held in memory; other transports assemble it first.
+
+pub mod channel;
+pub mod content_length;
+pub mod line;
+pub mod websocket;
+
+pub use channel::ChannelTransport;
+pub use content_length::ContentLengthTransport;
+pub use line::LineTransport;
+pub use websocket::WebSocketTransport;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Default limit on the size of a single inbound message, in bytes
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Size of the chunks the body of a [`StreamedMessage`] is read and encoded in
+///
+/// A multiple of 3 so that each chunk encodes to base64 without padding
+/// and the encoded chunks can simply be concatenated.
+pub const STREAM_CHUNK_SIZE: usize = 3 * 16 * 1024;
+
+/// An inbound message exceeded the transport's size limit
+#[derive(Debug, Error)]
+#[error("消息超过 {limit} 字节的上限")]
+pub struct MessageTooLarge {
+    /// The limit that was exceeded, in bytes
+    pub limit: usize,
+}
+
+impl MessageTooLarge {
+    /// Wraps the error in an `io::Error` for returning from a reader
+    pub fn into_io_error(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+
+    /// Finds a `MessageTooLarge` inside an `io::Error` returned by a reader
+    pub fn from_io_error(error: &io::Error) -> Option<&MessageTooLarge> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// An outbound message whose middle part is read from a stream as it is sent
+///
+/// The message is `head`, then the base64 encoding of exactly `body_len`
+/// bytes of the body, then `tail`. Base64 needs no escaping inside a JSON
+/// string, so `head` can end by opening a string that `tail` closes.
+///
+/// The length of the message is known before the body is read. If the
+/// body ends early, sending fails partway and the connection is closed,
+/// since the client cannot tell where the message was cut.
+pub struct StreamedMessage {
+    head: Option<String>,
+    body: Pin<Box<dyn AsyncRead + Send>>,
+    /// Bytes of the body still to be read
+    remaining: u64,
+    /// Encoded length of the body
+    encoded_len: usize,
+    tail: Option<String>,
+}
+
+impl StreamedMessage {
+    /// Creates a message around the encoding of a body
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - Text sent before the body
+    /// * `body` - Source of the bytes to encode
+    /// * `body_len` - Number of bytes read from the body
+    /// * `tail` - Text sent after the body
+    pub fn new(head: String, body: impl AsyncRead + Send + 'static, body_len: u64, tail: String) -> Self {
+        StreamedMessage {
+            head: Some(head),
+            body: Box::pin(body),
+            remaining: body_len,
+            encoded_len: (body_len as usize).div_ceil(3) * 4,
+            tail: Some(tail),
+        }
+    }
+
+    /// Gets the length of the whole message, in bytes
+    pub fn byte_len(&self) -> usize {
+        self.head.as_ref().map_or(0, String::len) + self.encoded_len + self.tail.as_ref().map_or(0, String::len)
+    }
+
+    /// Reads and encodes the next part of the message
+    ///
+    /// # Returns
+    ///
+    /// The head, then each encoded chunk of the body, then the tail, and
+    /// `None` once the whole message has been returned
+    pub async fn next_chunk(&mut self) -> io::Result<Option<String>> {
+        if let Some(head) = self.head.take() {
+            return Ok(Some(head));
+        }
+        if self.remaining > 0 {
+            let mut chunk = vec![0u8; self.remaining.min(STREAM_CHUNK_SIZE as u64) as usize];
+            self.body.read_exact(&mut chunk).await?;
+            self.remaining -= chunk.len() as u64;
+            return Ok(Some(STANDARD.encode(&chunk)));
+        }
+        Ok(self.tail.take())
+    }
+
+    /// Writes the message to a byte stream one chunk at a time
+    pub async fn write_to<W: AsyncWrite + Unpin>(mut self, writer: &mut W) -> io::Result<()> {
+        while let Some(chunk) = self.next_chunk().await? {
+            writer.write_all(chunk.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Assembles the whole message in memory
+    pub async fn into_string(mut self) -> io::Result<String> {
+        let mut message = String::with_capacity(self.byte_len());
+        while let Some(chunk) = self.next_chunk().await? {
+            message.push_str(&chunk);
+        }
+        Ok(message)
+    }
+}
+
+/// A bidirectional message channel to one client
+///
+/// The server reads and writes concurrently, so a transport is split into
+/// independently owned halves before use.
+pub trait Transport: Send + 'static {
+    /// Half that receives messages from the client
+    type Reader: MessageReader;
+    /// Half that sends messages to the client
+    type Writer: MessageWriter;
+
+    /// Splits the transport into its receiving and sending halves
+    fn split(self) -> (Self::Reader, Self::Writer);
+}
+
+/// Receiving half of a transport
+pub trait MessageReader: Send + 'static {
+    /// Receives the next message
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` once the client has stopped sending, otherwise the next
+    /// serialized JSON-RPC message
+    fn recv(&mut self) -> impl Future<Output = io::Result<Option<String>>> + Send;
+}
+
+/// Sending half of a transport
+pub trait MessageWriter: Send + 'static {
+    /// Sends one serialized JSON-RPC message
+    fn send(&mut self, message: String) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// Sends one message whose body is read as it is sent
+    ///
+    /// The default implementation assembles the message in memory and
+    /// sends it with [`MessageWriter::send`]; transports that can write a
+    /// message in parts override it.
+    fn send_streamed(&mut self, message: StreamedMessage) -> impl Future<Output = io::Result<()>> + Send {
+        async move {
+            let message = message.into_string().await?;
+            self.send(message).await
+        }
+    }
+
+    /// Flushes and closes the sending side once the session is over
+    fn close(&mut self) -> impl Future<Output = io::Result<()>> + Send;
+}
@@ -0,0 +1,257 @@
+//! # WebSocket Transport
+//!
+//! Carries one JSON-RPC message per WebSocket text message. Messages are
+//! sent in a single frame, except streamed ones, which are fragmented
+//! into one frame per chunk. Pings are answered and close frames
+//! acknowledged by the protocol layer; binary frames are ignored.
+
+use super::{DEFAULT_MAX_MESSAGE_SIZE, MessageReader, MessageTooLarge, MessageWriter, StreamedMessage, Transport};
+use crate::auth::{Authenticator, Identity, bearer_token};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::error::CapacityError;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL, WWW_AUTHENTICATE};
+use tokio_tungstenite::tungstenite::http::{HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+
+/// WebSocket subprotocol negotiated with clients
+pub const WEBSOCKET_SUBPROTOCOL: &str = "mcp";
+
+/// A server-side WebSocket connection
+pub struct WebSocketTransport<S> {
+    websocket: WebSocketStream<S>,
+    max_message_size: usize,
+}
+
+impl<S> WebSocketTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    /// Performs the server side of the opening handshake on a stream
+    ///
+    /// The `mcp` subprotocol is selected when the client offers it.
+    /// Clients offering no subprotocol are accepted as is; clients
+    /// offering only other subprotocols are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream the WebSocket runs on, usually a TCP socket
+    pub async fn accept(stream: S) -> io::Result<Self> {
+        Self::accept_with_max_message_size(stream, DEFAULT_MAX_MESSAGE_SIZE).await
+    }
+
+    /// Performs the opening handshake, limiting the size of inbound messages
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream the WebSocket runs on
+    /// * `max_message_size` - Largest message accepted, in bytes
+    pub async fn accept_with_max_message_size(stream: S, max_message_size: usize) -> io::Result<Self> {
+        let websocket = Self::handshake(stream, max_message_size, negotiate_subprotocol).await?;
+        Ok(WebSocketTransport {
+            websocket,
+            max_message_size,
+        })
+    }
+
+    /// Performs the opening handshake, requiring an `Authorization: Bearer` header
+    ///
+    /// Clients without a valid token are refused with HTTP 401. This
+    /// upgrade request is the only place the server reads the header, as
+    /// there is no plain HTTP transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream the WebSocket runs on
+    /// * `max_message_size` - Largest message accepted, in bytes
+    /// * `authenticator` - Checks the presented token
+    ///
+    /// # Returns
+    ///
+    /// The transport and the identity the client authenticated as
+    pub async fn accept_authenticated(
+        stream: S,
+        max_message_size: usize,
+        authenticator: &dyn Authenticator,
+    ) -> io::Result<(Self, Identity)> {
+        let mut identity = None;
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, response: Response| {
+            identity = Some(authenticate(request, authenticator)?);
+            negotiate_subprotocol(request, response)
+        };
+        let websocket = Self::handshake(stream, max_message_size, callback).await?;
+        let identity = identity.ok_or_else(|| io::Error::other("握手未完成认证"))?;
+        Ok((
+            WebSocketTransport {
+                websocket,
+                max_message_size,
+            },
+            identity,
+        ))
+    }
+
+    /// Runs the server side of the handshake with a header callback
+    async fn handshake<C>(stream: S, max_message_size: usize, callback: C) -> io::Result<WebSocketStream<S>>
+    where
+        C: tokio_tungstenite::tungstenite::handshake::server::Callback + Unpin,
+    {
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(max_message_size))
+            .max_frame_size(Some(max_message_size));
+        tokio_tungstenite::accept_hdr_async_with_config(stream, callback, Some(config))
+            .await
+            .map_err(io::Error::other)
+    }
+}
+
+impl<S> Transport for WebSocketTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Reader = WebSocketReader<S>;
+    type Writer = WebSocketWriter<S>;
+
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (sink, source) = self.websocket.split();
+        (
+            WebSocketReader {
+                source,
+                max_message_size: self.max_message_size,
+            },
+            WebSocketWriter { sink },
+        )
+    }
+}
+
+/// Receiving half of a [`WebSocketTransport`]
+pub struct WebSocketReader<S> {
+    source: SplitStream<WebSocketStream<S>>,
+    max_message_size: usize,
+}
+
+impl<S> MessageReader for WebSocketReader<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    async fn recv(&mut self) -> io::Result<Option<String>> {
+        while let Some(frame) = self.source.next().await {
+            match frame {
+                Ok(WsMessage::Text(text)) => return Ok(Some(text.to_string())),
+                Ok(WsMessage::Binary(_)) => tracing::warn!("忽略 WebSocket 二进制帧"),
+                // Pings are answered and close frames acknowledged while reading
+                Ok(_) => {}
+                Err(WsError::ConnectionClosed | WsError::AlreadyClosed) => return Ok(None),
+                Err(WsError::Capacity(
+                    CapacityError::MessageTooLong { .. } | CapacityError::TooManyHeaders,
+                )) => {
+                    return Err(MessageTooLarge {
+                        limit: self.max_message_size,
+                    }
+                    .into_io_error());
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Sending half of a [`WebSocketTransport`]
+pub struct WebSocketWriter<S> {
+    sink: SplitSink<WebSocketStream<S>, WsMessage>,
+}
+
+impl<S> MessageWriter for WebSocketWriter<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    async fn send(&mut self, message: String) -> io::Result<()> {
+        self.sink
+            .send(WsMessage::text(message))
+            .await
+            .map_err(io::Error::other)
+    }
+
+    /// Sends the message as a text frame per chunk, continued until the last
+    async fn send_streamed(&mut self, mut message: StreamedMessage) -> io::Result<()> {
+        let mut opcode = OpCode::Data(Data::Text);
+        let mut chunk = message.next_chunk().await?.unwrap_or_default();
+        loop {
+            let next = message.next_chunk().await?;
+            let frame = Frame::message(chunk, opcode, next.is_none());
+            self.sink.send(WsMessage::Frame(frame)).await.map_err(io::Error::other)?;
+            match next {
+                Some(next) => {
+                    chunk = next;
+                    opcode = OpCode::Data(Data::Continue);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        match self.sink.close().await {
+            Ok(()) | Err(WsError::ConnectionClosed | WsError::AlreadyClosed) => Ok(()),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+/// Checks the bearer token in the handshake's `Authorization` header
+// The error type is dictated by tungstenite's handshake callback
+#[allow(clippy::result_large_err)]
+fn authenticate(request: &Request, authenticator: &dyn Authenticator) -> Result<Identity, ErrorResponse> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    match token.and_then(|token| authenticator.authenticate(token)) {
+        Some(identity) => Ok(identity),
+        None => {
+            tracing::warn!("WebSocket 客户端认证失败");
+            let mut error = ErrorResponse::new(Some("认证失败".to_string()));
+            *error.status_mut() = StatusCode::UNAUTHORIZED;
+            error
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            Err(error)
+        }
+    }
+}
+
+/// Selects the `mcp` subprotocol during the WebSocket handshake
+// The error type is dictated by tungstenite's handshake callback
+#[allow(clippy::result_large_err)]
+fn negotiate_subprotocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    let offered: Vec<&str> = request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    if offered.is_empty() {
+        return Ok(response);
+    }
+    if !offered.contains(&WEBSOCKET_SUBPROTOCOL) {
+        let mut error = ErrorResponse::new(Some(format!("不支持的子协议，需要 {}", WEBSOCKET_SUBPROTOCOL)));
+        *error.status_mut() = StatusCode::BAD_REQUEST;
+        return Err(error);
+    }
+
+    response
+        .headers_mut()
+        .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(WEBSOCKET_SUBPROTOCOL));
+    Ok(response)
+}
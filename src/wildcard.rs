@@ -0,0 +1,125 @@
+//! # Wildcard Patterns
+//!
+//! Shell-style patterns used for file names, tool names and resource URIs.
+//! `*` matches any run of characters (including `/`), `?` matches a
+//! single character and `{a,b}` matches either alternative.
+//!
+//! Patterns may come from clients, so they are never expanded: a pattern
+//! compiles to a small automaton whose size grows linearly with the
+//! pattern, and matching tracks every position the pattern could be at
+//! simultaneously. This keeps both memory and time linear in the pattern
+//! for each character of the name, however many `{a,b}` groups it has.
+
+/// A pattern supporting `*`, `?` and `{a,b}` alternatives
+#[derive(Debug, Clone)]
+pub struct WildcardPattern {
+    /// Compiled automaton; state `i` continues at `i + 1` unless it says otherwise
+    states: Vec<State>,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    /// Consumes one given character
+    Char(char),
+    /// Consumes any one character (`?`)
+    AnyChar,
+    /// Consumes any run of characters, possibly empty (`*`)
+    Star,
+    /// Starts of the alternatives of a `{a,b}` group
+    Split(Vec<usize>),
+    /// End of an alternative, continuing after the group
+    Jump(usize),
+    /// The whole pattern has matched
+    Match,
+}
+
+impl WildcardPattern {
+    /// Compiles a pattern such as `*.{txt,log}`
+    ///
+    /// A `{` without a closing `}` is taken literally, as is a `{` inside
+    /// a group: groups do not nest.
+    pub fn new(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut states = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let close = (chars[i] == '{')
+                .then(|| chars[i..].iter().position(|&c| c == '}').map(|offset| i + offset))
+                .flatten();
+            let Some(close) = close else {
+                states.push(State::token(chars[i]));
+                i += 1;
+                continue;
+            };
+
+            let split = states.len();
+            states.push(State::Split(Vec::new()));
+            let mut starts = Vec::new();
+            let mut jumps = Vec::new();
+            for alternative in chars[i + 1..close].split(|&c| c == ',') {
+                starts.push(states.len());
+                states.extend(alternative.iter().map(|&c| State::token(c)));
+                jumps.push(states.len());
+                states.push(State::Jump(0));
+            }
+            let end = states.len();
+            states[split] = State::Split(starts);
+            for jump in jumps {
+                states[jump] = State::Jump(end);
+            }
+            i = close + 1;
+        }
+        states.push(State::Match);
+        WildcardPattern { states }
+    }
+
+    /// Checks whether a name matches the pattern
+    pub fn matches(&self, name: &str) -> bool {
+        let mut current = vec![false; self.states.len()];
+        self.enter(0, &mut current);
+        for c in name.chars() {
+            let mut next = vec![false; self.states.len()];
+            for (state, _) in current.iter().enumerate().filter(|(_, active)| **active) {
+                match &self.states[state] {
+                    State::Char(expected) if *expected == c => self.enter(state + 1, &mut next),
+                    State::AnyChar => self.enter(state + 1, &mut next),
+                    State::Star => self.enter(state, &mut next),
+                    _ => {}
+                }
+            }
+            if !next.contains(&true) {
+                return false;
+            }
+            current = next;
+        }
+        current[self.states.len() - 1]
+    }
+
+    /// Marks a state active, along with every state reachable from it
+    /// without consuming a character
+    fn enter(&self, state: usize, active: &mut [bool]) {
+        let mut pending = vec![state];
+        while let Some(state) = pending.pop() {
+            if active[state] {
+                continue;
+            }
+            active[state] = true;
+            match &self.states[state] {
+                State::Split(starts) => pending.extend(starts),
+                State::Jump(target) => pending.push(*target),
+                State::Star => pending.push(state + 1),
+                State::Char(_) | State::AnyChar | State::Match => {}
+            }
+        }
+    }
+}
+
+impl State {
+    fn token(c: char) -> Self {
+        match c {
+            '*' => State::Star,
+            '?' => State::AnyChar,
+            c => State::Char(c),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 # test_client.py
 import json
+import os
 import socket
 
 def test_mcp_server():
@@ -7,6 +8,20 @@ def test_mcp_server():
     sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
     sock.connect(('127.0.0.1', 8080))
 
+    # 服务器要求认证时，首条消息必须是 authenticate 请求
+    token = os.environ.get("MCP_AUTH_TOKEN")
+    if token:
+        request = {
+            "jsonrpc": "2.0",
+            "method": "authenticate",
+            "params": {"token": token},
+            "id": 0
+        }
+        sock.sendall((json.dumps(request) + '\n').encode())
+        response = sock.recv(4096).decode()
+        print("认证响应:")
+        print(json.dumps(json.loads(response), indent=2))
+
     # 发送工具列表请求
     request = {
         "jsonrpc": "2.0",
@@ -84,7 +84,7 @@ mod tests {
         assert!(result.is_ok(), "Reading hosts file should succeed");
         let contents = result.unwrap();
         assert!(!contents.is_empty(), "Hosts file should have content");
-        assert_eq!(contents[0].type_, "text");
+        assert!(matches!(contents[0], ResourceContents::Text { .. }));
     }
 
     #[tokio::test]
@@ -94,6 +94,167 @@ mod tests {
         let result = registry.read_resource("file:///nonexistent").await;
         
         assert!(result.is_err(), "Reading nonexistent resource should fail");
+        let error = result.unwrap_err();
+        assert!(matches!(error, mcp_server_rust::McpError::ResourceNotFound { .. }));
+        assert_eq!(error.code(), mcp_server_rust::error::RESOURCE_NOT_FOUND);
+        assert_eq!(error.data().unwrap()["uri"], "file:///nonexistent");
+    }
+
+    #[tokio::test]
+    async fn test_resource_read_failure_keeps_cause() {
+        // Test that I/O failures surface the URI and the underlying cause
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gone.txt");
+        std::fs::write(&path, "soon deleted").unwrap();
+
+        let mut registry = mcp_server_rust::resources::ResourceRegistry::new();
+        let resource = registry.register_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let error = registry.read_resource(&resource.uri).await.unwrap_err();
+        let rpc = error.to_json_rpc();
+        assert_eq!(rpc.code, mcp_server_rust::error::INTERNAL_ERROR);
+        let data = rpc.data.unwrap();
+        assert_eq!(data["uri"], resource.uri);
+        let cause = data["cause"].as_array().unwrap();
+        assert!(cause.len() >= 2, "cause chain should include the I/O error: {:?}", cause);
+    }
+
+    #[test]
+    fn test_mcp_error_codes_and_data() {
+        // Test that each error kind maps to a stable code and payload
+        use mcp_server_rust::McpError;
+        use mcp_server_rust::error;
+
+        let parse = serde_json::from_str::<McpMessage>("{not json").unwrap_err();
+        assert_eq!(McpError::Parse(parse).code(), error::PARSE_ERROR);
+
+        // Failing to serialize a result is the server's fault, not the client's
+        let unserializable = serde_json::to_value(std::collections::HashMap::from([((1, 2), 3)])).unwrap_err();
+        let internal = McpError::from(anyhow::Error::new(unserializable).context("无法序列化响应"));
+        assert_eq!(internal.code(), error::INTERNAL_ERROR);
+
+        let missing = McpError::MethodNotFound("foo/bar".to_string());
+        assert_eq!(missing.code(), error::METHOD_NOT_FOUND);
+        assert_eq!(missing.data().unwrap()["method"], "foo/bar");
+
+        let tool = McpError::ToolNotFound("nope".to_string());
+        assert_eq!(tool.code(), error::METHOD_NOT_FOUND);
+        assert_eq!(tool.data().unwrap()["tool"], "nope");
+
+        let invalid = McpError::InvalidParams {
+            message: "缺少 city 参数".to_string(),
+            details: Some(json!({ "tool": "get_weather" })),
+        };
+        let rpc = serde_json::to_value(invalid.to_json_rpc()).unwrap();
+        assert_eq!(rpc["code"], error::INVALID_PARAMS);
+        assert_eq!(rpc["message"], "无效参数: 缺少 city 参数");
+        assert_eq!(rpc["data"]["details"]["tool"], "get_weather");
+
+        let internal = McpError::from(anyhow::anyhow!("root cause").context("outer"));
+        assert_eq!(internal.code(), error::INTERNAL_ERROR);
+        assert_eq!(internal.data().unwrap()["cause"], json!(["outer", "root cause"]));
+
+        // Errors without data omit the member entirely
+        let rpc = serde_json::to_value(McpError::InvalidRequest("x".to_string()).to_json_rpc()).unwrap();
+        assert!(rpc.get("data").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resource_read_registered_text_file() {
+        // Test that a registered text file is returned as text contents
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "# Notes\nhello").unwrap();
+
+        let mut registry = mcp_server_rust::resources::ResourceRegistry::new();
+        let resource = registry.register_file(&path).unwrap();
+        assert!(resource.uri.starts_with("file://"));
+        assert_eq!(resource.mime_type, "text/markdown");
+
+        let contents = registry.read_resource(&resource.uri).await.unwrap();
+        match &contents[0] {
+            ResourceContents::Text { uri, mime_type, text } => {
+                assert_eq!(uri, &resource.uri);
+                assert_eq!(mime_type.as_deref(), Some("text/markdown"));
+                assert_eq!(text, "# Notes\nhello");
+            }
+            other => panic!("expected text contents, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource_read_binary_file_as_blob() {
+        // Test that binary files are sniffed and returned as base64 blobs,
+        // including files larger than a single encoding chunk
+        use base64::Engine;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image-without-extension");
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        bytes.extend((0..200_000u32).map(|i| (i % 251) as u8));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut registry = mcp_server_rust::resources::ResourceRegistry::new();
+        let resource = registry.register_file(&path).unwrap();
+        assert_eq!(resource.mime_type, "image/png");
+
+        let contents = registry.read_resource(&resource.uri).await.unwrap();
+        match &contents[0] {
+            ResourceContents::Blob { mime_type, blob, .. } => {
+                assert_eq!(mime_type.as_deref(), Some("image/png"));
+                let decoded = base64::engine::general_purpose::STANDARD.decode(blob).unwrap();
+                assert_eq!(decoded, bytes);
+            }
+            other => panic!("expected blob contents, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource_read_size_limit() {
+        // Test that files over the maximum resource size are refused unread
+        use mcp_server_rust::McpError;
+        use mcp_server_rust::error::RESOURCE_TOO_LARGE;
+
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.txt");
+        let large = dir.path().join("large.bin");
+        std::fs::write(&small, "x".repeat(1024)).unwrap();
+        std::fs::write(&large, vec![0u8; 1025]).unwrap();
+
+        let mut registry = mcp_server_rust::resources::ResourceRegistry::new().with_max_resource_size(1024);
+        let small = registry.register_file(&small).unwrap();
+        let large = registry.register_file(&large).unwrap();
+
+        assert!(registry.read_resource(&small.uri).await.is_ok());
+        let error = registry.read_resource(&large.uri).await.unwrap_err();
+        assert!(matches!(error, McpError::ResourceTooLarge { size: 1025, limit: 1024, .. }));
+        assert_eq!(error.code(), RESOURCE_TOO_LARGE);
+        assert_eq!(error.data().unwrap()["uri"], large.uri);
+    }
+
+    #[test]
+    fn test_mime_detection() {
+        // Test extension lookup and content sniffing
+        use mcp_server_rust::resources::mime;
+        use std::path::Path;
+
+        assert_eq!(mime::from_extension(Path::new("a/report.PDF")), Some("application/pdf"));
+        assert_eq!(mime::from_extension(Path::new("archive.tar")), Some("application/x-tar"));
+        assert_eq!(mime::from_extension(Path::new("noext")), None);
+
+        assert_eq!(mime::sniff(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(mime::sniff(b"\x1f\x8b\x08\x00"), Some("application/gzip"));
+        assert_eq!(mime::sniff(b"RIFF\0\0\0\0WAVEfmt "), Some("audio/wav"));
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(mime::sniff(&tar), Some("application/x-tar"));
+        assert_eq!(mime::sniff("plain 文本".as_bytes()), Some("text/plain"));
+        assert_eq!(mime::sniff(&[0, 1, 2, 3]), None);
+
+        // Extension takes precedence over sniffed content
+        assert_eq!(mime::detect(Path::new("data.json"), b"{}"), "application/json");
+        assert_eq!(mime::detect(Path::new("blob"), &[0, 1, 2]), mime::OCTET_STREAM);
     }
 
     // Data Model Tests
@@ -108,10 +269,64 @@ mod tests {
                 properties: Default::default(),
                 required: vec![],
             },
+            annotations: None,
         };
         
         let json = serde_json::to_string(&tool);
         assert!(json.is_ok());
+        assert!(!json.unwrap().contains("annotations"));
+    }
+
+    #[test]
+    fn test_builtin_tool_annotations() {
+        // Test that built-in tools advertise their behavioral hints
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let tools = registry.list_tools();
+
+        let weather = tools.iter().find(|t| t.name == "get_weather").unwrap();
+        let annotations = weather.annotations.as_ref().unwrap();
+        assert_eq!(annotations.read_only_hint, Some(true));
+        assert_eq!(annotations.destructive_hint, Some(false));
+        assert_eq!(annotations.open_world_hint, Some(true));
+
+        let json = serde_json::to_value(weather).unwrap();
+        assert_eq!(json["annotations"]["readOnlyHint"], true);
+        assert_eq!(json["annotations"]["openWorldHint"], true);
+        assert!(json["annotations"]["title"].is_string());
+    }
+
+    #[test]
+    fn test_configured_tool_annotations_override_defaults() {
+        // Test that annotations from the config file are merged over the defaults
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            r#"
+            [tools.search_files.annotations]
+            title = "Find files"
+            destructiveHint = true
+            "#,
+        )
+        .unwrap();
+        let registry = mcp_server_rust::tools::ToolRegistry::with_config(config.tools);
+        let tools = registry.list_tools();
+
+        let search = tools.iter().find(|t| t.name == "search_files").unwrap();
+        let annotations = search.annotations.as_ref().unwrap();
+        assert_eq!(annotations.title.as_deref(), Some("Find files"));
+        assert_eq!(annotations.destructive_hint, Some(true));
+        // Hints not mentioned in the config keep their built-in values
+        assert_eq!(annotations.read_only_hint, Some(true));
+        assert_eq!(annotations.idempotent_hint, Some(true));
+    }
+
+    #[test]
+    fn test_load_bundled_config_file() {
+        // Test that the repository's sample configuration parses
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/mcp-config.toml");
+        let config = mcp_server_rust::config::ServerConfig::load(path).unwrap();
+
+        assert_eq!(config.server.name, "mcp-server-rust");
+        let weather = config.tools["get_weather"].annotations.as_ref().unwrap();
+        assert_eq!(weather.open_world_hint, Some(true));
     }
 
     #[test]
@@ -226,12 +441,12 @@ mod tests {
             "directory": "/tmp"
         });
         
-        let result = tool.execute(args).await;
+        let result = tool.execute(args, &mcp_server_rust::RequestContext::detached()).await;
         assert!(result.is_ok());
         
         let call_result = result.unwrap();
         assert!(!call_result.content.is_empty());
-        assert_eq!(call_result.content[0].type_, "text");
+        assert!(matches!(call_result.content[0], Content::Text { .. }));
     }
 
     #[tokio::test]
@@ -244,7 +459,7 @@ mod tests {
             "city": "Beijing"
         });
         
-        let result = tool.execute(args).await;
+        let result = tool.execute(args, &mcp_server_rust::RequestContext::detached()).await;
         assert!(result.is_ok());
         
         let call_result = result.unwrap();
@@ -262,17 +477,47 @@ mod tests {
             // Missing required "pattern"
         });
         
-        let result = tool.execute(args).await;
+        let result = tool.execute(args, &mcp_server_rust::RequestContext::detached()).await;
         assert!(result.is_err(), "Should fail with missing required parameter");
+        assert!(matches!(
+            result.unwrap_err(),
+            mcp_server_rust::tools::ToolError::InvalidArguments(_)
+        ));
+    }
+
+    #[test]
+    fn test_call_tool_result_is_error_serialization() {
+        // Test that isError is only emitted for failed tool results
+        let ok = serde_json::to_value(CallToolResult::success(vec![Content::text("done")])).unwrap();
+        assert!(ok.get("isError").is_none());
+
+        let failed = serde_json::to_value(CallToolResult::error("磁盘已满")).unwrap();
+        assert_eq!(failed["isError"], true);
+        assert_eq!(failed["content"][0]["type"], "text");
+        assert_eq!(failed["content"][0]["text"], "磁盘已满");
+
+        let parsed: CallToolResult = serde_json::from_value(json!({ "content": [] })).unwrap();
+        assert!(!parsed.is_error);
+    }
+
+    #[test]
+    fn test_tool_error_messages() {
+        // Test that tool errors describe the failure including its cause
+        use mcp_server_rust::tools::ToolError;
+
+        let invalid = ToolError::InvalidArguments("缺少 city 参数".to_string());
+        assert_eq!(invalid.to_string(), "无效参数: 缺少 city 参数");
+
+        let cause = anyhow::anyhow!("connection refused").context("天气服务不可用");
+        let failed = ToolError::from(cause);
+        assert!(matches!(failed, ToolError::Execution(_)));
+        assert_eq!(failed.to_string(), "天气服务不可用: connection refused");
     }
 
     #[test]
     fn test_content_model() {
         // Test Content model creation and serialization
-        let content = Content {
-            type_: "text".to_string(),
-            text: "Test content".to_string(),
-        };
+        let content = Content::text("Test content");
         
         let json = serde_json::to_value(&content);
         assert!(json.is_ok());
@@ -282,10 +527,56 @@ mod tests {
         assert_eq!(json["text"], "Test content");
     }
 
+    #[test]
+    fn test_rich_content_serialization() {
+        // Test that non-text content variants use the MCP wire format
+        let image = serde_json::to_value(Content::image("aGVsbG8=", "image/png")).unwrap();
+        assert_eq!(image["type"], "image");
+        assert_eq!(image["data"], "aGVsbG8=");
+        assert_eq!(image["mimeType"], "image/png");
+
+        let audio = serde_json::to_value(Content::audio("UklGRg==", "audio/wav")).unwrap();
+        assert_eq!(audio["type"], "audio");
+        assert_eq!(audio["mimeType"], "audio/wav");
+
+        let link = serde_json::to_value(Content::resource_link("file:///etc/hosts", "hosts")).unwrap();
+        assert_eq!(link["type"], "resource_link");
+        assert_eq!(link["uri"], "file:///etc/hosts");
+        assert!(link.get("mimeType").is_none());
+
+        let embedded = serde_json::to_value(Content::resource(ResourceContents::Blob {
+            uri: "file:///tmp/a.bin".to_string(),
+            mime_type: Some("application/octet-stream".to_string()),
+            blob: "AAE=".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(embedded["type"], "resource");
+        assert_eq!(embedded["resource"]["blob"], "AAE=");
+        assert!(embedded["resource"].get("text").is_none());
+    }
+
+    #[test]
+    fn test_content_deserialization() {
+        // Test that tagged content round-trips back into the right variant
+        let json = json!({
+            "type": "resource",
+            "resource": { "uri": "file:///etc/hosts", "text": "127.0.0.1 localhost" }
+        });
+        let content: Content = serde_json::from_value(json).unwrap();
+        match content {
+            Content::Resource { resource: ResourceContents::Text { uri, mime_type, text } } => {
+                assert_eq!(uri, "file:///etc/hosts");
+                assert!(mime_type.is_none());
+                assert!(text.contains("localhost"));
+            }
+            other => panic!("unexpected content: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_tool_input_schema_model() {
         // Test ToolInputSchema model
-        let mut properties = Default::default();
+        let properties = Default::default();
         let schema = ToolInputSchema {
             type_: "object".to_string(),
             properties,
@@ -319,4 +610,2508 @@ mod tests {
         
         assert_eq!(tools1.len(), tools2.len());
     }
+
+    // Session Tests
+    #[tokio::test]
+    async fn test_sampling_requires_client_capability() {
+        // Test that sampling is refused when the client did not declare it
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = mcp_server_rust::Session::new(tx);
+
+        let result = session
+            .create_message(CreateMessageRequest::user_text("总结一下", 100))
+            .await;
+        let error = result.unwrap_err();
+        assert!(matches!(error, mcp_server_rust::McpError::CapabilityNotSupported(_)));
+        assert_eq!(error.code(), mcp_server_rust::error::CAPABILITY_NOT_SUPPORTED);
+        assert!(rx.try_recv().is_err(), "no request should reach the client");
+    }
+
+    #[tokio::test]
+    async fn test_sampling_round_trip() {
+        // Test that a sampling request is sent to the client and its
+        // correlated response is delivered back to the caller
+        use std::sync::Arc;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = Arc::new(mcp_server_rust::Session::new(tx));
+        session.set_client_capabilities(ClientCapabilities {
+            sampling: Some(json!({})),
+            ..Default::default()
+        });
+
+        let caller = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move {
+                session
+                    .create_message(CreateMessageRequest::user_text("分类: 晴天", 50))
+                    .await
+            })
+        };
+
+        let request: serde_json::Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(request["method"], "sampling/createMessage");
+        assert_eq!(request["params"]["maxTokens"], 50);
+        assert_eq!(request["params"]["messages"][0]["role"], "user");
+        let id = request["id"].as_u64().unwrap();
+
+        // A response for an unknown request is ignored
+        let stray: JsonRpcResponse = serde_json::from_value(json!({
+            "jsonrpc": "2.0", "id": id + 100, "result": {}
+        }))
+        .unwrap();
+        assert!(!session.handle_response(stray));
+
+        let response: JsonRpcResponse = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": {
+                "role": "assistant",
+                "content": { "type": "text", "text": "weather" },
+                "model": "test-model",
+                "stopReason": "endTurn"
+            }
+        }))
+        .unwrap();
+        assert!(session.handle_response(response));
+
+        let result = caller.await.unwrap().unwrap();
+        assert_eq!(result.role, Role::Assistant);
+        assert_eq!(result.model, "test-model");
+        assert_eq!(result.content, Content::text("weather"));
+    }
+
+    #[tokio::test]
+    async fn test_client_request_ids_and_errors() {
+        // Test that server-side ids are allocated per request and that a
+        // client error is surfaced to the caller
+        use std::sync::Arc;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = Arc::new(mcp_server_rust::Session::new(tx));
+
+        let first = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move { session.request::<_, serde_json::Value>("ping", json!({})).await })
+        };
+        let first_id = serde_json::from_str::<serde_json::Value>(&rx.recv().await.unwrap()).unwrap()["id"]
+            .as_u64()
+            .unwrap();
+        let second = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move { session.request::<_, serde_json::Value>("ping", json!({})).await })
+        };
+        let second_id = serde_json::from_str::<serde_json::Value>(&rx.recv().await.unwrap()).unwrap()["id"]
+            .as_u64()
+            .unwrap();
+        assert_ne!(first_id, second_id);
+
+        session.handle_response(
+            serde_json::from_value(json!({
+                "jsonrpc": "2.0",
+                "id": first_id,
+                "error": { "code": -1, "message": "User rejected sampling request" }
+            }))
+            .unwrap(),
+        );
+        let error = first.await.unwrap().unwrap_err();
+        assert!(matches!(error, mcp_server_rust::McpError::Client(_)));
+        assert_eq!(error.code(), -1);
+
+        // Closing the session fails whatever is still pending
+        session.close();
+        assert!(matches!(
+            second.await.unwrap().unwrap_err(),
+            mcp_server_rust::McpError::SessionClosed
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_client_reply_is_not_a_parse_error() {
+        // Test that a client reply of the wrong shape is an internal error naming the request
+        use std::sync::Arc;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = Arc::new(mcp_server_rust::Session::new(tx));
+        let caller = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move { session.request::<_, ListRootsResult>("roots/list", json!({})).await })
+        };
+        let id = serde_json::from_str::<serde_json::Value>(&rx.recv().await.unwrap()).unwrap()["id"].clone();
+        session.handle_response(
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": id, "result": { "roots": "none" } })).unwrap(),
+        );
+
+        let error = caller.await.unwrap().unwrap_err();
+        assert!(matches!(error, mcp_server_rust::McpError::Internal(_)));
+        assert_eq!(error.code(), mcp_server_rust::error::INTERNAL_ERROR);
+        assert!(error.data().unwrap()["cause"][0].as_str().unwrap().contains("roots/list"));
+    }
+
+    #[tokio::test]
+    async fn test_client_request_timeout() {
+        // Test that an unanswered request fails after the session timeout
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = mcp_server_rust::Session::new(tx)
+            .with_request_timeout(std::time::Duration::from_millis(20));
+        session.set_client_capabilities(ClientCapabilities {
+            sampling: Some(json!({})),
+            ..Default::default()
+        });
+
+        let error = session
+            .create_message(CreateMessageRequest::user_text("hello", 10))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, mcp_server_rust::McpError::RequestTimeout { .. }));
+        assert_eq!(error.data().unwrap()["method"], "sampling/createMessage");
+    }
+
+    #[tokio::test]
+    async fn test_detached_context_cannot_reach_client() {
+        // Test that tools run outside a connection get a closed session
+        let ctx = mcp_server_rust::RequestContext::detached();
+        let result = ctx.session.request::<_, serde_json::Value>("ping", json!({})).await;
+        assert!(matches!(result.unwrap_err(), mcp_server_rust::McpError::SessionClosed));
+    }
+
+    /// Creates a session with the given client capabilities and returns it
+    /// together with the receiver of its outbound messages
+    fn connected_session(
+        capabilities: ClientCapabilities,
+    ) -> (
+        std::sync::Arc<mcp_server_rust::Session>,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = mcp_server_rust::Session::new(tx);
+        session.set_client_capabilities(capabilities);
+        (std::sync::Arc::new(session), rx)
+    }
+
+    /// Runs `get_weather` without a city and answers the resulting
+    /// elicitation with `answer`
+    async fn weather_with_elicitation(answer: serde_json::Value) -> Result<CallToolResult, mcp_server_rust::tools::ToolError> {
+        let (session, mut rx) = connected_session(ClientCapabilities {
+            elicitation: Some(json!({})),
+            ..Default::default()
+        });
+        let ctx = mcp_server_rust::RequestContext::new(std::sync::Arc::clone(&session));
+        let call = tokio::spawn(async move {
+            let registry = mcp_server_rust::tools::ToolRegistry::new();
+            registry.get("get_weather").unwrap().execute(json!({}), &ctx).await
+        });
+
+        let request: serde_json::Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(request["method"], "elicitation/create");
+        assert_eq!(request["params"]["requestedSchema"]["type"], "object");
+        assert!(request["params"]["requestedSchema"]["properties"]["city"].is_object());
+
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": answer });
+        assert!(session.handle_response(serde_json::from_value(response).unwrap()));
+        call.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_weather_elicits_missing_city() {
+        // Test that get_weather asks the user for a missing city
+        let result = weather_with_elicitation(json!({
+            "action": "accept",
+            "content": { "city": "上海" }
+        }))
+        .await
+        .unwrap();
+
+        assert!(!result.is_error);
+        match &result.content[0] {
+            Content::Text { text } => assert!(text.contains("上海")),
+            other => panic!("unexpected content: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_weather_elicitation_decline_and_cancel() {
+        // Test that declining or cancelling the form ends the call with an execution error
+        use mcp_server_rust::tools::ToolError;
+
+        let declined = weather_with_elicitation(json!({ "action": "decline" })).await;
+        assert!(matches!(declined, Err(ToolError::Execution(_))));
+
+        let cancelled = weather_with_elicitation(json!({ "action": "cancel" })).await;
+        assert!(matches!(cancelled, Err(ToolError::Execution(_))));
+
+        let empty = weather_with_elicitation(json!({ "action": "accept", "content": {} })).await;
+        assert!(matches!(empty, Err(ToolError::Execution(_))));
+    }
+
+    #[tokio::test]
+    async fn test_elicitation_requires_client_capability() {
+        // Test that elicitation is never attempted without the capability
+        let (session, mut rx) = connected_session(ClientCapabilities::default());
+        let ctx = mcp_server_rust::RequestContext::new(std::sync::Arc::clone(&session));
+
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let result = registry.get("get_weather").unwrap().execute(json!({}), &ctx).await;
+        assert!(matches!(
+            result,
+            Err(mcp_server_rust::tools::ToolError::InvalidArguments(_))
+        ));
+        assert!(rx.try_recv().is_err(), "no elicitation should be sent");
+
+        let direct = session
+            .elicit(ElicitRequest {
+                message: "?".to_string(),
+                requested_schema: ToolInputSchema {
+                    type_: "object".to_string(),
+                    properties: Default::default(),
+                    required: vec![],
+                },
+            })
+            .await;
+        assert!(matches!(
+            direct.unwrap_err(),
+            mcp_server_rust::McpError::CapabilityNotSupported(_)
+        ));
+    }
+
+    // Roots Tests
+    fn text_of(result: &CallToolResult) -> &str {
+        match &result.content[0] {
+            Content::Text { text } => text,
+            other => panic!("expected text content, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_files_matches_patterns_recursively() {
+        // Test that search_files walks subdirectories and honours brace patterns
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        for name in ["a.txt", "b.log", "sub/c.md", "sub/d.rs"] {
+            std::fs::write(dir.path().join(name), "x").unwrap();
+        }
+
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let args = json!({ "pattern": "*.{txt,md}", "directory": dir.path() });
+        let result = registry
+            .get("search_files")
+            .unwrap()
+            .execute(args, &mcp_server_rust::RequestContext::detached())
+            .await
+            .unwrap();
+
+        let text = text_of(&result);
+        assert!(text.contains("a.txt"));
+        assert!(text.contains("c.md"));
+        assert!(!text.contains("b.log"));
+        assert!(!text.contains("d.rs"));
+    }
+
+    #[test]
+    fn test_wildcard_patterns() {
+        // Test wildcard matching, including patterns with many brace groups
+        use mcp_server_rust::wildcard::WildcardPattern;
+
+        let pattern = WildcardPattern::new("*.{txt,md}");
+        assert!(pattern.matches("notes.txt"));
+        assert!(pattern.matches("dir/readme.md"));
+        assert!(!pattern.matches("main.rs"));
+        assert!(WildcardPattern::new("a?c{,d}").matches("abc"));
+        assert!(WildcardPattern::new("a?c{,d}").matches("abcd"));
+        assert!(WildcardPattern::new("**/.ssh/**").matches("/home/u/.ssh/id_rsa"));
+        assert!(WildcardPattern::new("{a").matches("{a"));
+        assert!(!WildcardPattern::new("a*b*c").matches("aXbY"));
+
+        // 2^64 alternatives if expanded; compiled lazily this is instant
+        let many_groups = "{a,b}".repeat(64);
+        let pattern = WildcardPattern::new(&many_groups);
+        assert!(pattern.matches(&"ab".repeat(32)));
+        assert!(!pattern.matches(&"a".repeat(63)));
+        assert!(!pattern.matches(&"c".repeat(64)));
+    }
+
+    #[tokio::test]
+    async fn test_search_files_confined_to_client_roots() {
+        // Test that search_files only searches inside the client's roots,
+        // defaults to the first root and caches the roots list
+        use mcp_server_rust::roots::path_to_file_uri;
+        use mcp_server_rust::tools::ToolError;
+        use std::sync::Arc;
+
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("inside.txt"), "x").unwrap();
+
+        let (session, mut rx) = connected_session(ClientCapabilities {
+            roots: Some(RootsCapability { list_changed: Some(true) }),
+            ..Default::default()
+        });
+        let ctx = mcp_server_rust::RequestContext::new(Arc::clone(&session));
+
+        let escaped = {
+            let ctx = ctx.clone();
+            let args = json!({
+                "pattern": "*",
+                "directory": root.path().join("..").join(outside.path().file_name().unwrap())
+            });
+            tokio::spawn(async move {
+                let registry = mcp_server_rust::tools::ToolRegistry::new();
+                registry.get("search_files").unwrap().execute(args, &ctx).await
+            })
+        };
+        let request: serde_json::Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(request["method"], "roots/list");
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": { "roots": [{ "uri": path_to_file_uri(root.path()), "name": "workspace" }] }
+        });
+        assert!(session.handle_response(serde_json::from_value(response).unwrap()));
+        assert!(matches!(escaped.await.unwrap(), Err(ToolError::InvalidArguments(_))));
+
+        // Roots are cached, so no further request reaches the client
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let result = registry
+            .get("search_files")
+            .unwrap()
+            .execute(json!({ "pattern": "*.txt" }), &ctx)
+            .await
+            .unwrap();
+        assert!(text_of(&result).contains("inside.txt"));
+        assert!(rx.try_recv().is_err());
+
+        // A list_changed notification makes the next lookup ask again
+        session.invalidate_roots();
+        let lookup = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move { session.list_roots().await })
+        };
+        let request: serde_json::Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(request["method"], "roots/list");
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "roots": [] } });
+        session.handle_response(serde_json::from_value(response).unwrap());
+        assert!(lookup.await.unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_roots_path_helpers() {
+        // Test file URI conversion and root containment checks
+        use mcp_server_rust::roots::{file_uri_to_path, is_within_roots, path_to_file_uri};
+        use std::path::{Path, PathBuf};
+
+        assert_eq!(
+            file_uri_to_path("file:///home/me/my%20project"),
+            Some(PathBuf::from("/home/me/my project"))
+        );
+        assert_eq!(file_uri_to_path("file://localhost/srv"), Some(PathBuf::from("/srv")));
+        assert_eq!(file_uri_to_path("https://example.com/x"), None);
+        assert_eq!(path_to_file_uri(Path::new("/a b/c")), "file:///a%20b/c");
+
+        let roots = vec![PathBuf::from("/workspace/project")];
+        assert!(is_within_roots(Path::new("/workspace/project/src"), &roots));
+        assert!(!is_within_roots(Path::new("/workspace/project/../other"), &roots));
+        assert!(!is_within_roots(Path::new("/workspace/project-2"), &roots));
+    }
+
+    // Logging Tests
+    fn drain(rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>) -> Vec<serde_json::Value> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|message| serde_json::from_str(&message).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_log_records_filtered_by_client_level() {
+        // Test that records at or above the selected level are forwarded
+        // as notifications/message with logger name and structured data
+        use mcp_server_rust::logging::{LogRouter, LoggingLevel, McpLogLayer};
+        use tracing_subscriber::prelude::*;
+
+        let router = LogRouter::new();
+        let (session, mut rx) = connected_session(ClientCapabilities::default());
+        router.register(&session);
+
+        let subscriber = tracing_subscriber::registry().with(McpLogLayer::new(router.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let _session = tracing::info_span!("session", session_id = session.id()).entered();
+            tracing::warn!("dropped before a level is selected");
+            session.set_log_level(LoggingLevel::Warning);
+            tracing::info!("too verbose");
+            tracing::warn!(tool = "get_weather", attempts = 3u64, "upstream slow");
+            tracing::error!(target: "weather", "upstream down");
+        });
+
+        let messages = drain(&mut rx);
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert_eq!(messages[0]["method"], "notifications/message");
+        assert_eq!(messages[0]["params"]["level"], "warning");
+        assert_eq!(messages[0]["params"]["logger"], "integration_tests::tests");
+        assert_eq!(messages[0]["params"]["data"]["message"], "upstream slow");
+        assert_eq!(messages[0]["params"]["data"]["fields"]["tool"], "get_weather");
+        assert_eq!(messages[0]["params"]["data"]["fields"]["attempts"], 3);
+        assert_eq!(messages[1]["params"]["level"], "error");
+        assert_eq!(messages[1]["params"]["logger"], "weather");
+
+        // Unregistered sessions receive nothing
+        router.unregister(session.id());
+        let subscriber = tracing_subscriber::registry().with(McpLogLayer::new(router));
+        tracing::subscriber::with_default(subscriber, || {
+            let _session = tracing::info_span!("session", session_id = session.id()).entered();
+            tracing::error!("after unregister");
+        });
+        assert!(drain(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn test_log_records_routed_to_owning_session() {
+        // Test that records inside a session span only reach that session,
+        // while records outside any session and audit records reach no one
+        use mcp_server_rust::logging::{LogRouter, LoggingLevel, McpLogLayer};
+        use tracing_subscriber::prelude::*;
+
+        let router = LogRouter::new();
+        let (first, mut first_rx) = connected_session(ClientCapabilities::default());
+        let (second, mut second_rx) = connected_session(ClientCapabilities::default());
+        for session in [&first, &second] {
+            router.register(session);
+            session.set_log_level(LoggingLevel::Debug);
+        }
+
+        let subscriber = tracing_subscriber::registry().with(McpLogLayer::new(router));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("session", session_id = first.id());
+            span.in_scope(|| {
+                let _request = tracing::debug_span!("request", method = "tools/call").entered();
+                tracing::debug!("only for the first session");
+                tracing::info!(target: "audit", "only for operators");
+            });
+            tracing::info!(identity = "alice", "客户端已认证");
+        });
+
+        let first_messages = drain(&mut first_rx);
+        assert_eq!(first_messages.len(), 1, "{:?}", first_messages);
+        assert_eq!(first_messages[0]["params"]["level"], "debug");
+        assert_eq!(first_messages[0]["params"]["data"]["message"], "only for the first session");
+        assert!(drain(&mut second_rx).is_empty());
+    }
+
+    #[test]
+    fn test_logging_level_ordering_and_serialization() {
+        // Test that levels are ordered by severity and use MCP names
+        use mcp_server_rust::logging::{LoggingLevel, SetLevelRequest};
+
+        assert!(LoggingLevel::Debug < LoggingLevel::Notice);
+        assert!(LoggingLevel::Critical < LoggingLevel::Emergency);
+        assert_eq!(LoggingLevel::from(tracing::Level::WARN), LoggingLevel::Warning);
+
+        let request: SetLevelRequest = serde_json::from_value(json!({ "level": "critical" })).unwrap();
+        assert_eq!(request.level, LoggingLevel::Critical);
+        assert!(serde_json::from_value::<SetLevelRequest>(json!({ "level": "verbose" })).is_err());
+    }
+
+    // Completion Tests
+    #[tokio::test]
+    async fn test_tool_argument_completion() {
+        // Test that tool arguments complete cities and subdirectories
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let ctx = mcp_server_rust::RequestContext::detached();
+
+        let weather = registry.get("get_weather").unwrap();
+        let cities = weather.complete("city", "sh", &ctx).await.unwrap();
+        assert_eq!(cities, vec!["Shanghai", "Shenzhen"]);
+        assert_eq!(weather.complete("city", "上", &ctx).await.unwrap(), vec!["上海"]);
+        assert!(weather.complete("unknown", "", &ctx).await.unwrap().is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["src", "scripts", ".git", "target"] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        std::fs::write(dir.path().join("setup.txt"), "x").unwrap();
+
+        let prefix = format!("{}/s", dir.path().display());
+        let dirs = registry
+            .get("search_files")
+            .unwrap()
+            .complete("directory", &prefix, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(
+            dirs,
+            vec![
+                format!("{}/scripts", dir.path().display()),
+                format!("{}/src", dir.path().display()),
+            ]
+        );
+
+        let hidden = format!("{}/.", dir.path().display());
+        let dirs = registry
+            .get("search_files")
+            .unwrap()
+            .complete("directory", &hidden, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(dirs, vec![format!("{}/.git", dir.path().display())]);
+    }
+
+    #[test]
+    fn test_prompt_registry_and_completion() {
+        // Test listing, rendering and completing the built-in prompt
+        use std::collections::HashMap;
+
+        let registry = mcp_server_rust::PromptRegistry::new();
+        let prompts = registry.list_prompts();
+        let report = prompts.iter().find(|p| p.name == "weather_report").unwrap();
+        assert!(report.arguments.iter().any(|a| a.name == "city" && a.required));
+
+        let prompt = registry.get("weather_report").unwrap();
+        let arguments = HashMap::from([("city".to_string(), "北京".to_string())]);
+        let result = prompt.get(&arguments).unwrap();
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].role, Role::User);
+        match &result.messages[0].content {
+            Content::Text { text } => assert!(text.contains("北京")),
+            other => panic!("unexpected content: {:?}", other),
+        }
+
+        let missing = prompt.get(&HashMap::new()).unwrap_err();
+        assert_eq!(missing.code(), mcp_server_rust::error::INVALID_PARAMS);
+
+        assert_eq!(prompt.complete("city", "bei"), vec!["Beijing"]);
+        assert!(registry.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_resource_template_completion() {
+        // Test that the file template completes registered paths inside the roots
+        use mcp_server_rust::resources::resource_handler::FILE_TEMPLATE;
+
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "x").unwrap();
+        std::fs::write(other.path().join("secret.txt"), "x").unwrap();
+
+        let mut registry = mcp_server_rust::ResourceRegistry::new();
+        registry.register_file(dir.path().join("notes.txt")).unwrap();
+        registry.register_file(other.path().join("secret.txt")).unwrap();
+
+        let templates = registry.list_templates();
+        assert_eq!(templates[0].uri_template, FILE_TEMPLATE);
+
+        let all = registry.complete_template(FILE_TEMPLATE, "path", "", None).unwrap();
+        assert!(all.contains(&"etc/hosts".to_string()));
+        assert_eq!(all.len(), 3);
+
+        let roots = vec![dir.path().to_path_buf()];
+        let confined = registry
+            .complete_template(FILE_TEMPLATE, "path", "", Some(&roots))
+            .unwrap();
+        assert_eq!(confined.len(), 1);
+        assert!(confined[0].ends_with("notes.txt"));
+
+        let prefix = dir.path().to_string_lossy().trim_start_matches('/').to_string();
+        let typed = registry.complete_template(FILE_TEMPLATE, "path", &prefix, None).unwrap();
+        assert_eq!(typed.len(), 1);
+
+        let unknown = registry.complete_template("http://{host}", "host", "", None);
+        assert!(matches!(
+            unknown.unwrap_err(),
+            mcp_server_rust::McpError::ResourceNotFound { .. }
+        ));
+    }
+
+    #[test]
+    fn test_completion_models() {
+        // Test completion request parsing and result truncation
+        let request: CompleteRequest = serde_json::from_value(json!({
+            "ref": { "type": "ref/prompt", "name": "weather_report" },
+            "argument": { "name": "city", "value": "Be" }
+        }))
+        .unwrap();
+        assert_eq!(
+            request.reference,
+            CompletionReference::Prompt { name: "weather_report".to_string() }
+        );
+        assert_eq!(request.argument.value, "Be");
+
+        let tool_ref: CompletionReference =
+            serde_json::from_value(json!({ "type": "ref/tool", "name": "search_files" })).unwrap();
+        assert_eq!(tool_ref, CompletionReference::Tool { name: "search_files".to_string() });
+
+        let candidates: Vec<String> = (0..150).map(|i| i.to_string()).collect();
+        let completion = Completion::from_candidates(candidates);
+        assert_eq!(completion.values.len(), Completion::MAX_VALUES);
+        assert_eq!(completion.total, Some(150));
+        assert!(completion.has_more);
+
+        let json = serde_json::to_value(CompleteResult {
+            completion: Completion::from_candidates(vec!["a".to_string()]),
+        })
+        .unwrap();
+        assert_eq!(json, json!({ "completion": { "values": ["a"], "total": 1, "hasMore": false } }));
+    }
+
+    // Transport Tests
+    #[test]
+    fn test_listen_addr_parsing() {
+        // Test parsing of --listen values
+        use mcp_server_rust::server::ListenAddr;
+        use std::path::PathBuf;
+
+        assert_eq!(
+            "unix:/run/mcp.sock".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Unix(PathBuf::from("/run/mcp.sock"))
+        );
+        assert_eq!(
+            "tcp:0.0.0.0:9000".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Tcp("0.0.0.0:9000".to_string())
+        );
+        assert_eq!(
+            "127.0.0.1:8080".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Tcp("127.0.0.1:8080".to_string())
+        );
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert_eq!("stdio".parse::<ListenAddr>().unwrap(), ListenAddr::Stdio);
+        assert_eq!(
+            "ws:127.0.0.1:8081".parse::<ListenAddr>().unwrap(),
+            ListenAddr::WebSocket("127.0.0.1:8081".to_string())
+        );
+        assert_eq!(ListenAddr::Unix(PathBuf::from("/tmp/a.sock")).to_string(), "unix:/tmp/a.sock");
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_transport() {
+        // Test serving over a Unix socket: stale socket removal, permissions,
+        // a request round trip and cleanup when the server stops
+        use std::os::unix::fs::PermissionsExt;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mcp.sock");
+        // Leave a stale socket behind, as a crashed server would
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let server = mcp_server_rust::McpServer::new();
+        let listener = {
+            let path = path.clone();
+            tokio::spawn(async move { server.start_unix(&path).await })
+        };
+
+        let mut stream = loop {
+            match tokio::net::UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        stream
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":7}\n")
+            .await
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).await.unwrap();
+        let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"], json!({}));
+
+        // A second server must not take over a live socket
+        let second = mcp_server_rust::McpServer::new().start_unix(&path).await;
+        assert!(second.is_err());
+
+        listener.abort();
+        let _ = listener.await;
+        assert!(!path.exists(), "socket file should be removed on shutdown");
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_refuses_non_socket_path() {
+        // Test that an existing regular file is never deleted to make room for the socket
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("important.txt");
+        std::fs::write(&path, "keep me").unwrap();
+
+        let result = mcp_server_rust::McpServer::new().start_unix(&path).await;
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    }
+
+    /// Starts a WebSocket server on a free local port and returns its URL
+    async fn websocket_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { mcp_server_rust::McpServer::new().serve_websocket(listener).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_websocket_transport() {
+        // Test subprotocol negotiation, text frame round trips, ping/pong and close
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = websocket_server().await.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", "mcp".parse().unwrap());
+        let (mut ws, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(response.headers()["Sec-WebSocket-Protocol"], "mcp");
+
+        ws.send(Message::text(r#"{"jsonrpc":"2.0","method":"tools/list","id":1}"#))
+            .await
+            .unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"]["tools"].as_array().unwrap().len(), 2);
+
+        ws.send(Message::Ping(b"hi".to_vec().into())).await.unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Pong(payload) => assert_eq!(&payload[..], b"hi"),
+            other => panic!("expected pong, got {:?}", other),
+        }
+
+        // The server acknowledges the close frame and ends the connection
+        ws.close(None).await.unwrap();
+        match ws.next().await {
+            Some(Ok(Message::Close(_))) | None | Some(Err(_)) => {}
+            Some(Ok(other)) => panic!("unexpected frame after close: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_rejects_foreign_subprotocol() {
+        // Test that a client offering only other subprotocols is refused
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = websocket_server().await.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", "graphql-ws".parse().unwrap());
+        assert!(tokio_tungstenite::connect_async(request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_content_length_transport() {
+        // Test LSP-style framing in both directions, including extra headers
+        use mcp_server_rust::transport::{ContentLengthTransport, MessageReader, MessageWriter, Transport};
+        use std::sync::Arc;
+        use tokio::io::AsyncWriteExt;
+
+        let (server_io, mut client_io) = tokio::io::duplex(4096);
+        let server = Arc::new(mcp_server_rust::McpServer::new());
+        let serving = tokio::spawn(server.serve_transport(ContentLengthTransport::new(server_io)));
+
+        let body = r#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+        let frame = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        client_io.write_all(frame.as_bytes()).await.unwrap();
+
+        let (mut reader, mut writer) = ContentLengthTransport::new(client_io).split();
+        let reply: serde_json::Value = serde_json::from_str(&reader.recv().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 1);
+
+        writer
+            .send(r#"{"jsonrpc":"2.0","method":"tools/list","id":2}"#.to_string())
+            .await
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&reader.recv().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 2);
+        assert!(reply["result"]["tools"].is_array());
+
+        writer.close().await.unwrap();
+        assert!(reader.recv().await.unwrap().is_none());
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_content_length_rejects_bad_header() {
+        // Test that a malformed Content-Length ends the session with an error
+        use mcp_server_rust::transport::ContentLengthTransport;
+        use std::sync::Arc;
+        use tokio::io::AsyncWriteExt;
+
+        let (server_io, mut client_io) = tokio::io::duplex(1024);
+        let server = Arc::new(mcp_server_rust::McpServer::new());
+        let serving = tokio::spawn(server.serve_transport(ContentLengthTransport::new(server_io)));
+
+        client_io.write_all(b"Content-Length: lots\r\n\r\n{}").await.unwrap();
+        assert!(serving.await.unwrap().is_err());
+    }
+
+    /// Serves a binary file as a resource over a byte-stream transport and reads it back
+    async fn read_streamed_blob<T, C>(server_transport: T, client_transport: C, uri: &str) -> serde_json::Value
+    where
+        T: mcp_server_rust::transport::Transport,
+        C: mcp_server_rust::transport::Transport,
+    {
+        use mcp_server_rust::transport::{MessageReader, MessageWriter};
+        use std::sync::Arc;
+
+        let config = mcp_server_rust::config::ServerConfig::parse("[limits]\nmax_resource_size = 1024\n").unwrap();
+        let mut server = mcp_server_rust::McpServer::with_config(config);
+        server.resource_registry.register_file(uri.strip_prefix("file://").unwrap()).unwrap();
+        let serving = tokio::spawn(Arc::new(server).serve_transport(server_transport));
+
+        let (mut reader, mut writer) = client_transport.split();
+        let request = json!({ "jsonrpc": "2.0", "method": "resources/read", "params": { "uri": uri }, "id": 3 });
+        writer.send(request.to_string()).await.unwrap();
+        let reply = serde_json::from_str(&reader.recv().await.unwrap().unwrap()).unwrap();
+        writer.close().await.unwrap();
+        serving.await.unwrap().unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_streamed_blob_resource() {
+        // Test that binary files are streamed over each kind of transport,
+        // whatever the limit on content read into memory
+        use base64::Engine;
+        use futures_util::{SinkExt, StreamExt};
+        use mcp_server_rust::transport::{ContentLengthTransport, LineTransport, WebSocketTransport};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend((0..300_000u32).map(|i| (i % 251) as u8));
+        std::fs::write(&path, &data).unwrap();
+        let uri = format!("file://{}", path.display());
+        let check = |reply: serde_json::Value| {
+            assert_eq!(reply["id"], 3);
+            let contents = &reply["result"]["contents"][0];
+            assert_eq!(contents["uri"], uri);
+            assert_eq!(contents["mimeType"], "image/png");
+            let blob = base64::engine::general_purpose::STANDARD.decode(contents["blob"].as_str().unwrap());
+            assert!(blob.unwrap() == data, "blob does not match the file");
+        };
+
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        check(read_streamed_blob(LineTransport::new(server_io), LineTransport::new(client_io), &uri).await);
+
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let (server_transport, client_transport) = (ContentLengthTransport::new(server_io), ContentLengthTransport::new(client_io));
+        check(read_streamed_blob(server_transport, client_transport, &uri).await);
+
+        // WebSocket sends the blob as a fragmented message, which the client reassembles
+        let mut server = mcp_server_rust::McpServer::new();
+        server.resource_registry.register_file(&path).unwrap();
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let (server_transport, client) = tokio::join!(
+            WebSocketTransport::accept(server_io),
+            tokio_tungstenite::client_async("ws://localhost/", client_io)
+        );
+        let serving = tokio::spawn(std::sync::Arc::new(server).serve_transport(server_transport.unwrap()));
+        let (mut websocket, _) = client.unwrap();
+        let request = json!({ "jsonrpc": "2.0", "method": "resources/read", "params": { "uri": uri }, "id": 3 });
+        websocket.send(Message::text(request.to_string())).await.unwrap();
+        let reply = websocket.next().await.unwrap().unwrap();
+        check(serde_json::from_str(reply.to_text().unwrap()).unwrap());
+        websocket.close(None).await.unwrap();
+        serving.await.unwrap().unwrap();
+
+        // Reading into memory is still limited
+        let registry = {
+            let mut registry = mcp_server_rust::ResourceRegistry::new().with_max_resource_size(1024);
+            registry.register_file(&path).unwrap();
+            registry
+        };
+        assert!(matches!(
+            registry.read_resource(&uri).await.unwrap_err(),
+            mcp_server_rust::McpError::ResourceTooLarge { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_channel_transport_session() {
+        // Test a session over the in-memory channel transport
+        use mcp_server_rust::transport::{ChannelTransport, MessageReader, MessageWriter, Transport};
+        use std::sync::Arc;
+
+        let (server_end, client_end) = ChannelTransport::pair();
+        let server = Arc::new(mcp_server_rust::McpServer::new());
+        let serving = tokio::spawn(server.serve_transport(server_end));
+        let (mut reader, mut writer) = client_end.split();
+
+        writer
+            .send(json!({ "jsonrpc": "2.0", "method": "unknown/method", "id": 9 }).to_string())
+            .await
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&reader.recv().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["error"]["code"], mcp_server_rust::error::METHOD_NOT_FOUND);
+
+        // Closing the client end finishes the session and closes the server end
+        writer.close().await.unwrap();
+        serving.await.unwrap().unwrap();
+        assert!(reader.recv().await.unwrap().is_none());
+    }
+
+    // Protocol Tests (through the in-process client)
+    #[tokio::test]
+    async fn test_protocol_initialize_handshake() {
+        // Test version negotiation and the advertised server capabilities
+        let (client, server) = mcp_server_rust::McpServer::new().connect_in_process();
+
+        let result = client.initialize(ClientCapabilities::default()).await.unwrap();
+        assert_eq!(result.protocol_version, mcp_server_rust::server::LATEST_PROTOCOL_VERSION);
+        assert_eq!(result.server_info.name, env!("CARGO_PKG_NAME"));
+        let capabilities = &result.capabilities;
+        assert!(capabilities.tools.is_some());
+        assert!(capabilities.resources.is_some());
+        assert!(capabilities.prompts.is_some());
+        assert!(capabilities.logging.is_some());
+        assert!(capabilities.completions.is_some());
+
+        // An unsupported version is answered with the server's own
+        let request = json!({
+            "protocolVersion": "1999-01-01",
+            "capabilities": {},
+            "clientInfo": { "name": "old", "version": "0" }
+        });
+        let result: InitializeResult = client.request("initialize", request).await.unwrap();
+        assert_eq!(result.protocol_version, mcp_server_rust::server::LATEST_PROTOCOL_VERSION);
+
+        let pong: serde_json::Value = client.request("ping", json!({})).await.unwrap();
+        assert_eq!(pong, json!({}));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_protocol_tools_list_and_call() {
+        // Test listing and calling tools, and how failures are reported
+        use mcp_server_rust::McpError;
+        use mcp_server_rust::error::{INVALID_PARAMS, METHOD_NOT_FOUND};
+
+        let (client, _server) = mcp_server_rust::McpServer::new().connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        let tools = client.list_tools().await.unwrap();
+        let weather = tools.iter().find(|t| t.name == "get_weather").unwrap();
+        assert_eq!(weather.annotations.as_ref().unwrap().read_only_hint, Some(true));
+
+        let result = client.call_tool("get_weather", json!({ "city": "Beijing" })).await.unwrap();
+        assert!(!result.is_error);
+        assert!(text_of(&result).contains("Beijing"));
+
+        let missing = client.call_tool("get_weather", json!({})).await.unwrap_err();
+        assert!(matches!(&missing, McpError::Client(e) if e.code == INVALID_PARAMS));
+
+        let unknown = client.call_tool("no_such_tool", json!({})).await.unwrap_err();
+        match unknown {
+            McpError::Client(e) => {
+                assert_eq!(e.code, METHOD_NOT_FOUND);
+                assert_eq!(e.data.unwrap()["tool"], "no_such_tool");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let bad_params = client.request::<_, serde_json::Value>("tools/call", json!({ "nope": 1 })).await;
+        assert!(matches!(bad_params, Err(McpError::Client(e)) if e.code == INVALID_PARAMS));
+
+        let unknown_method = client.request::<_, serde_json::Value>("tools/destroy", json!({})).await;
+        assert!(matches!(unknown_method, Err(McpError::Client(e)) if e.code == METHOD_NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_resources_prompts_and_completion() {
+        // Test resources, prompts and completion over the wire
+        let (client, _server) = mcp_server_rust::McpServer::new().connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        let resources = client.list_resources().await.unwrap();
+        assert!(resources.iter().any(|r| r.uri == "file:///etc/hosts"));
+        let contents = client.read_resource("file:///etc/hosts").await.unwrap();
+        assert!(matches!(&contents[0], ResourceContents::Text { text, .. } if text.contains("localhost")));
+        let missing = client.read_resource("file:///missing").await.unwrap_err();
+        assert!(matches!(missing, mcp_server_rust::McpError::Client(e) if e.code == mcp_server_rust::error::RESOURCE_NOT_FOUND));
+
+        let templates: ListResourceTemplatesResult =
+            client.request("resources/templates/list", json!({})).await.unwrap();
+        assert_eq!(templates.resource_templates.len(), 1);
+
+        let prompt: GetPromptResult = client
+            .request("prompts/get", json!({ "name": "weather_report", "arguments": { "city": "成都" } }))
+            .await
+            .unwrap();
+        assert_eq!(prompt.messages.len(), 1);
+
+        let completion: CompleteResult = client
+            .request(
+                "completion/complete",
+                json!({
+                    "ref": { "type": "ref/prompt", "name": "weather_report" },
+                    "argument": { "name": "city", "value": "Ch" }
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(completion.completion.values, vec!["Chengdu", "Chongqing"]);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_server_initiated_request() {
+        // Test that the client sees and answers the server's elicitation request
+        let (client, _server) = mcp_server_rust::McpServer::new().connect_in_process();
+        client
+            .initialize(ClientCapabilities {
+                elicitation: Some(json!({})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let call = client.call_tool("get_weather", json!({}));
+        let answer = async {
+            let request = client.next_server_message().await.unwrap();
+            assert_eq!(request.method, "elicitation/create");
+            client
+                .respond(request.id.unwrap(), Ok(json!({ "action": "accept", "content": { "city": "杭州" } })))
+                .unwrap();
+        };
+        let (result, ()) = tokio::join!(call, answer);
+        assert!(text_of(&result.unwrap()).contains("杭州"));
+    }
+
+    // Shutdown Tests
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        // Test that shutdown notifies the session, refuses new requests and
+        // lets an in-flight call finish before the session ends
+        use mcp_server_rust::shutdown::SHUTDOWN_NOTIFICATION;
+
+        let server = mcp_server_rust::McpServer::new();
+        let shutdown = server.shutdown_handle();
+        let (client, serving) = server.connect_in_process();
+        client
+            .initialize(ClientCapabilities {
+                elicitation: Some(json!({})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // The call stays in flight until the client answers the elicitation
+        let call = client.call_tool("get_weather", json!({}));
+        let drive = async {
+            let elicitation = client.next_server_message().await.unwrap();
+            assert_eq!(elicitation.method, "elicitation/create");
+
+            shutdown.shutdown();
+            let notice = client.next_server_message().await.unwrap();
+            assert_eq!(notice.method, SHUTDOWN_NOTIFICATION);
+            assert!(notice.id.is_none());
+
+            let refused = client.list_tools().await.unwrap_err();
+            assert!(matches!(refused, mcp_server_rust::McpError::Client(e) if e.code == mcp_server_rust::error::SHUTTING_DOWN));
+
+            client
+                .respond(elicitation.id.unwrap(), Ok(json!({ "action": "accept", "content": { "city": "南京" } })))
+                .unwrap();
+        };
+        let (result, ()) = tokio::join!(call, drive);
+        assert!(text_of(&result.unwrap()).contains("南京"));
+
+        // With nothing left in flight the session ends on its own
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_after_deadline() {
+        // Test that requests still running at the deadline are cancelled with an error
+        let config = mcp_server_rust::config::ServerConfig::parse("[server]\nshutdown_timeout_secs = 0").unwrap();
+        let server = mcp_server_rust::McpServer::with_config(config);
+        let shutdown = server.shutdown_handle();
+        let (client, serving) = server.connect_in_process();
+        client
+            .initialize(ClientCapabilities {
+                elicitation: Some(json!({})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let call = client.call_tool("get_weather", json!({}));
+        let drive = async {
+            client.next_server_message().await.unwrap();
+            shutdown.shutdown();
+        };
+        let (result, ()) = tokio::join!(call, drive);
+        match result.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => assert_eq!(e.code, mcp_server_rust::error::SHUTTING_DOWN),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_accepting_connections() {
+        // Test that the accept loop ends and the port is released on shutdown
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = mcp_server_rust::McpServer::new();
+        let shutdown = server.shutdown_handle();
+        let serving = tokio::spawn(async move { server.serve_tcp(listener).await });
+
+        // An idle connection does not hold up shutdown
+        let idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        shutdown.shutdown();
+        assert!(shutdown.is_shutdown());
+        tokio::time::timeout(std::time::Duration::from_secs(5), serving)
+            .await
+            .expect("server should stop")
+            .unwrap()
+            .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+        drop(idle);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_abandons_websocket_upgrades() {
+        // Test that a client stalled in the WebSocket upgrade does not hold up shutdown
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = mcp_server_rust::McpServer::new();
+        let shutdown = server.shutdown_handle();
+        let serving = tokio::spawn(async move { server.serve_websocket(listener).await });
+
+        let mut stalled = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stalled.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        shutdown.shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(5), serving)
+            .await
+            .expect("server should stop")
+            .unwrap()
+            .unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(stalled.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+
+    // Limits Tests
+    async fn limited_tcp_server(limits: &str) -> std::net::SocketAddr {
+        let config = mcp_server_rust::config::ServerConfig::parse(&format!("[limits]\n{}", limits)).unwrap();
+        let server = mcp_server_rust::McpServer::with_config(config);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve_tcp(listener).await });
+        addr
+    }
+
+    async fn read_line_or_eof(stream: &mut tokio::net::TcpStream) -> Option<serde_json::Value> {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let mut line = String::new();
+        let read = tokio::time::timeout(std::time::Duration::from_secs(5), BufReader::new(stream).read_line(&mut line))
+            .await
+            .expect("connection should answer or close")
+            .unwrap_or(0);
+        (read > 0).then(|| serde_json::from_str(&line).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_limits_reject_oversized_message() {
+        // Test that a line over the size limit is answered with an error and closes the connection
+        use tokio::io::AsyncWriteExt;
+
+        let addr = limited_tcp_server("max_message_size = 64").await;
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":1}\n")
+            .await
+            .unwrap();
+        assert_eq!(read_line_or_eof(&mut stream).await.unwrap()["id"], 1);
+
+        let oversized = format!("{{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"params\":\"{}\",\"id\":2}}\n", "x".repeat(100));
+        stream.write_all(oversized.as_bytes()).await.unwrap();
+        let response = read_line_or_eof(&mut stream).await.unwrap();
+        assert_eq!(response["error"]["code"], mcp_server_rust::error::INVALID_REQUEST);
+        assert_eq!(response["error"]["data"]["limit"], 64);
+        assert!(response["id"].is_null());
+        assert!(read_line_or_eof(&mut stream).await.is_none(), "connection should be closed");
+
+        // Content-Length framing refuses the body before reading it
+        use mcp_server_rust::transport::{ContentLengthTransport, MessageReader, MessageTooLarge, Transport};
+        let (client, server) = tokio::io::duplex(1024);
+        let (mut reader, _writer) = ContentLengthTransport::new(server).with_max_message_size(16).split();
+        let (_, mut client) = tokio::io::split(client);
+        client.write_all(b"Content-Length: 1000000\r\n\r\n").await.unwrap();
+        let error = reader.recv().await.unwrap_err();
+        assert_eq!(MessageTooLarge::from_io_error(&error).unwrap().limit, 16);
+    }
+
+    #[tokio::test]
+    async fn test_limits_connection_caps() {
+        // Test that connections beyond the global or per-IP cap are closed, and
+        // that a slot is released when its connection ends
+        use tokio::io::AsyncWriteExt;
+
+        for limits in ["max_connections = 1", "max_connections_per_ip = 1"] {
+            let addr = limited_tcp_server(limits).await;
+            let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+            first
+                .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":1}\n")
+                .await
+                .unwrap();
+            assert_eq!(read_line_or_eof(&mut first).await.unwrap()["id"], 1);
+
+            let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+            assert!(read_line_or_eof(&mut second).await.is_none(), "{}: second connection should be closed", limits);
+
+            drop(first);
+            let mut third = loop {
+                let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                let _ = stream
+                    .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":3}\n")
+                    .await;
+                if let Some(response) = read_line_or_eof(&mut stream).await {
+                    assert_eq!(response["id"], 3);
+                    break stream;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            };
+            third.shutdown().await.unwrap();
+        }
+
+        let limiter = mcp_server_rust::limits::ConnectionLimiter::new(0, 1);
+        let local: std::net::IpAddr = "127.0.0.1".parse().unwrap();
+        let permit = limiter.try_acquire(Some(local)).unwrap();
+        assert!(limiter.try_acquire(Some(local)).is_err());
+        assert!(limiter.try_acquire(Some("10.0.0.1".parse().unwrap())).is_ok());
+        assert!(limiter.try_acquire(None).is_ok(), "local connections only count towards the global cap");
+        drop(permit);
+        assert_eq!(limiter.open_connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_limits_idle_timeout_closes_session() {
+        // Test that a session without traffic is closed once the idle timeout passes
+        let config = mcp_server_rust::config::ServerConfig::parse("[limits]\nidle_timeout_secs = 1").unwrap();
+        let (client, serving) = mcp_server_rust::McpServer::with_config(config).connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), serving)
+            .await
+            .expect("idle session should be closed")
+            .unwrap()
+            .unwrap();
+        assert!(matches!(client.list_tools().await, Err(mcp_server_rust::McpError::SessionClosed)));
+    }
+
+    #[tokio::test]
+    async fn test_limits_request_timeout() {
+        // Test that a handler running past the request timeout is answered with a typed error
+        let config = mcp_server_rust::config::ServerConfig::parse("[limits]\nrequest_timeout_secs = 1").unwrap();
+        let (client, _serving) = mcp_server_rust::McpServer::with_config(config).connect_in_process();
+        client
+            .initialize(ClientCapabilities {
+                elicitation: Some(json!({})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // The elicitation is never answered, so the handler would wait for the client
+        match client.call_tool("get_weather", json!({})).await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => {
+                assert_eq!(e.code, mcp_server_rust::error::HANDLER_TIMEOUT);
+                assert_eq!(e.data.unwrap()["method"], "tools/call");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    // Tool Limits Tests
+    #[test]
+    fn test_tool_limits_defaults_and_overrides() {
+        // Test that configured limits replace the tools' built-in ones
+        use mcp_server_rust::tools::QueuePolicy;
+        use std::time::Duration;
+
+        let registry = mcp_server_rust::tools::ToolRegistry::new();
+        let search = registry.limits(registry.get("search_files").unwrap());
+        assert_eq!(search.max_concurrency, Some(2), "the expensive file walk runs at most twice at once");
+        assert_eq!(search.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(search.queue, QueuePolicy::Wait);
+        let weather = registry.limits(registry.get("get_weather").unwrap());
+        assert_eq!(weather.max_concurrency, None);
+        assert_eq!(weather.timeout, None);
+
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            r#"
+            [tools.search_files]
+            timeout_secs = 0
+            max_concurrency = 4
+            queue = "reject"
+            "#,
+        )
+        .unwrap();
+        let registry = mcp_server_rust::tools::ToolRegistry::with_config(config.tools);
+        let search = registry.limits(registry.get("search_files").unwrap());
+        assert_eq!(search.max_concurrency, Some(4));
+        assert_eq!(search.timeout, None);
+        assert_eq!(search.queue, QueuePolicy::Reject);
+
+        assert!(mcp_server_rust::config::ServerConfig::parse("[tools.get_weather]\nqueue = \"maybe\"").is_err());
+    }
+
+    async fn elicitation_client(tool_config: &str) -> mcp_server_rust::McpClient {
+        let config = mcp_server_rust::config::ServerConfig::parse(&format!("[tools.get_weather]\n{}", tool_config)).unwrap();
+        let (client, _serving) = mcp_server_rust::McpServer::with_config(config).connect_in_process();
+        client
+            .initialize(ClientCapabilities {
+                elicitation: Some(json!({})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout() {
+        // Test that a tool running past its timeout is answered with a typed error
+        let client = elicitation_client("timeout_secs = 1").await;
+
+        // The elicitation is never answered, so the tool would wait forever
+        match client.call_tool("get_weather", json!({})).await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => {
+                assert_eq!(e.code, mcp_server_rust::error::TOOL_TIMEOUT);
+                assert_eq!(e.data.unwrap(), json!({ "tool": "get_weather", "timeoutMs": 1000 }));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        // Later calls are unaffected
+        assert!(client.call_tool("get_weather", json!({ "city": "上海" })).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tool_concurrency_reject() {
+        // Test that a call over the concurrency limit is rejected while the slot is taken
+        let client = elicitation_client("max_concurrency = 1\nqueue = \"reject\"").await;
+
+        let first = client.call_tool("get_weather", json!({}));
+        let drive = async {
+            let elicitation = client.next_server_message().await.unwrap();
+            match client.call_tool("get_weather", json!({ "city": "北京" })).await.unwrap_err() {
+                mcp_server_rust::McpError::Client(e) => {
+                    assert_eq!(e.code, mcp_server_rust::error::TOOL_OVERLOADED);
+                    assert_eq!(e.data.unwrap()["maxConcurrency"], 1);
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+            client
+                .respond(elicitation.id.unwrap(), Ok(json!({ "action": "accept", "content": { "city": "天津" } })))
+                .unwrap();
+        };
+        let (result, ()) = tokio::join!(first, drive);
+        assert!(text_of(&result.unwrap()).contains("天津"));
+
+        // The slot is free again once the first call finished
+        assert!(client.call_tool("get_weather", json!({ "city": "北京" })).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tool_concurrency_wait() {
+        // Test that with the wait policy a second call runs once the first one finishes
+        let client = elicitation_client("max_concurrency = 1").await;
+
+        let first = client.call_tool("get_weather", json!({}));
+        let second = client.call_tool("get_weather", json!({ "city": "西安" }));
+        let drive = async {
+            let elicitation = client.next_server_message().await.unwrap();
+            // Give the second call time to queue up behind the first
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            client
+                .respond(elicitation.id.unwrap(), Ok(json!({ "action": "accept", "content": { "city": "武汉" } })))
+                .unwrap();
+        };
+        let (first, second, ()) = tokio::join!(first, second, drive);
+        assert!(text_of(&first.unwrap()).contains("武汉"));
+        assert!(text_of(&second.unwrap()).contains("西安"));
+    }
+
+    // Authentication Tests
+    #[test]
+    fn test_static_tokens_and_bearer_header() {
+        // Test token lookup, the env token and Authorization header parsing
+        use mcp_server_rust::auth::{Authenticator, Identity, StaticTokens, bearer_token};
+
+        let tokens = StaticTokens::new().with_token("alpha-token", "alice").with_token("beta-token", "bob");
+        assert_eq!(tokens.authenticate("beta-token"), Some(Identity::new("bob")));
+        assert_eq!(tokens.authenticate("beta-toke"), None);
+        assert_eq!(tokens.authenticate(""), None);
+
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            r#"
+            [auth]
+            token_env = "MCP_TEST_AUTH_TOKEN_043"
+            [[auth.tokens]]
+            identity = "ci"
+            token = "ci-token"
+            "#,
+        )
+        .unwrap();
+        unsafe { std::env::set_var("MCP_TEST_AUTH_TOKEN_043", "from-env") };
+        let tokens = StaticTokens::from_config(&config.auth);
+        assert_eq!(tokens.authenticate("ci-token"), Some(Identity::new("ci")));
+        assert_eq!(tokens.authenticate("from-env"), Some(Identity::new("env")));
+
+        // Tools see the identity through their request context
+        let (outbound, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let session = mcp_server_rust::Session::new(outbound).with_identity(Some(Identity::new("ci")));
+        let ctx = mcp_server_rust::RequestContext::new(std::sync::Arc::new(session));
+        assert_eq!(ctx.identity().unwrap().name, "ci");
+        assert!(mcp_server_rust::RequestContext::detached().identity().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_public_listener_requires_authentication() {
+        // Test that listening beyond loopback without authentication is refused unless allowed
+        use tokio::net::TcpListener;
+
+        let config = |extra: &str| {
+            mcp_server_rust::config::ServerConfig::parse(&format!(
+                "[auth]\ntoken_env = \"MCP_TEST_UNSET_TOKEN_043\"\n{}",
+                extra
+            ))
+            .unwrap()
+        };
+        let public = || async { TcpListener::bind("0.0.0.0:0").await.unwrap() };
+
+        let server = mcp_server_rust::McpServer::with_config(config(""));
+        let error = server.serve_tcp(public().await).await.unwrap_err();
+        assert!(error.to_string().contains("allow_anonymous"), "{error}");
+        assert!(server.serve_websocket(public().await).await.is_err());
+
+        // Loopback, tokens and an explicit opt-out are each enough to start
+        let servers = [
+            (mcp_server_rust::McpServer::with_config(config("")), TcpListener::bind("127.0.0.1:0").await.unwrap()),
+            (mcp_server_rust::McpServer::with_config(config("allow_anonymous = true")), public().await),
+            (authenticated_server(), public().await),
+        ];
+        for (server, listener) in servers {
+            let shutdown = server.shutdown_handle();
+            let serving = tokio::spawn(async move { server.serve_tcp(listener).await });
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            shutdown.shutdown();
+            serving.await.unwrap().unwrap();
+        }
+    }
+
+    fn authenticated_server() -> mcp_server_rust::McpServer {
+        use mcp_server_rust::auth::StaticTokens;
+
+        mcp_server_rust::McpServer::new().with_authenticator(StaticTokens::new().with_token("s3cret", "ci"))
+    }
+
+    #[tokio::test]
+    async fn test_tcp_first_message_authentication() {
+        // Test that a TCP client must authenticate first, and is disconnected otherwise
+        use mcp_server_rust::transport::LineTransport;
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { authenticated_server().serve_tcp(listener).await });
+
+        let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let client = mcp_server_rust::McpClient::new(LineTransport::new(socket));
+        assert_eq!(client.authenticate("s3cret").await.unwrap().name, "ci");
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+        assert_eq!(client.list_tools().await.unwrap().len(), 2);
+
+        let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let client = mcp_server_rust::McpClient::new(LineTransport::new(socket));
+        match client.authenticate("wrong").await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => assert_eq!(e.code, mcp_server_rust::error::UNAUTHENTICATED),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(matches!(client.list_tools().await, Err(mcp_server_rust::McpError::SessionClosed)));
+
+        // Skipping the handshake is refused too
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"tools/list\",\"id\":1}\n")
+            .await
+            .unwrap();
+        let response = read_line_or_eof(&mut stream).await.unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], mcp_server_rust::error::UNAUTHENTICATED);
+        assert!(read_line_or_eof(&mut stream).await.is_none(), "connection should be closed");
+    }
+
+    #[tokio::test]
+    async fn test_websocket_bearer_authentication() {
+        // Test that the WebSocket handshake requires a valid Authorization header
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::{Error, Message};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { authenticated_server().serve_websocket(listener).await });
+
+        for header in [None, Some("Bearer wrong"), Some("Basic s3cret")] {
+            let mut request = url.as_str().into_client_request().unwrap();
+            if let Some(header) = header {
+                request.headers_mut().insert("Authorization", header.parse().unwrap());
+            }
+            match tokio_tungstenite::connect_async(request).await {
+                Err(Error::Http(response)) => {
+                    assert_eq!(response.status(), 401);
+                    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+                }
+                other => panic!("{:?}: expected 401, got {:?}", header, other.map(|(_, r)| r)),
+            }
+        }
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert("Authorization", "Bearer s3cret".parse().unwrap());
+        let (mut ws, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        ws.send(Message::text(r#"{"jsonrpc":"2.0","method":"ping","id":1}"#))
+            .await
+            .unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(reply["result"], json!({}));
+    }
+
+    // TLS Tests
+    /// A throwaway CA with a server certificate and a client certificate
+    struct TestPki {
+        dir: tempfile::TempDir,
+        ca: rustls_pki_types::CertificateDer<'static>,
+        client_chain: Vec<rustls_pki_types::CertificateDer<'static>>,
+        client_key: rustls_pki_types::PrivateKeyDer<'static>,
+    }
+
+    impl TestPki {
+        fn generate() -> Self {
+            use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair};
+
+            let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            ca_params.distinguished_name.push(DnType::CommonName, "Test CA");
+            let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+            let server_key = KeyPair::generate().unwrap();
+            let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+                .unwrap()
+                .signed_by(&server_key, &ca)
+                .unwrap();
+
+            let client_key = KeyPair::generate().unwrap();
+            let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            client_params.distinguished_name.push(DnType::CommonName, "agent-1");
+            client_params.distinguished_name.push(DnType::OrganizationName, "Acme");
+            let client_cert = client_params.signed_by(&client_key, &ca).unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("server.pem"), server_cert.pem()).unwrap();
+            std::fs::write(dir.path().join("server.key"), server_key.serialize_pem()).unwrap();
+            std::fs::write(dir.path().join("ca.pem"), ca.pem()).unwrap();
+
+            TestPki {
+                dir,
+                ca: ca.der().clone(),
+                client_chain: vec![client_cert.der().clone()],
+                client_key: rustls_pki_types::PrivateKeyDer::Pkcs8(client_key.serialize_der().into()),
+            }
+        }
+
+        fn config(&self, mutual: bool, extra: &str) -> mcp_server_rust::config::ServerConfig {
+            let dir = self.dir.path().display();
+            let mut text = format!("[tls]\ncert_path = \"{dir}/server.pem\"\nkey_path = \"{dir}/server.key\"\n");
+            if mutual {
+                text.push_str(&format!("client_ca_path = \"{dir}/ca.pem\"\n"));
+            }
+            text.push_str(extra);
+            mcp_server_rust::config::ServerConfig::parse(&text).unwrap()
+        }
+
+        fn connector(&self, with_client_cert: bool) -> tokio_rustls::TlsConnector {
+            use tokio_rustls::rustls;
+
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(self.ca.clone()).unwrap();
+            let builder = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+            let config = if with_client_cert {
+                builder
+                    .with_client_auth_cert(self.client_chain.clone(), self.client_key.clone_key())
+                    .unwrap()
+            } else {
+                builder.with_no_client_auth()
+            };
+            tokio_rustls::TlsConnector::from(std::sync::Arc::new(config))
+        }
+    }
+
+    async fn tls_server(config: mcp_server_rust::config::ServerConfig) -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { mcp_server_rust::McpServer::with_config(config).serve_tcp(listener).await });
+        addr
+    }
+
+    async fn tls_ping(
+        connector: &tokio_rustls::TlsConnector,
+        addr: std::net::SocketAddr,
+    ) -> std::io::Result<Option<serde_json::Value>> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let socket = tokio::net::TcpStream::connect(addr).await?;
+        let name = rustls_pki_types::ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, socket).await?;
+        stream
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"tools/list\",\"id\":1}\n")
+            .await?;
+        let mut line = String::new();
+        let read = BufReader::new(stream).read_line(&mut line).await?;
+        Ok((read > 0).then(|| serde_json::from_str(&line).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_tls_listener() {
+        // Test that the TCP listener speaks TLS and refuses plaintext clients
+        use tokio::io::AsyncWriteExt;
+
+        let pki = TestPki::generate();
+        let addr = tls_server(pki.config(false, "")).await;
+
+        let response = tls_ping(&pki.connector(false), addr).await.unwrap().unwrap();
+        assert_eq!(response["result"]["tools"].as_array().unwrap().len(), 2);
+
+        let mut plain = tokio::net::TcpStream::connect(addr).await.unwrap();
+        plain
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"tools/list\",\"id\":1}\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), tokio::io::AsyncReadExt::read_to_end(&mut plain, &mut buf))
+            .await
+            .expect("plaintext connection should be closed");
+        assert!(!String::from_utf8_lossy(&buf).contains("jsonrpc"), "no JSON-RPC over plaintext");
+
+        // A missing certificate file is reported when the listener starts
+        let mut config = pki.config(false, "");
+        config.tls.as_mut().unwrap().cert_path = pki.dir.path().join("missing.pem");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        assert!(mcp_server_rust::McpServer::with_config(config).serve_tcp(listener).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_identity() {
+        // Test that mTLS requires a client certificate, whose subject identifies the
+        // client in place of a bearer token
+        let pki = TestPki::generate();
+        let tokens = "[[auth.tokens]]\nidentity = \"ci\"\ntoken = \"s3cret\"\n";
+        let addr = tls_server(pki.config(true, tokens)).await;
+
+        // Without a certificate the handshake is rejected
+        assert!(!matches!(tls_ping(&pki.connector(false), addr).await, Ok(Some(_))));
+
+        // With one, no authenticate request is needed even though tokens are configured
+        let response = tls_ping(&pki.connector(true), addr).await.unwrap().unwrap();
+        assert_eq!(response["result"]["tools"].as_array().unwrap().len(), 2);
+
+        // The identity is the certificate's common name
+        let acceptor = mcp_server_rust::tls::load_acceptor(pki.config(true, "").tls.as_ref().unwrap()).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connector = pki.connector(true);
+        let client = tokio::spawn(async move {
+            let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let name = rustls_pki_types::ServerName::try_from("localhost").unwrap();
+            connector.connect(name, socket).await.unwrap()
+        });
+        let (socket, _) = listener.accept().await.unwrap();
+        let stream = acceptor.accept(socket).await.unwrap();
+        assert_eq!(mcp_server_rust::tls::peer_identity(&stream).unwrap().name, "agent-1");
+        drop(client.await.unwrap());
+    }
+
+    // Policy Tests
+    const POLICY_CONFIG: &str = r#"
+[[auth.tokens]]
+identity = "alice"
+token = "alice-token"
+
+[[auth.tokens]]
+identity = "ops-bob"
+token = "bob-token"
+
+[policy.roles]
+operators = ["ops-*"]
+
+[[policy.rules]]
+identities = ["alice"]
+tools = ["get_weather"]
+
+[[policy.rules]]
+roles = ["operators"]
+tools = ["*"]
+resources = ["file:///etc/*"]
+"#;
+
+    /// Connects to a TCP server with the policy above as the identity owning `token`
+    async fn policy_client(addr: std::net::SocketAddr, token: &str) -> mcp_server_rust::McpClient {
+        use mcp_server_rust::transport::LineTransport;
+
+        let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let client = mcp_server_rust::McpClient::new(LineTransport::new(socket));
+        client.authenticate(token).await.unwrap();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+        client
+    }
+
+    #[test]
+    fn test_policy_rules() {
+        // Test that rules grant by identity pattern or role, and deny everything else
+        use mcp_server_rust::auth::Identity;
+        use mcp_server_rust::policy::{Action, Policy};
+
+        let config = mcp_server_rust::config::ServerConfig::parse(POLICY_CONFIG).unwrap();
+        let policy = Policy::from_config(config.policy.as_ref());
+        let alice = Identity::new("alice");
+        let bob = Identity::new("ops-bob");
+
+        assert!(policy.permits(Some(&alice), &Action::CallTool("get_weather")));
+        assert!(!policy.permits(Some(&alice), &Action::CallTool("search_files")));
+        assert!(!policy.permits(Some(&alice), &Action::ReadResource("file:///etc/hosts")));
+        assert!(policy.permits(Some(&bob), &Action::CallTool("search_files")));
+        assert!(policy.permits(Some(&bob), &Action::ReadResource("file:///etc/hosts")));
+        assert!(!policy.permits(Some(&bob), &Action::ReadResource("file:///root/secret")));
+        assert!(!policy.permits(None, &Action::CallTool("get_weather")));
+
+        // An empty section denies everything, a missing one allows everything
+        let empty = mcp_server_rust::config::ServerConfig::parse("[policy]").unwrap();
+        assert!(!Policy::from_config(empty.policy.as_ref()).permits(Some(&bob), &Action::CallTool("get_weather")));
+        assert!(Policy::allow_all().permits(None, &Action::ReadResource("file:///etc/hosts")));
+    }
+
+    #[tokio::test]
+    async fn test_policy_filters_and_denies() {
+        // Test that denied tools and resources are hidden from lists and
+        // completions, and refused on use
+        use mcp_server_rust::McpError;
+        use mcp_server_rust::error::ACCESS_DENIED;
+
+        let config = mcp_server_rust::config::ServerConfig::parse(POLICY_CONFIG).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { mcp_server_rust::McpServer::with_config(config).serve_tcp(listener).await });
+
+        let alice = policy_client(addr, "alice-token").await;
+        let tools = alice.list_tools().await.unwrap();
+        assert_eq!(tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["get_weather"]);
+        assert!(alice.list_resources().await.unwrap().is_empty());
+        match alice.call_tool("search_files", json!({ "pattern": "*" })).await.unwrap_err() {
+            McpError::Client(e) => {
+                assert_eq!(e.code, ACCESS_DENIED);
+                assert_eq!(e.data.unwrap()["target"], "search_files");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        let denied = alice.read_resource("file:///etc/hosts").await.unwrap_err();
+        assert!(matches!(denied, McpError::Client(e) if e.code == ACCESS_DENIED));
+
+        let complete = |reference: serde_json::Value, argument: &str, value: &str| {
+            json!({ "ref": reference, "argument": { "name": argument, "value": value } })
+        };
+        let tool_ref = json!({ "type": "ref/tool", "name": "search_files" });
+        let file_ref = json!({ "type": "ref/resource", "uri": "file:///{path}" });
+        let denied = alice
+            .request::<_, CompleteResult>("completion/complete", complete(tool_ref.clone(), "directory", "/"))
+            .await
+            .unwrap_err();
+        assert!(matches!(denied, McpError::Client(e) if e.code == ACCESS_DENIED));
+        let hidden: CompleteResult = alice
+            .request("completion/complete", complete(file_ref.clone(), "path", "etc/"))
+            .await
+            .unwrap();
+        assert!(hidden.completion.values.is_empty());
+
+        let bob = policy_client(addr, "bob-token").await;
+        assert_eq!(bob.list_tools().await.unwrap().len(), 2);
+        assert!(bob.list_resources().await.unwrap().iter().any(|r| r.uri == "file:///etc/hosts"));
+        assert!(bob.read_resource("file:///etc/hosts").await.is_ok());
+        let visible: CompleteResult = bob
+            .request("completion/complete", complete(file_ref, "path", "etc/"))
+            .await
+            .unwrap();
+        assert!(visible.completion.values.contains(&"etc/hosts".to_string()));
+        let allowed = bob
+            .request::<_, CompleteResult>("completion/complete", complete(tool_ref, "directory", "/"))
+            .await;
+        assert!(allowed.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_policy_completion_rejects_multi_variable_templates() {
+        // Test that resource completions are refused when the candidates cannot
+        // be checked against the policy as complete URIs
+        let config = mcp_server_rust::config::ServerConfig::parse(POLICY_CONFIG).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { mcp_server_rust::McpServer::with_config(config).serve_tcp(listener).await });
+
+        let bob = policy_client(addr, "bob-token").await;
+        let error = bob
+            .request::<_, CompleteResult>(
+                "completion/complete",
+                json!({
+                    "ref": { "type": "ref/resource", "uri": "file:///{dir}/{path}" },
+                    "argument": { "name": "path", "value": "hosts" }
+                }),
+            )
+            .await
+            .unwrap_err();
+        match error {
+            mcp_server_rust::McpError::Client(e) => {
+                assert_eq!(e.code, mcp_server_rust::error::INVALID_PARAMS);
+                assert_eq!(e.data.unwrap()["details"]["uri"], "file:///{dir}/{path}");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_anonymous_clients() {
+        // Test that local clients are checked as the anonymous identity
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            "[[policy.rules]]\nidentities = [\"anonymous\"]\ntools = [\"get_weather\"]\n",
+        )
+        .unwrap();
+        let (client, _server) = mcp_server_rust::McpServer::with_config(config).connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        assert_eq!(client.list_tools().await.unwrap().len(), 1);
+        assert!(client.list_resources().await.unwrap().is_empty());
+    }
+
+    // Sandbox Tests
+    /// A sandbox rooted at a fresh directory, with a file outside it
+    fn sandbox_fixture() -> (tempfile::TempDir, tempfile::TempDir, mcp_server_rust::sandbox::PathSandbox) {
+        use mcp_server_rust::config::SandboxConfig;
+
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("docs/.ssh")).unwrap();
+        std::fs::write(root.path().join("docs/notes.txt"), "notes").unwrap();
+        std::fs::write(root.path().join("docs/server.pem"), "key").unwrap();
+        std::fs::write(root.path().join("docs/.ssh/id.txt"), "key").unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.txt"), root.path().join("docs/link.txt")).unwrap();
+
+        let sandbox = mcp_server_rust::sandbox::PathSandbox::from_config(&SandboxConfig {
+            roots: vec![root.path().to_path_buf()],
+            ..Default::default()
+        });
+        (root, outside, sandbox)
+    }
+
+    #[test]
+    fn test_sandbox_check() {
+        // Test that traversal, symlinks, deny patterns and special files are refused
+        use mcp_server_rust::sandbox::{PathKind, SandboxError};
+
+        let (root, _outside, sandbox) = sandbox_fixture();
+        let docs = root.path().join("docs");
+
+        let canonical = sandbox.check(&docs.join("./notes.txt"), PathKind::File).unwrap();
+        assert_eq!(canonical, std::fs::canonicalize(docs.join("notes.txt")).unwrap());
+        assert!(sandbox.check(&docs, PathKind::Directory).is_ok());
+
+        let traversal = sandbox.check(&docs.join("../.."), PathKind::Directory).unwrap_err();
+        assert!(matches!(traversal, SandboxError::OutsideRoots(_)));
+        let symlink = sandbox.check(&docs.join("link.txt"), PathKind::File).unwrap_err();
+        assert!(matches!(symlink, SandboxError::OutsideRoots(_)));
+        let pem = sandbox.check(&docs.join("server.pem"), PathKind::File).unwrap_err();
+        assert!(matches!(pem, SandboxError::Denied { pattern, .. } if pattern == "**/*.pem"));
+        let ssh = sandbox.check(&docs.join(".ssh"), PathKind::Directory).unwrap_err();
+        assert!(matches!(ssh, SandboxError::Denied { .. }));
+
+        let _socket = std::os::unix::net::UnixListener::bind(docs.join("agent.sock")).unwrap();
+        let special = sandbox.check(&docs.join("agent.sock"), PathKind::File).unwrap_err();
+        assert!(matches!(special, SandboxError::SpecialFile(_)));
+        let wrong = sandbox.check(&docs, PathKind::File).unwrap_err();
+        assert!(matches!(wrong, SandboxError::WrongKind { expected: PathKind::File, .. }));
+
+        let missing = sandbox.check(&docs.join("missing.txt"), PathKind::File).unwrap_err();
+        assert!(!missing.is_violation());
+    }
+
+    #[tokio::test]
+    async fn test_search_files_sandbox() {
+        // Test that search_files is confined to the sandbox roots and skips denied files
+        use mcp_server_rust::tools::ToolError;
+
+        let (root, outside, sandbox) = sandbox_fixture();
+        let registry = mcp_server_rust::tools::ToolRegistry::new().with_sandbox(std::sync::Arc::new(sandbox));
+        let tool = registry.get("search_files").unwrap();
+        let ctx = mcp_server_rust::RequestContext::detached();
+
+        let result = tool
+            .execute(json!({ "pattern": "*", "directory": root.path() }), &ctx)
+            .await
+            .unwrap();
+        let Content::Text { text } = &result.content[0] else {
+            panic!("expected text content");
+        };
+        assert!(text.contains("notes.txt"));
+        assert!(!text.contains("server.pem"));
+        assert!(!text.contains("id.txt"));
+        assert!(!text.contains("link.txt"));
+
+        for directory in [outside.path().to_path_buf(), root.path().join("docs/../..")] {
+            let refused = tool
+                .execute(json!({ "pattern": "*", "directory": directory }), &ctx)
+                .await
+                .unwrap_err();
+            assert!(matches!(refused, ToolError::InvalidArguments(message) if message.contains("根目录")));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource_sandbox() {
+        // Test that file resources the sandbox refuses are hidden and cannot be read
+        use mcp_server_rust::McpError;
+
+        let (root, outside, sandbox) = sandbox_fixture();
+        let mut registry = mcp_server_rust::ResourceRegistry::new().with_sandbox(std::sync::Arc::new(sandbox));
+        let notes = registry.register_file(root.path().join("docs/notes.txt")).unwrap();
+        let pem = registry.register_file(root.path().join("docs/server.pem")).unwrap();
+        let secret = registry.register_file(outside.path().join("secret.txt")).unwrap();
+
+        let listed: Vec<String> = registry.list_resources().into_iter().map(|r| r.uri).collect();
+        assert!(listed.contains(&notes.uri));
+        assert!(!listed.contains(&pem.uri));
+        assert!(!listed.contains(&secret.uri));
+
+        assert!(registry.read_resource(&notes.uri).await.is_ok());
+        for uri in [&pem.uri, &secret.uri] {
+            let error = registry.read_resource(uri).await.unwrap_err();
+            assert!(matches!(error, McpError::AccessDenied { target, .. } if &target == uri));
+        }
+    }
+
+    // Rate Limit Tests
+    #[tokio::test]
+    async fn test_rate_limiter_buckets() {
+        // Test that each caller gets its own bucket, which refills over time
+        use mcp_server_rust::config::RateLimitConfig;
+        use mcp_server_rust::rate_limit::RateLimiter;
+
+        let limiter = RateLimiter::new(&RateLimitConfig { per_minute: 600, burst: Some(2) }).unwrap();
+        assert!(limiter.try_acquire("a").is_ok());
+        assert!(limiter.try_acquire("a").is_ok());
+        let retry_after = limiter.try_acquire("a").unwrap_err();
+        assert!(retry_after > std::time::Duration::ZERO && retry_after <= std::time::Duration::from_millis(100));
+        assert!(limiter.try_acquire("b").is_ok());
+
+        tokio::time::sleep(retry_after).await;
+        assert!(limiter.try_acquire("a").is_ok());
+
+        assert!(RateLimiter::new(&RateLimitConfig { per_minute: 0, burst: None }).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tool_rate_limit() {
+        // Test that calls over a tool's rate limit get a distinct error with retry-after data
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            "[tools.get_weather.rate_limit]\nper_minute = 60\nburst = 2\n",
+        )
+        .unwrap();
+        let (client, _server) = mcp_server_rust::McpServer::with_config(config).connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        for _ in 0..2 {
+            client.call_tool("get_weather", json!({ "city": "Beijing" })).await.unwrap();
+        }
+        match client.call_tool("get_weather", json!({ "city": "Beijing" })).await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => {
+                assert_eq!(e.code, mcp_server_rust::error::RATE_LIMITED);
+                let data = e.data.unwrap();
+                assert_eq!(data["tool"], "get_weather");
+                let retry_after = data["retryAfterMs"].as_u64().unwrap();
+                assert!(retry_after > 0 && retry_after <= 1000);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Other tools are not affected
+        client.call_tool("search_files", json!({ "pattern": "*.none", "directory": "src" })).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_rate_limit_per_identity() {
+        // Test that the server-wide limit is counted per identity across connections
+        let config = mcp_server_rust::config::ServerConfig::parse(
+            "[limits.rate_limit]\nper_minute = 60\nburst = 3\n\n\
+             [[auth.tokens]]\nidentity = \"alice\"\ntoken = \"alice-token\"\n\n\
+             [[auth.tokens]]\nidentity = \"ops-bob\"\ntoken = \"bob-token\"\n",
+        )
+        .unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { mcp_server_rust::McpServer::with_config(config).serve_tcp(listener).await });
+
+        // initialize and one list each use up alice's burst, even on separate connections
+        let first = policy_client(addr, "alice-token").await;
+        first.list_tools().await.unwrap();
+        let second = policy_client(addr, "alice-token").await;
+        match second.list_tools().await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => {
+                assert_eq!(e.code, mcp_server_rust::error::RATE_LIMITED);
+                assert!(e.data.unwrap().get("tool").is_none());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let bob = policy_client(addr, "bob-token").await;
+        bob.list_tools().await.unwrap();
+    }
+
+    // Audit Tests
+    /// Reads every record of a JSON Lines audit file
+    fn read_audit(path: &std::path::Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records() {
+        // Test that tool calls and resource reads are recorded with redacted arguments and outcomes
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let config = mcp_server_rust::config::ServerConfig::parse(&format!(
+            "[audit]\npath = {:?}\nredact = [\"*key*\", \"password\"]\n",
+            path
+        ))
+        .unwrap();
+        let server = mcp_server_rust::McpServer::with_config(config);
+        let (client, _serving) = server.connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+
+        let arguments = json!({ "city": "Beijing", "api_key": "k", "nested": [{ "Password": "p" }] });
+        client.call_tool("get_weather", arguments).await.unwrap();
+        client
+            .call_tool("search_files", json!({ "pattern": "*", "directory": "/nonexistent" }))
+            .await
+            .unwrap();
+        client.call_tool("missing_tool", json!({})).await.unwrap_err();
+        client.read_resource("file:///etc/hosts").await.unwrap();
+        client.list_tools().await.unwrap();
+        server.flush_audit().await;
+
+        let records = read_audit(&path);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["method"], "tools/call");
+        assert_eq!(records[0]["target"], "get_weather");
+        assert_eq!(records[0]["outcome"], "success");
+        assert_eq!(records[0]["arguments"], json!({ "city": "Beijing", "api_key": "[REDACTED]", "nested": [{ "Password": "[REDACTED]" }] }));
+        assert!(records[0]["sessionId"].as_u64().is_some());
+        assert!(records[0]["durationMs"].as_u64().is_some());
+        assert!(records[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(records[1]["outcome"], "isError");
+        assert_eq!(records[2]["outcome"], "error");
+        assert_eq!(records[2]["errorCode"], mcp_server_rust::error::METHOD_NOT_FOUND);
+        assert_eq!(records[3]["method"], "resources/read");
+        assert_eq!(records[3]["target"], "file:///etc/hosts");
+        assert!(records[3].get("arguments").is_none());
+    }
+
+    #[test]
+    fn test_audit_log_rotation() {
+        // Test that the log is rotated by size and only the configured number of files is kept
+        use mcp_server_rust::audit::{AuditLog, rotated_path};
+        use mcp_server_rust::config::AuditConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let audit = AuditLog::new(&AuditConfig {
+            path: path.clone(),
+            max_bytes: 400,
+            max_files: 2,
+            redact: Vec::new(),
+        });
+        let session = mcp_server_rust::Session::detached();
+        let params = json!({ "name": "get_weather", "arguments": { "city": "Beijing" } });
+        for _ in 0..12 {
+            audit.record(&session, "tools/call", &params, &Ok(json!({})), std::time::Duration::ZERO);
+        }
+        audit.flush();
+
+        for file in [path.clone(), rotated_path(&path, 1), rotated_path(&path, 2)] {
+            let size = std::fs::metadata(&file).unwrap().len();
+            assert!(size > 0 && size <= 400, "{} has {} bytes", file.display(), size);
+            assert!(read_audit(&file).iter().all(|record| record["target"] == "get_weather"));
+        }
+        assert!(!rotated_path(&path, 3).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_audit_log_records_dropped_count() {
+        // Test that records dropped while the queue is full leave a count in the file
+        use mcp_server_rust::audit::AuditLog;
+        use mcp_server_rust::config::AuditConfig;
+        use std::io::BufRead;
+
+        // Opening a FIFO blocks the writer thread until it is read, so the queue fills up
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        assert!(std::process::Command::new("mkfifo").arg(&path).status().unwrap().success());
+        let audit = AuditLog::new(&AuditConfig {
+            path: path.clone(),
+            max_bytes: 0,
+            max_files: 0,
+            redact: Vec::new(),
+        });
+        let session = mcp_server_rust::Session::detached();
+        let params = json!({ "name": "get_weather", "arguments": { "city": "Beijing" } });
+        let total = 5000;
+        for _ in 0..total {
+            audit.record(&session, "tools/call", &params, &Ok(json!({})), std::time::Duration::ZERO);
+        }
+
+        let reader = std::thread::spawn(move || {
+            let fifo = std::fs::File::open(&path).unwrap();
+            std::io::BufReader::new(fifo)
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(&line.unwrap()).unwrap())
+                .collect::<Vec<_>>()
+        });
+        audit.flush();
+        drop(audit);
+        let records = reader.join().unwrap();
+
+        let (gaps, written): (Vec<_>, Vec<_>) = records.iter().partition(|record| record.get("dropped").is_some());
+        assert_eq!(gaps.len(), 1);
+        let dropped = gaps[0]["dropped"].as_u64().unwrap();
+        assert!(dropped > 0);
+        assert_eq!(written.len() as u64 + dropped, total);
+        assert!(records.last().unwrap().get("dropped").is_some());
+    }
+
+    // Metrics Tests
+    /// Gets the value of a sample line such as `name{labels} value` from rendered metrics
+    fn metric_value(rendered: &str, sample: &str) -> Option<f64> {
+        rendered
+            .lines()
+            .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' ')?.parse().ok())
+    }
+
+    #[tokio::test]
+    async fn test_metrics_counts() {
+        // Test that requests, errors, sessions, traffic and resource reads are counted
+        let server = mcp_server_rust::McpServer::new();
+        let (client, _serving) = server.connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+        client.call_tool("get_weather", json!({ "city": "Beijing" })).await.unwrap();
+        client.call_tool("made_up_tool", json!({})).await.unwrap_err();
+        client.request::<_, serde_json::Value>("made/up", json!({})).await.unwrap_err();
+        client.read_resource("file:///etc/hosts").await.unwrap();
+
+        let rendered = server.metrics().render();
+        let count = |sample: &str| metric_value(&rendered, sample).unwrap_or_default();
+        assert_eq!(count(r#"mcp_requests_total{method="initialize",tool=""}"#), 1.0);
+        assert_eq!(count(r#"mcp_requests_total{method="tools/call",tool="get_weather"}"#), 1.0);
+        assert_eq!(count(r#"mcp_requests_total{method="tools/call",tool="unknown"}"#), 1.0);
+        assert_eq!(count(r#"mcp_requests_total{method="unknown",tool=""}"#), 1.0);
+        assert_eq!(count(r#"mcp_request_duration_seconds_count{method="tools/call",tool="get_weather"}"#), 1.0);
+        assert_eq!(count(r#"mcp_errors_total{code="-32601"}"#), 2.0);
+        assert_eq!(count("mcp_active_sessions"), 1.0);
+        assert_eq!(count(r#"mcp_in_flight_tool_calls{tool="get_weather"}"#), 0.0);
+        assert!(count("mcp_received_bytes_total") > 0.0);
+        assert!(count("mcp_sent_bytes_total") > 0.0);
+        assert_eq!(count("mcp_resource_read_bytes_count"), 1.0);
+
+        drop(client);
+        _serving.await.unwrap().unwrap();
+        assert_eq!(metric_value(&server.metrics().render(), "mcp_active_sessions"), Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_method_labels_are_bounded() {
+        // Test that made-up methods never become labels, even when rejected before dispatch
+        use mcp_server_rust::server::METHODS;
+
+        let config = mcp_server_rust::config::ServerConfig::parse("[limits.rate_limit]\nper_minute = 1\nburst = 1\n").unwrap();
+        let server = mcp_server_rust::McpServer::with_config(config);
+        let (client, _serving) = server.connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+        match client.request::<_, serde_json::Value>("made/up/1", json!({})).await.unwrap_err() {
+            mcp_server_rust::McpError::Client(e) => assert_eq!(e.code, mcp_server_rust::error::RATE_LIMITED),
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let rendered = server.metrics().render();
+        assert!(!rendered.contains("made/up"), "{rendered}");
+        assert_eq!(metric_value(&rendered, r#"mcp_requests_total{method="unknown",tool=""}"#), Some(1.0));
+
+        // Every listed method is dispatched
+        let (client, _serving) = mcp_server_rust::McpServer::new().connect_in_process();
+        for method in METHODS {
+            if let Err(mcp_server_rust::McpError::Client(e)) = client.request::<_, serde_json::Value>(method, json!({})).await {
+                assert_ne!(e.code, mcp_server_rust::error::METHOD_NOT_FOUND, "{method}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        // Test that the admin listener serves the metrics over HTTP
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn get(addr: std::net::SocketAddr, request: &str) -> String {
+            let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+            socket.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            response
+        }
+
+        let server = mcp_server_rust::McpServer::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let admin = server.clone();
+        tokio::spawn(async move { admin.serve_metrics(listener).await });
+
+        let response = get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(response.contains("# TYPE mcp_active_sessions gauge"));
+
+        assert!(get(addr, "GET /other HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 404"));
+        assert!(get(addr, "POST /metrics HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 405"));
+    }
+
+    // Telemetry Tests
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_parse() {
+        // Test parsing and formatting of W3C trace context
+        use mcp_server_rust::telemetry::TraceContext;
+
+        let context = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!(context.trace_id[..4], [0x4b, 0xf9, 0x2f, 0x35]);
+        assert_eq!(context.span_id[7], 0xb7);
+        assert!(context.sampled);
+        assert_eq!(context.to_string(), TRACEPARENT);
+        assert!(!TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap().sampled);
+        // Later versions may carry more fields
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902bz-01",
+        ] {
+            assert!(TraceContext::parse(invalid).is_none(), "{invalid:?} should be rejected");
+        }
+    }
+
+    /// Starts a stand-in OTLP/HTTP collector that answers every export with 200
+    ///
+    /// Returns its endpoint and the request bodies it receives.
+    async fn otlp_collector() -> (String, tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>) {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (exports, received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let exports = exports.clone();
+                // Serves requests until the exporter closes the kept-alive connection
+                tokio::spawn(async move {
+                    let mut socket = BufReader::new(socket);
+                    let mut line = String::new();
+                    while socket.read_line(&mut line).await.unwrap() > 0 {
+                        assert_eq!(line, "POST /v1/traces HTTP/1.1\r\n");
+                        let mut length = 0;
+                        loop {
+                            line.clear();
+                            socket.read_line(&mut line).await.unwrap();
+                            if line == "\r\n" {
+                                break;
+                            }
+                            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                                length = value.trim().parse().unwrap();
+                            }
+                        }
+                        let mut body = vec![0; length];
+                        socket.read_exact(&mut body).await.unwrap();
+                        exports.send(serde_json::from_slice(&body).unwrap()).unwrap();
+                        socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+        (endpoint, received)
+    }
+
+    /// Gets the value of a span attribute, as an OTLP `AnyValue`
+    fn span_attribute<'a>(span: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+        span["attributes"]
+            .as_array()?
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .map(|attribute| &attribute["value"])
+    }
+
+    #[tokio::test]
+    async fn test_otlp_export() {
+        // Test that request spans reach the collector and join the caller's trace
+        use tracing_subscriber::prelude::*;
+
+        let (endpoint, mut exports) = otlp_collector().await;
+        let config = mcp_server_rust::config::TelemetryConfig {
+            otlp_endpoint: endpoint,
+            service_name: "mcp-test".to_string(),
+        };
+        let (layer, otlp) = mcp_server_rust::telemetry::otlp(&config).unwrap();
+        // The test runtime is single-threaded, so the server runs under this subscriber
+        let _subscriber = tracing_subscriber::registry().with(layer).set_default();
+
+        let server = mcp_server_rust::McpServer::new();
+        let (client, _serving) = server.connect_in_process();
+        client.initialize(ClientCapabilities::default()).await.unwrap();
+        let call = |traceparent: &str| {
+            json!({
+                "name": "get_weather",
+                "arguments": { "city": "Beijing" },
+                "_meta": { "traceparent": traceparent },
+            })
+        };
+        client.request::<_, serde_json::Value>("tools/call", call(TRACEPARENT)).await.unwrap();
+        client.call_tool("made_up_tool", json!({})).await.unwrap_err();
+        // The caller does not record this trace, so neither does the server
+        let unsampled = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+        client.request::<_, serde_json::Value>("tools/call", call(unsampled)).await.unwrap();
+        otlp.flush().await;
+
+        let mut spans = Vec::new();
+        while let Ok(export) = exports.try_recv() {
+            let resource = &export["resourceSpans"][0];
+            assert_eq!(span_attribute(&resource["resource"], "service.name").unwrap()["stringValue"], "mcp-test");
+            spans.extend(resource["scopeSpans"][0]["spans"].as_array().unwrap().iter().cloned());
+        }
+        assert_eq!(spans.len(), 3, "{spans:#?}");
+        let find = |method: &str, tool: Option<&str>| {
+            spans
+                .iter()
+                .find(|span| {
+                    span_attribute(span, "method").unwrap()["stringValue"] == method
+                        && tool.is_none_or(|tool| span_attribute(span, "tool").unwrap()["stringValue"] == tool)
+                })
+                .unwrap()
+        };
+
+        let initialize = find("initialize", None);
+        assert_eq!(initialize["name"], "request");
+        assert_eq!(initialize["kind"], 2);
+        assert_eq!(initialize["traceId"].as_str().unwrap().len(), 32);
+        assert!(initialize.get("parentSpanId").is_none());
+        assert!(span_attribute(initialize, "tool").is_none());
+
+        let weather = find("tools/call", Some("get_weather"));
+        assert_eq!(weather["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(weather["parentSpanId"], "00f067aa0ba902b7");
+        assert_ne!(weather["spanId"], initialize["spanId"]);
+        assert_eq!(span_attribute(weather, "id").unwrap()["intValue"], "2");
+        assert!(span_attribute(weather, "session_id").is_some());
+        assert!(span_attribute(weather, "traceparent").is_none());
+        assert_eq!(weather["status"]["code"], 0);
+        let start: u128 = weather["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let end: u128 = weather["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert!(start > 0 && end >= start);
+
+        let missing = find("tools/call", Some("unknown"));
+        assert_ne!(missing["traceId"], initialize["traceId"]);
+        assert_eq!(missing["status"]["code"], 2);
+        assert!(missing["status"]["message"].as_str().unwrap().contains("made_up_tool"));
+    }
+
+    #[tokio::test]
+    async fn test_otlp_queue_is_bounded() {
+        // Test that spans closed faster than they are exported are dropped, not queued
+        use tracing_subscriber::prelude::*;
+
+        let (endpoint, mut exports) = otlp_collector().await;
+        let config = mcp_server_rust::config::TelemetryConfig {
+            otlp_endpoint: endpoint,
+            service_name: "mcp-test".to_string(),
+        };
+        let (layer, otlp) = mcp_server_rust::telemetry::otlp(&config).unwrap();
+        let _subscriber = tracing_subscriber::registry().with(layer).set_default();
+
+        // The exporter cannot run until this loop yields, as the runtime is single-threaded
+        for _ in 0..10_000 {
+            tracing::info_span!("request", traceparent = tracing::field::Empty).in_scope(|| {});
+        }
+        otlp.flush().await;
+
+        let mut exported = 0;
+        while let Ok(export) = exports.try_recv() {
+            let spans = export["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap().len();
+            assert!(spans <= 512, "batch of {spans} spans");
+            exported += spans;
+        }
+        assert!(exported > 0 && exported <= 2048, "{exported} spans exported");
+
+        // Spans are exported again once the queue has drained
+        tracing::info_span!("request", traceparent = tracing::field::Empty).in_scope(|| {});
+        otlp.flush().await;
+        let export = exports.try_recv().unwrap();
+        assert_eq!(export["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_otlp_endpoint_validation() {
+        // Test that only http:// collector endpoints are accepted
+        let config = |endpoint: &str| mcp_server_rust::config::TelemetryConfig {
+            otlp_endpoint: endpoint.to_string(),
+            service_name: "mcp-test".to_string(),
+        };
+        assert!(mcp_server_rust::telemetry::otlp(&config("http://127.0.0.1:4318")).is_ok());
+        assert!(mcp_server_rust::telemetry::otlp(&config("http://collector/v1/traces")).is_ok());
+        assert!(mcp_server_rust::telemetry::otlp(&config("https://collector:4318")).is_err());
+        assert!(mcp_server_rust::telemetry::otlp(&config("http://")).is_err());
+    }
 }